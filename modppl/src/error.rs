@@ -0,0 +1,55 @@
+use std::fmt;
+use crate::{AddrMap, ArgDiff};
+
+
+/// Errors surfaced by the fallible (`try_*`) variants of the GFI and `Trie` operations.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Error {
+    /// No value was found at the address.
+    MissingAddress(String),
+
+    /// The value at the address could not be downcast to the `expected` type.
+    TypeMismatch {
+        /// Address of the value.
+        addr: String,
+        /// Name of the requested type.
+        expected: &'static str
+    },
+
+    /// Some constraints were not visited during execution. Holds the schema of the residual constraints.
+    UnconsumedConstraints(AddrMap),
+
+    /// Attempted to put a value into an occupied address.
+    AddressCollision(String),
+
+    /// The generative function can't handle this type of change to its arguments.
+    UnsupportedDiff(ArgDiff),
+
+    /// A distribution was called with parameters outside of its domain.
+    InvalidParameters(String),
+
+    /// A generative function was called with arguments or constraints it can't handle (eg. a number of steps that
+    /// doesn't match the number of constraints).
+    InvalidArguments(String),
+
+    /// The weights of an inference algorithm collapsed onto too few particles for it to proceed.
+    DegenerateWeights(String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingAddress(addr) => write!(f, "no value found at address \"{addr}\""),
+            Error::TypeMismatch { addr, expected } => write!(f, "failed to downcast value at address \"{addr}\" into {expected}"),
+            Error::UnconsumedConstraints(residual) => write!(f, "not all constraints were consumed, residual found:\n{residual:#?}"),
+            Error::AddressCollision(addr) => write!(f, "attempted to put into occupied address \"{addr}\""),
+            Error::UnsupportedDiff(diff) => write!(f, "ArgDiff::{diff:?} not supported"),
+            Error::InvalidParameters(msg) => write!(f, "invalid distribution parameters: {msg}"),
+            Error::InvalidArguments(msg) => write!(f, "invalid arguments: {msg}"),
+            Error::DegenerateWeights(msg) => write!(f, "degenerate weights: {msg}")
+        }
+    }
+}
+
+impl std::error::Error for Error { }
+
@@ -1,4 +1,6 @@
-use crate::AddrMap;
+use rand::Rng;
+use nalgebra::{DVector,DMatrix};
+use crate::{AddrMap,Error,Trie};
 
 /// Representation of the probabilistic execution of a `GenFn`.
 #[derive(Clone)]
@@ -46,16 +48,20 @@ impl<Args,Data,Ret> Trace<Args,Data,Ret> {
 /// `trace.data` refers to all random variables, while `constraints` more precisely
 /// refers to an observed subset of the `trace.data`. `args` and `impl Data`
 /// are respectively the parameterizing arguments and implementing `Data` type.
+/// 
+/// Every method draws its randomness from the supplied `rng`, so passing
+/// a seeded `Rng` (eg. `StdRng::seed_from_u64`) makes execution reproducible.
 pub trait GenFn<Args,Data,Ret> {
 
     /// Execute the generative function and return a sampled trace.
-    fn simulate(&self, args: Args) -> Trace<Args,Data,Ret>;
+    fn simulate(&self, rng: &mut impl Rng, args: Args) -> Trace<Args,Data,Ret>;
 
     /// Execute the generative function consistent with `constraints`.
-    fn generate(&self, args: Args, constraints: Data) -> (Trace<Args,Data,Ret>, f64);
+    fn generate(&self, rng: &mut impl Rng, args: Args, constraints: Data) -> (Trace<Args,Data,Ret>, f64);
 
     /// Update a trace.
     fn update(&self,
+        rng: &mut impl Rng,
         trace: Trace<Args,Data,Ret>,
         args: Args,
         diff: ArgDiff,
@@ -64,31 +70,223 @@ pub trait GenFn<Args,Data,Ret> {
 
     /// Regenerate a masked subset of a trace.
     fn regenerate(&self,
-        trace: Trace<Args,Data,Ret>,
-        args: Args,
-        diff: ArgDiff,
-        mask: &AddrMap
+        _rng: &mut impl Rng,
+        _trace: Trace<Args,Data,Ret>,
+        _args: Args,
+        _diff: ArgDiff,
+        _mask: &AddrMap
     ) -> (Trace<Args,Data,Ret>, f64) {
         panic!("regenerate: impl not found")
     }
 
     /// Call a generative function and return the output.
-    fn call(&self, args: Args) -> Ret {
-        self.simulate(args).retv.unwrap()
+    fn call(&self, rng: &mut impl Rng, args: Args) -> Ret {
+        self.simulate(rng, args).retv.unwrap()
     }
 
     /// Use a generative function to propose some data.
-    fn propose(&self, args: Args) -> (Data, f64) {
-        let trace = self.simulate(args);
+    fn propose(&self, rng: &mut impl Rng, args: Args) -> (Data, f64) {
+        let trace = self.simulate(rng, args);
         (trace.data, trace.logjp)
     }
 
     /// Assess the conditional probability of some proposed `constraints` under a generative function.
-    fn assess(&self, args: Args, constraints: Data) -> f64 {
-        let (_, weight) = self.generate(args, constraints);
+    fn assess(&self, rng: &mut impl Rng, args: Args, constraints: Data) -> f64 {
+        let (_, weight) = self.generate(rng, args, constraints);
         weight
     }
 
+    /// Fallible variant of `simulate`.
+    /// 
+    /// Implementors that can fail on user input should override the `try_*` methods
+    /// and implement their panicking counterparts in terms of them. By default, defers to `simulate`.
+    fn try_simulate(&self, rng: &mut impl Rng, args: Args) -> Result<Trace<Args,Data,Ret>, Error> {
+        Ok(self.simulate(rng, args))
+    }
+
+    /// Fallible variant of `generate`. By default, defers to `generate`.
+    fn try_generate(&self, rng: &mut impl Rng, args: Args, constraints: Data) -> Result<(Trace<Args,Data,Ret>, f64), Error> {
+        Ok(self.generate(rng, args, constraints))
+    }
+
+    /// Fallible variant of `update`. By default, defers to `update`.
+    #[allow(clippy::type_complexity)]
+    fn try_update(&self,
+        rng: &mut impl Rng,
+        trace: Trace<Args,Data,Ret>,
+        args: Args,
+        diff: ArgDiff,
+        constraints: Data
+    ) -> Result<(Trace<Args,Data,Ret>, Data, f64), Error> {
+        Ok(self.update(rng, trace, args, diff, constraints))
+    }
+
+    /// Fallible variant of `regenerate`. By default, defers to `regenerate`.
+    fn try_regenerate(&self,
+        rng: &mut impl Rng,
+        trace: Trace<Args,Data,Ret>,
+        args: Args,
+        diff: ArgDiff,
+        mask: &AddrMap
+    ) -> Result<(Trace<Args,Data,Ret>, f64), Error> {
+        Ok(self.regenerate(rng, trace, args, diff, mask))
+    }
+
+    /// Return the gradient of `trace.logjp` with respect to the continuous choices in `trace.data` selected by `selection`.
+    ///
+    /// Implementations without automatic differentiation may re-execute the function several times per selected coordinate
+    /// (eg. `DynGenFn` replays `trace` `1 + 2d` times for `d` selected coordinates), so select only the choices you need.
+    fn choice_gradients(&self,
+        _rng: &mut impl Rng,
+        _trace: &Trace<Args,Data,Ret>,
+        _selection: &AddrMap
+    ) -> Data {
+        panic!("choice_gradients: impl not found")
+    }
+
+    /// Return the gradient of `trace.logjp` with respect to `trace.args`.
+    ///
+    /// As with `choice_gradients`, this may cost several executions per coordinate of the `args`
+    /// (eg. `1 + 2d` replays of `trace` for a `DynGenFn` with `d` coordinates).
+    fn argument_gradients(&self, _rng: &mut impl Rng, _trace: &Trace<Args,Data,Ret>) -> Args
+    where
+        Args: Differentiable + Clone,
+        Data: Clone + Default,
+        Ret: Clone
+    {
+        panic!("argument_gradients: impl not found")
+    }
+
+}
+
+
+/// Values that gradients can be taken with respect to, viewed as a flat vector of `f64` coordinates.
+pub trait Differentiable {
+    /// Flatten `self` into its coordinates.
+    fn to_coords(&self) -> Vec<f64>;
+
+    /// Rebuild a value with the same shape as `self` from the leading `coords`.
+    fn with_coords(&self, coords: &[f64]) -> Self;
+
+    /// The number of coordinates in `self`.
+    fn num_coords(&self) -> usize {
+        self.to_coords().len()
+    }
+}
+
+impl Differentiable for () {
+    fn to_coords(&self) -> Vec<f64> { vec![] }
+    fn with_coords(&self, _coords: &[f64]) -> Self { }
+}
+
+impl Differentiable for f64 {
+    fn to_coords(&self) -> Vec<f64> { vec![*self] }
+    fn with_coords(&self, coords: &[f64]) -> Self { coords[0] }
+    fn num_coords(&self) -> usize { 1 }
+}
+
+impl Differentiable for DVector<f64> {
+    fn to_coords(&self) -> Vec<f64> { self.as_slice().to_vec() }
+    fn with_coords(&self, coords: &[f64]) -> Self { DVector::from_column_slice(&coords[..self.len()]) }
+    fn num_coords(&self) -> usize { self.len() }
+}
+
+impl Differentiable for DMatrix<f64> {
+    fn to_coords(&self) -> Vec<f64> { self.as_slice().to_vec() }
+    fn with_coords(&self, coords: &[f64]) -> Self { DMatrix::from_column_slice(self.nrows(), self.ncols(), &coords[..self.len()]) }
+    fn num_coords(&self) -> usize { self.len() }
+}
+
+impl<A: Differentiable> Differentiable for Vec<A> {
+    fn to_coords(&self) -> Vec<f64> {
+        self.iter().flat_map(|a| a.to_coords()).collect()
+    }
+
+    fn with_coords(&self, coords: &[f64]) -> Self {
+        let mut offset = 0;
+        self.iter().map(|a| {
+            let a_new = a.with_coords(&coords[offset..]);
+            offset += a.num_coords();
+            a_new
+        }).collect()
+    }
+}
+
+impl<A: Differentiable, B: Differentiable> Differentiable for (A,B) {
+    fn to_coords(&self) -> Vec<f64> {
+        [self.0.to_coords(), self.1.to_coords()].concat()
+    }
+
+    fn with_coords(&self, coords: &[f64]) -> Self {
+        let n0 = self.0.num_coords();
+        (self.0.with_coords(coords), self.1.with_coords(&coords[n0..]))
+    }
+}
+
+impl<A: Differentiable, B: Differentiable, C: Differentiable> Differentiable for (A,B,C) {
+    fn to_coords(&self) -> Vec<f64> {
+        [self.0.to_coords(), self.1.to_coords(), self.2.to_coords()].concat()
+    }
+
+    fn with_coords(&self, coords: &[f64]) -> Self {
+        let n0 = self.0.num_coords();
+        let n1 = self.1.num_coords();
+        (self.0.with_coords(coords), self.1.with_coords(&coords[n0..]), self.2.with_coords(&coords[n0+n1..]))
+    }
+}
+
+/// Estimate the Jacobian of `f` at `x` by central differences, with a column per coordinate of `x`.
+pub(crate) fn central_difference_jacobian(x: &[f64], mut f: impl FnMut(&[f64]) -> Vec<f64>) -> DMatrix<f64> {
+    let mut x_h = x.to_vec();
+    let mut columns = Vec::with_capacity(x.len());
+    for i in 0..x.len() {
+        let h = f64::EPSILON.cbrt() * x[i].abs().max(1.);
+        x_h[i] = x[i] + h;
+        let f_plus = DVector::from_vec(f(&x_h));
+        x_h[i] = x[i] - h;
+        let f_minus = DVector::from_vec(f(&x_h));
+        x_h[i] = x[i];
+        columns.push((f_plus - f_minus) / (2.*h));
+    }
+    DMatrix::from_columns(&columns)
+}
+
+
+/// Choices that can be combined, eg. to constrain a `GenFn` with both some observations
+/// and the choices sampled by a proposal.
+pub trait Merge<Other = Self> {
+    /// Merge the choices in `other` into `self`.
+    fn merge(&mut self, other: Other);
+}
+
+impl<V> Merge for Trie<V> {
+    fn merge(&mut self, other: Self) {
+        Trie::merge(self, other)
+    }
+}
+
+/// Merge step-by-step, appending any trailing steps of `other`.
+impl<A: Merge> Merge for Vec<A> {
+    fn merge(&mut self, other: Self) {
+        let mut other = other.into_iter();
+        for a in self.iter_mut() {
+            match other.next() {
+                Some(b) => a.merge(b),
+                None => { return; }
+            }
+        }
+        self.extend(other);
+    }
+}
+
+/// Merge into the last step (eg. the step being added by `ParticleSystem::step_with_proposal`).
+impl<V> Merge<Trie<V>> for Vec<Trie<V>> {
+    fn merge(&mut self, other: Trie<V>) {
+        match self.last_mut() {
+            Some(last) => last.merge(other),
+            None => self.push(other)
+        }
+    }
 }
 
 
@@ -0,0 +1,186 @@
+use std::sync::Arc;
+use std::any::Any;
+use rand::Rng;
+use nalgebra::DVector;
+use crate::{AddrMap, ArgDiff, GenFn, Trace, DynTrie, Differentiable, Distribution, normal};
+use crate::modeling::dyngenfn::selected_choices;
+
+
+/// The log joint density of a trace viewed as a function of the flattened coordinates
+/// of its selected continuous (`f64` or `DVector<f64>`) choices.
+///
+/// Shared by the gradient-based kernels (`hmc`, `nuts`, `mala`).
+pub(crate) struct ContinuousTarget<'a,Args,Ret,G: GenFn<Args,DynTrie,Ret>> {
+    model: &'a G,
+    selection: &'a AddrMap,
+    addrs: Vec<String>,
+    trace: Trace<Args,DynTrie,Ret>,
+    grad: Vec<f64>
+}
+
+impl<'a,Args: Clone,Ret: Clone,G: GenFn<Args,DynTrie,Ret>> ContinuousTarget<'a,Args,Ret,G> {
+    /// Construct a target from the continuous choices in `trace` selected by `selection`.
+    pub fn new(rng: &mut impl Rng, model: &'a G, trace: Trace<Args,DynTrie,Ret>, selection: &'a AddrMap) -> Self {
+        let addrs = continuous_choices(&trace.data, selection);
+        let grad = flatten(&model.choice_gradients(rng, &trace, selection), &addrs);
+        ContinuousTarget { model, selection, addrs, trace, grad }
+    }
+
+    /// Consume the target, returning the current trace.
+    pub fn into_trace(self) -> Trace<Args,DynTrie,Ret> {
+        self.trace
+    }
+
+    /// The coordinates of the selected choices in the current trace.
+    pub fn coords(&self) -> Vec<f64> {
+        flatten(&self.trace.data, &self.addrs)
+    }
+
+    /// The gradient of `logjp` with respect to the coordinates of the current trace.
+    pub fn grad(&self) -> &[f64] {
+        &self.grad
+    }
+
+    /// The log joint density of the current trace.
+    pub fn logjp(&self) -> f64 {
+        self.trace.logjp
+    }
+
+    /// Move the current trace to `coords`, recomputing its gradient.
+    ///
+    /// Return `false` (leaving the current trace unchanged) if the model is
+    /// undefined at `coords`, eg. if a distribution's parameters are invalid.
+    pub fn move_to(&mut self, rng: &mut impl Rng, coords: &[f64]) -> bool {
+        let constraints = self.unflatten(coords);
+        let args = self.trace.args.clone();
+        match self.model.try_update(rng, self.trace.clone(), args, ArgDiff::NoChange, constraints) {
+            Ok((trace, _, _)) => {
+                self.grad = flatten(&self.model.choice_gradients(rng, &trace, self.selection), &self.addrs);
+                self.trace = trace;
+                true
+            }
+            Err(_) => false
+        }
+    }
+
+    /// Rebuild a trie of the selected choices from `coords`.
+    pub fn unflatten(&self, coords: &[f64]) -> DynTrie {
+        let mut choices = DynTrie::new();
+        let mut offset = 0;
+        for addr in self.addrs.iter() {
+            let x = self.trace.data.search(addr).unwrap().ref_inner().unwrap();
+            if let Some(x) = x.downcast_ref::<f64>() {
+                choices.observe(addr, Arc::new(x.with_coords(&coords[offset..])));
+                offset += 1;
+            } else if let Some(x) = x.downcast_ref::<DVector<f64>>() {
+                choices.observe(addr, Arc::new(x.with_coords(&coords[offset..])));
+                offset += x.len();
+            }
+        }
+        choices
+    }
+}
+
+/// A point in phase space: position `x` and momentum `p`, with the `logjp` and its gradient at `x`.
+#[derive(Clone)]
+pub(crate) struct PhasePoint {
+    pub x: Vec<f64>,
+    pub p: Vec<f64>,
+    pub logjp: f64,
+    pub grad: Vec<f64>
+}
+
+impl PhasePoint {
+    /// Start from the current trace of `target` with momentum `p`.
+    pub fn new<Args: Clone,Ret: Clone,G: GenFn<Args,DynTrie,Ret>>(target: &ContinuousTarget<Args,Ret,G>, p: Vec<f64>) -> Self {
+        PhasePoint { x: target.coords(), p, logjp: target.logjp(), grad: target.grad().to_vec() }
+    }
+
+    /// The (negative) Hamiltonian `logjp - p^T M^-1 p / 2` for a diagonal mass matrix with inverse diagonal `inv_mass`.
+    pub fn log_density(&self, inv_mass: &[f64]) -> f64 {
+        self.logjp - kinetic_energy(&self.p, inv_mass)
+    }
+}
+
+/// Sample a momentum `p ~ N(0, M)` for a diagonal mass matrix `M` with inverse diagonal `inv_mass`.
+pub(crate) fn sample_momentum(rng: &mut impl Rng, inv_mass: &[f64]) -> Vec<f64> {
+    inv_mass.iter().map(|m_inv| normal.random(rng, (0., 1.)) / m_inv.sqrt()).collect()
+}
+
+/// Kinetic energy `p^T M^-1 p / 2` of a momentum `p` for a diagonal mass matrix with inverse diagonal `inv_mass`.
+pub(crate) fn kinetic_energy(p: &[f64], inv_mass: &[f64]) -> f64 {
+    p.iter().zip(inv_mass).map(|(p, m_inv)| p*p*m_inv).sum::<f64>() / 2.
+}
+
+/// Take a single leapfrog step of size `step_size` from `point`, moving the `target` to the new position.
+///
+/// Return `None` if the step left the region where the model is defined.
+pub(crate) fn leapfrog<Args: Clone,Ret: Clone,G: GenFn<Args,DynTrie,Ret>>(
+    rng: &mut impl Rng,
+    target: &mut ContinuousTarget<Args,Ret,G>,
+    point: &PhasePoint,
+    step_size: f64,
+    inv_mass: &[f64]
+) -> Option<PhasePoint> {
+    let mut p = point.p.iter().zip(&point.grad)
+        .map(|(p, g)| p + step_size/2. * g)
+        .collect::<Vec<f64>>();
+    let x = point.x.iter().zip(&p).zip(inv_mass)
+        .map(|((x, p), m_inv)| x + step_size * m_inv * p)
+        .collect::<Vec<f64>>();
+    if !target.move_to(rng, &x) {
+        return None;
+    }
+    for (p, g) in p.iter_mut().zip(target.grad()) {
+        *p += step_size/2. * g;
+    }
+    Some(PhasePoint { x, p, logjp: target.logjp(), grad: target.grad().to_vec() })
+}
+
+/// Collect the (sorted) addresses of the continuous (`f64` or `DVector<f64>`) choices in `data` that are selected by `selection`.
+pub(crate) fn continuous_choices(data: &DynTrie, selection: &AddrMap) -> Vec<String> {
+    let mut addrs = selected_choices(data, selection)
+        .into_iter()
+        .filter(|addr| {
+            let x = data.search(addr).unwrap().ref_inner().unwrap();
+            x.is::<f64>() || x.is::<DVector<f64>>()
+        })
+        .collect::<Vec<String>>();
+    addrs.sort();
+    addrs
+}
+
+/// Flatten the `f64` and `DVector<f64>` values at `addrs` in `data` into coordinates.
+pub(crate) fn flatten(data: &DynTrie, addrs: &[String]) -> Vec<f64> {
+    let mut coords = vec![];
+    for addr in addrs {
+        let x = data.search(addr).unwrap().ref_inner().unwrap();
+        if let Some(x) = x.downcast_ref::<f64>() {
+            coords.extend(x.to_coords());
+        } else if let Some(x) = x.downcast_ref::<DVector<f64>>() {
+            coords.extend(x.to_coords());
+        }
+    }
+    coords
+}
+
+/// Copy `data`, replacing the `f64` and `DVector<f64>` values at `addrs` with the (flattened) `coords`.
+pub(crate) fn with_coords(data: &DynTrie, addrs: &[String], coords: &[f64]) -> DynTrie {
+    let mut data = data.clone();
+    let mut offset = 0;
+    for addr in addrs {
+        let leaf = data.remove(addr).unwrap();
+        let x = leaf.ref_inner().unwrap();
+        let x: Arc<dyn Any + Send + Sync> = if let Some(x) = x.downcast_ref::<f64>() {
+            offset += 1;
+            Arc::new(x.with_coords(&coords[offset-1..]))
+        } else if let Some(x) = x.downcast_ref::<DVector<f64>>() {
+            offset += x.len();
+            Arc::new(x.with_coords(&coords[offset-x.len()..]))
+        } else {
+            unreachable!()
+        };
+        data.w_observe(addr, x, leaf.weight());
+    }
+    data
+}
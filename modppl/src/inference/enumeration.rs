@@ -0,0 +1,161 @@
+use std::sync::Arc;
+use std::any::Any;
+use rand::Rng;
+use crate::{Trace, AddrMap, DynTrie, DynTrace, DynGenFn, DynGenFnHandler, Error, logsumexp};
+
+
+/// A finite grid of values for a selection of choices, whose cartesian product
+/// enumerates every joint assignment of the selection.
+///
+/// In `enumerate`, the values of an axis override the support of the choice (eg. to discretize a continuous choice).
+#[derive(Clone,Default)]
+pub struct ChoiceGrid {
+    axes: Vec<(String, Vec<Arc<dyn Any + Send + Sync>>)>
+}
+
+impl ChoiceGrid {
+    /// Construct an empty grid, with a single (empty) assignment.
+    pub fn new() -> Self {
+        ChoiceGrid { axes: vec![] }
+    }
+
+    /// Add an axis enumerating the `values` of the choice at `addr`.
+    pub fn with<V: Any + Send + Sync>(mut self, addr: &str, values: impl IntoIterator<Item=V>) -> Self {
+        let values = values.into_iter()
+            .map(|v| Arc::new(v) as Arc<dyn Any + Send + Sync>)
+            .collect::<Vec<_>>();
+        self.axes.push((addr.to_string(), values));
+        self
+    }
+
+    /// Return the number of joint assignments in the grid.
+    pub fn len(&self) -> usize {
+        self.axes.iter().map(|(_, values)| values.len()).product()
+    }
+
+    /// Return `true` if some axis has no values (so the grid has no assignments), otherwise `false`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the values of the axis at `addr`, if any.
+    fn values(&self, addr: &str) -> Option<&Vec<Arc<dyn Any + Send + Sync>>> {
+        self.axes.iter().find(|(a, _)| a == addr).map(|(_, values)| values)
+    }
+
+    /// Return the selection of addresses enumerated by the grid.
+    pub fn selection(&self) -> AddrMap {
+        let mut selection = AddrMap::new();
+        for (addr, _) in self.axes.iter() {
+            selection.visit(addr);
+        }
+        selection
+    }
+
+    /// Return every joint assignment of the grid, each merged into a copy of the `observations`.
+    pub fn assignments(&self, observations: &DynTrie) -> Vec<DynTrie> {
+        let mut assignments = Vec::with_capacity(self.len());
+        let mut index = vec![0; self.axes.len()];
+        for _ in 0..self.len() {
+            let mut constraints = observations.clone();
+            for ((addr, values), &i) in self.axes.iter().zip(index.iter()) {
+                constraints.observe(addr, values[i].clone());
+            }
+            assignments.push(constraints);
+
+            // advance the mixed-radix counter, last axis fastest
+            for (k, (_, values)) in self.axes.iter().enumerate().rev() {
+                index[k] += 1;
+                if index[k] < values.len() {
+                    break;
+                }
+                index[k] = 0;
+            }
+        }
+        assignments
+    }
+}
+
+/// Generate a trace of the `model` under the `constraints`, returning it with its weight and the
+/// first unconstrained choice in the `selection` (and its support), if any.
+#[allow(clippy::type_complexity)]
+fn generate_next<Args: Clone,Ret>(
+    rng: &mut impl Rng,
+    model: &DynGenFn<Args,Ret>,
+    args: Args,
+    constraints: DynTrie,
+    selection: &AddrMap
+) -> (DynTrace<Args,Ret>, f64, Option<(String, Option<Vec<Arc<dyn Any + Send + Sync>>>)>) {
+    let mut g = DynGenFnHandler::Enumerate {
+        prng: rng,
+        trace: Trace { args: args.clone(), data: DynTrie::new(), retv: None, logjp: 0. },
+        weight: 0.,
+        constraints,
+        selection,
+        next: None,
+        error: None
+    };
+    let retv = (model.func)(&mut g, args);
+    g.take_error().unwrap_or_else(|err| panic!("enumerate: {err}"));
+    let DynGenFnHandler::Enumerate {prng: _, mut trace, weight, constraints, selection: _, next, error: _} = g else { unreachable!() };
+    assert!(constraints.is_empty(), "enumerate: {}", Error::UnconsumedConstraints(constraints.schema()));
+    trace.logjp = trace.data.weight();
+    trace.set_retv(retv);
+    (trace, weight, next)
+}
+
+/// Performs exact inference for a `DynGenFn` whose latent choices are all discrete, by enumeration.
+///
+/// Enumerates every joint assignment of the choices of `model` in the `selection` (and in the `grid`) that aren't
+/// in the `observations`, depth-first in the order they are sampled, so a choice may depend on the values of earlier ones.
+/// The values of a choice are the `Distribution::support` of its distribution, unless overridden by the `grid`.
+///
+/// Panics if a choice has neither a finite support nor values in the `grid`, or if some choice of `model` is neither
+/// enumerated nor observed. Choices sampled inside `trace_at` are never enumerated, so must be observed.
+///
+/// Returns a tuple of (as in `importance_sampling`):
+/// 1. a vector of traces, one per assignment.
+/// 2. the log of their normalized weights (the exact log posterior probabilities).
+/// 3. the exact log marginal likelihood of the `observations` under the `model`.
+pub fn enumerate<Args: Clone,Ret>(
+    rng: &mut impl Rng,
+    model: &DynGenFn<Args,Ret>,
+    model_args: Args,
+    observations: DynTrie,
+    selection: &AddrMap,
+    grid: &ChoiceGrid
+) -> (Vec<DynTrace<Args,Ret>>, Vec<f64>, f64) {
+    let mut selection = selection.clone();
+    for (addr, _) in grid.axes.iter() {
+        selection.visit(addr);
+    }
+    let mut traces = vec![];
+    let mut log_weights = vec![];
+    let mut stack = vec![observations];
+    while let Some(constraints) = stack.pop() {
+        let (trace, weight, next) = generate_next(rng, model, model_args.clone(), constraints.clone(), &selection);
+        match next {
+            Some((addr, support)) => {
+                let values = grid.values(&addr).cloned().or(support)
+                    .unwrap_or_else(|| panic!("enumerate: the choice at \"{addr}\" has no finite support and no values in the grid"));
+                // pushed in reverse, so the assignments are visited in the order of the values
+                for v in values.into_iter().rev() {
+                    let mut constraints = constraints.clone();
+                    constraints.observe(&addr, v);
+                    stack.push(constraints);
+                }
+            }
+            None => {
+                // any unconstrained choice was sampled, so isn't accounted for in the weight
+                assert!(weight == trace.logjp || (weight - trace.logjp).abs() < 1e-8,
+                    "enumerate: the selection and observations must constrain every choice of the model");
+                traces.push(trace);
+                log_weights.push(weight);
+            }
+        }
+    }
+    // unlike `importance_sampling`, the weights are summed rather than averaged
+    let log_ml = logsumexp(&log_weights);
+    let log_normalized_weights = log_weights.iter().map(|w| w - log_ml).collect::<Vec<f64>>();
+    (traces, log_normalized_weights, log_ml)
+}
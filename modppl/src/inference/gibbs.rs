@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use rand::{distributions::Uniform, Rng};
+use crate::{Trace, GenFn, ArgDiff, DynTrie, Error, Distribution, categorical_logits, logsumexp};
+
+
+/// Perform an enumerative Gibbs update of the discrete choice at `addr` in the given `trace`.
+///
+/// The choice must be drawn from `dist` with parameters `params`, whose finite `Distribution::support`
+/// is enumerated by updating the `trace` to every value, and a new value is sampled in proportion to
+/// the resulting joint densities. Panics if `dist.logpdf` with `params` doesn't match the log density
+/// recorded at `addr`.
+///
+/// If a value changes the set of other choices in the trace (eg. `is_linear` in a model that only
+/// samples a quadratic coefficient when it is `false`), new choices are drawn from the internal
+/// proposal and the sampled value is accepted with a multiple-try Metropolis-Hastings correction.
+/// Otherwise the update samples the exact conditional and is always accepted.
+pub fn enumerative_gibbs<Args: Clone + 'static,Ret: Clone + 'static,V: Clone + PartialEq + Send + Sync + 'static,U: Clone>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    addr: &str,
+    dist: &impl Distribution<V,U>,
+    params: &U
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    let support = dist.support(params).expect("enumerative_gibbs: the distribution must have a finite support");
+    let value = trace.data.read::<V>(addr);
+    assert!(support.contains(&value), "enumerative_gibbs: the value at \"{addr}\" isn't in the support of the distribution");
+    if let Err(err) = check_recorded_logpdf(&trace.data, addr, &value, dist, params) {
+        panic!("enumerative_gibbs: {err}");
+    }
+
+    let fwd = support.iter()
+        .map(|v| if *v == value { Some((trace.clone(), DynTrie::new(), 0.)) } else { update_at(rng, model, &trace, addr, v) })
+        .collect::<Vec<_>>();
+    let fwd_log_weights = fwd.iter()
+        .map(|candidate| candidate.as_ref().map_or(f64::NEG_INFINITY, |(_, _, w)| trace.logjp + w))
+        .collect::<Vec<f64>>();
+    let k = categorical_logits.random(rng, fwd_log_weights.clone()) as usize;
+    if support[k] == value {
+        return (trace, true);
+    }
+    let schema = trace.data.schema();
+    let same_structure = fwd.iter().flatten().all(|(tr, _, _)| tr.data.schema() == schema);
+    let (new_trace, discard, _) = fwd.into_iter().nth(k).unwrap().unwrap();
+    if same_structure {
+        return (new_trace, true);
+    }
+
+    // the reverse move reproduces the discarded choices of the current trace, as if drawn from the internal proposal
+    let discard_log_q = discard.weight() - discard.search(addr).unwrap().weight();
+    let bwd_log_weights = support.iter()
+        .map(|v| if *v == support[k] {
+            new_trace.logjp
+        } else if *v == value {
+            trace.logjp - discard_log_q
+        } else {
+            update_at(rng, model, &new_trace, addr, v).map_or(f64::NEG_INFINITY, |(_, _, w)| new_trace.logjp + w)
+        })
+        .collect::<Vec<f64>>();
+
+    let alpha = logsumexp(&fwd_log_weights) - logsumexp(&bwd_log_weights);
+    if rng.sample(Uniform::new(0_f64, 1_f64)).ln() < alpha {
+        (new_trace, true)
+    } else {
+        (trace, false)
+    }
+}
+
+/// Alias for `enumerative_gibbs`.
+pub fn gibbs<Args: Clone + 'static,Ret: Clone + 'static,V: Clone + PartialEq + Send + Sync + 'static,U: Clone>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    addr: &str,
+    dist: &impl Distribution<V,U>,
+    params: &U
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    enumerative_gibbs(rng, model, trace, addr, dist, params)
+}
+
+/// Return `Error::InvalidParameters` unless the logpdf of `value` under `dist` with `params` matches the log density recorded
+/// at `addr` in `data`, ie. unless the caller passed the distribution and parameters that the model used for the choice.
+pub(crate) fn check_recorded_logpdf<V,U: Clone>(
+    data: &DynTrie,
+    addr: &str,
+    value: &V,
+    dist: &impl Distribution<V,U>,
+    params: &U
+) -> Result<(), Error> {
+    let recorded_logpdf = data.try_search(addr)?.weight();
+    let logpdf = dist.logpdf(value, params.clone());
+    if (logpdf - recorded_logpdf).abs() > 1e-8 * (1. + recorded_logpdf.abs()) {
+        return Err(Error::InvalidParameters(format!(
+            "the distribution has logpdf {logpdf} at \"{addr}\", but the trace recorded {recorded_logpdf}")));
+    }
+    Ok(())
+}
+
+/// Update a copy of `trace` with the value `v` at `addr`, or `None` if `v` leaves the support of the model.
+fn update_at<Args: Clone,Ret: Clone,V: Clone + Send + Sync + 'static>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: &Trace<Args,DynTrie,Ret>,
+    addr: &str,
+    v: &V
+) -> Option<(Trace<Args,DynTrie,Ret>, DynTrie, f64)> {
+    let mut constraints = DynTrie::new();
+    constraints.observe(addr, Arc::new(v.clone()));
+    match model.try_update(rng, trace.clone(), trace.args.clone(), ArgDiff::NoChange, constraints) {
+        Ok(result) => Some(result),
+        Err(Error::InvalidParameters(_)) => None,
+        Err(err) => { panic!("enumerative_gibbs: {err}"); }
+    }
+}
@@ -0,0 +1,50 @@
+use rand::{distributions::Uniform, Rng};
+use crate::{Trace, GenFn, AddrMap, DynTrie};
+use super::continuous::{ContinuousTarget, PhasePoint, sample_momentum, leapfrog};
+
+
+/// Perform a Hamiltonian Monte Carlo update of the continuous choices in the given `trace` selected by `selection`.
+///
+/// Simulates `num_steps` leapfrog steps of size `step_size` along the gradient of `trace.logjp` (see `GenFn::choice_gradients`),
+/// using an identity mass matrix, and accepts or rejects the endpoint with a Metropolis-Hastings correction.
+/// Only choices of type `f64` or `DVector<f64>` are updated.
+pub fn hamiltonian_monte_carlo<Args: Clone + 'static,Ret: Clone + 'static>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    selection: &AddrMap,
+    step_size: f64,
+    num_steps: u32
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    let prev_trace = trace.clone();
+    let mut target = ContinuousTarget::new(rng, model, trace, selection);
+    let inv_mass = vec![1.; target.coords().len()];
+
+    let init = PhasePoint::new(&target, sample_momentum(rng, &inv_mass));
+    let mut point = init.clone();
+    for _ in 0..num_steps {
+        match leapfrog(rng, &mut target, &point, step_size, &inv_mass) {
+            Some(next) => { point = next; }
+            None => { return (prev_trace, false); }
+        }
+    }
+
+    let alpha = point.log_density(&inv_mass) - init.log_density(&inv_mass);
+    if rng.sample(Uniform::new(0_f64, 1_f64)).ln() < alpha {
+        (target.into_trace(), true)
+    } else {
+        (prev_trace, false)
+    }
+}
+
+/// Alias for `hamiltonian_monte_carlo`.
+pub fn hmc<Args: Clone + 'static,Ret: Clone + 'static>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    selection: &AddrMap,
+    step_size: f64,
+    num_steps: u32
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    hamiltonian_monte_carlo(rng, model, trace, selection, step_size, num_steps)
+}
@@ -1,25 +1,114 @@
-use crate::{logsumexp, Trace, GenFn, Distribution, categorical};
-use rand::rngs::ThreadRng;
+use crate::{logsumexp, Trace, GenFn, Merge, AliasTable};
+use rand::Rng;
+#[cfg(feature = "parallel")]
+use rand::{SeedableRng, rngs::StdRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 
 /// Performs inference for a `GenFn` via importance sampling.
 /// 
 /// Given a `model`, input arguments `model_args`, and `constraints`,
-/// returns a tuple of:
+/// draws `num_samples` traces using randomness from `rng` and returns a tuple of:
 /// 1. a vector of traces generated from `model` under the `constraints`.
 /// 2. the log of the normalized weights using the internal proposal.
 /// 3. the log marginal likelihood estimate of the `constraints` under the `model`.
 pub fn importance_sampling<Args: Clone,Data: Clone,Ret>(
+    rng: &mut impl Rng,
     model: &impl GenFn<Args,Data,Ret>,
     model_args: Args,
     constraints: Data,
     num_samples: u32
 ) -> (Vec<Trace<Args,Data,Ret>>, Vec<f64>, f64) {
     let out = (0..num_samples)
-        .map(|_| model.generate(model_args.clone(), constraints.clone()))
+        .map(|_| model.generate(rng, model_args.clone(), constraints.clone()))
         .collect::<Vec<(Trace<Args,Data,Ret>,f64)>>();
+    normalize_samples(out)
+}
+
+/// Performs inference for a `GenFn` via importance sampling with a custom `proposal`.
+///
+/// Each sample simulates choices from the `proposal` given `proposal_args`, merges them with the
+/// `constraints`, and generates a trace of the `model` under the merged constraints. Any choices
+/// not covered by either are drawn from the internal proposal of the `model`.
+///
+/// Returns a tuple of (as in `importance_sampling`):
+/// 1. a vector of traces generated from `model` under the merged constraints.
+/// 2. the log of the normalized weights (model weight minus proposal log density).
+/// 3. the log marginal likelihood estimate of the `constraints` under the `model`.
+pub fn importance_sampling_with_proposal<Args: Clone,Data: Clone + Merge<ProposalData>,Ret,ProposalArgs: Clone,ProposalData,ProposalRet>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,Data,Ret>,
+    model_args: Args,
+    constraints: Data,
+    proposal: &impl GenFn<ProposalArgs,ProposalData,ProposalRet>,
+    proposal_args: ProposalArgs,
+    num_samples: u32
+) -> (Vec<Trace<Args,Data,Ret>>, Vec<f64>, f64) {
+    let out = (0..num_samples)
+        .map(|_| {
+            let (proposed, proposal_weight) = proposal.propose(rng, proposal_args.clone());
+            let mut merged = constraints.clone();
+            merged.merge(proposed);
+            let (trace, model_weight) = model.generate(rng, model_args.clone(), merged);
+            (trace, model_weight - proposal_weight)
+        })
+        .collect::<Vec<(Trace<Args,Data,Ret>,f64)>>();
+    normalize_samples(out)
+}
+
+/// Parallel variant of `importance_sampling`, generating the samples across threads.
+///
+/// Each sample draws from its own `StdRng` stream seeded from `rng`, so results are
+/// deterministic given the state of `rng` (though they differ from those of `importance_sampling`).
+#[cfg(feature = "parallel")]
+pub fn par_importance_sampling<Args: Clone + Send + Sync,Data: Clone + Send + Sync,Ret: Send>(
+    rng: &mut impl Rng,
+    model: &(impl GenFn<Args,Data,Ret> + Sync),
+    model_args: Args,
+    constraints: Data,
+    num_samples: u32
+) -> (Vec<Trace<Args,Data,Ret>>, Vec<f64>, f64) {
+    let seeds = (0..num_samples).map(|_| rng.gen::<u64>()).collect::<Vec<u64>>();
+    let out = seeds.into_par_iter()
+        .map(|seed| model.generate(&mut StdRng::seed_from_u64(seed), model_args.clone(), constraints.clone()))
+        .collect::<Vec<(Trace<Args,Data,Ret>,f64)>>();
+    normalize_samples(out)
+}
+
+/// Parallel variant of `importance_sampling_with_proposal`, proposing and generating the samples across threads.
+///
+/// Each sample draws from its own `StdRng` stream seeded from `rng`, as in `par_importance_sampling`.
+#[cfg(feature = "parallel")]
+pub fn par_importance_sampling_with_proposal<
+    Args: Clone + Send + Sync,Data: Clone + Send + Sync + Merge<ProposalData>,Ret: Send,
+    ProposalArgs: Clone + Send + Sync,ProposalData,ProposalRet
+>(
+    rng: &mut impl Rng,
+    model: &(impl GenFn<Args,Data,Ret> + Sync),
+    model_args: Args,
+    constraints: Data,
+    proposal: &(impl GenFn<ProposalArgs,ProposalData,ProposalRet> + Sync),
+    proposal_args: ProposalArgs,
+    num_samples: u32
+) -> (Vec<Trace<Args,Data,Ret>>, Vec<f64>, f64) {
+    let seeds = (0..num_samples).map(|_| rng.gen::<u64>()).collect::<Vec<u64>>();
+    let out = seeds.into_par_iter()
+        .map(|seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (proposed, proposal_weight) = proposal.propose(&mut rng, proposal_args.clone());
+            let mut merged = constraints.clone();
+            merged.merge(proposed);
+            let (trace, model_weight) = model.generate(&mut rng, model_args.clone(), merged);
+            (trace, model_weight - proposal_weight)
+        })
+        .collect::<Vec<(Trace<Args,Data,Ret>,f64)>>();
+    normalize_samples(out)
+}
+
+fn normalize_samples<Args,Data,Ret>(out: Vec<(Trace<Args,Data,Ret>,f64)>) -> (Vec<Trace<Args,Data,Ret>>, Vec<f64>, f64) {
     let log_total_weight = logsumexp(&out.iter().map(|(_, w)| *w).collect::<Vec<f64>>());
-    let log_ml_estimate = log_total_weight - (num_samples as f64).ln();
+    let log_ml_estimate = log_total_weight - (out.len() as f64).ln();
     let log_normalized_weights = out.iter()
         .map(|(_, w)| w - log_total_weight)
         .collect::<Vec<f64>>();
@@ -30,22 +119,83 @@ pub fn importance_sampling<Args: Clone,Data: Clone,Ret>(
 /// Performs inference for a `GenFn` via importance resampling.
 /// 
 /// Given a `model`, input arguments `model_args`, and `constraints`,
-/// returns a tuple of:
+/// draws `num_samples` traces using randomness from `rng` and returns a tuple of:
 /// 1. a vector of traces generated from `model` under the `constraints`.
 /// 2. a resampled set of traces according to the normalized probabilities.
 /// 3. the log marginal likelihood estimate of the `constraints` under the `model`.
 pub fn importance_resampling<Args: Clone,Data: Clone,Ret>(
+    rng: &mut impl Rng,
     model: &impl GenFn<Args,Data,Ret>,
     model_args: Args,
     constraints: Data,
     num_samples: u32,
     num_ret_samples: u32
 ) -> (Vec<Trace<Args,Data,Ret>>, Vec<usize>, f64) {
-    let (traces, weights, log_ml_estimate) = importance_sampling(model, model_args, constraints, num_samples);
-    let mut rng = ThreadRng::default();
-    let probs = weights.iter().map(|w| w.exp()).collect::<Vec<f64>>();
-    let resampled_indices = (0..num_ret_samples).map(|_| {
-        categorical.random(&mut rng, probs.clone()) as usize
-    }).collect::<Vec<usize>>();
-    (traces, resampled_indices, log_ml_estimate)
+    let (traces, weights, log_ml_estimate) = importance_sampling(rng, model, model_args, constraints, num_samples);
+    (traces, resample_indices(rng, &weights, num_ret_samples), log_ml_estimate)
+}
+
+/// Performs inference for a `GenFn` via importance resampling with a custom `proposal`.
+///
+/// Draws `num_samples` traces as in `importance_sampling_with_proposal` and returns a tuple of
+/// (as in `importance_resampling`):
+/// 1. a vector of traces generated from `model` under the merged constraints.
+/// 2. a resampled set of traces according to the normalized probabilities.
+/// 3. the log marginal likelihood estimate of the `constraints` under the `model`.
+#[allow(clippy::too_many_arguments)]
+pub fn importance_resampling_with_proposal<Args: Clone,Data: Clone + Merge<ProposalData>,Ret,ProposalArgs: Clone,ProposalData,ProposalRet>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,Data,Ret>,
+    model_args: Args,
+    constraints: Data,
+    proposal: &impl GenFn<ProposalArgs,ProposalData,ProposalRet>,
+    proposal_args: ProposalArgs,
+    num_samples: u32,
+    num_ret_samples: u32
+) -> (Vec<Trace<Args,Data,Ret>>, Vec<usize>, f64) {
+    let (traces, weights, log_ml_estimate) = importance_sampling_with_proposal(
+        rng, model, model_args, constraints, proposal, proposal_args, num_samples
+    );
+    (traces, resample_indices(rng, &weights, num_ret_samples), log_ml_estimate)
+}
+
+/// Parallel variant of `importance_resampling`, drawing the samples with `par_importance_sampling`.
+#[cfg(feature = "parallel")]
+pub fn par_importance_resampling<Args: Clone + Send + Sync,Data: Clone + Send + Sync,Ret: Send>(
+    rng: &mut impl Rng,
+    model: &(impl GenFn<Args,Data,Ret> + Sync),
+    model_args: Args,
+    constraints: Data,
+    num_samples: u32,
+    num_ret_samples: u32
+) -> (Vec<Trace<Args,Data,Ret>>, Vec<usize>, f64) {
+    let (traces, weights, log_ml_estimate) = par_importance_sampling(rng, model, model_args, constraints, num_samples);
+    (traces, resample_indices(rng, &weights, num_ret_samples), log_ml_estimate)
+}
+
+/// Parallel variant of `importance_resampling_with_proposal`, drawing the samples with `par_importance_sampling_with_proposal`.
+#[cfg(feature = "parallel")]
+#[allow(clippy::too_many_arguments)]
+pub fn par_importance_resampling_with_proposal<
+    Args: Clone + Send + Sync,Data: Clone + Send + Sync + Merge<ProposalData>,Ret: Send,
+    ProposalArgs: Clone + Send + Sync,ProposalData,ProposalRet
+>(
+    rng: &mut impl Rng,
+    model: &(impl GenFn<Args,Data,Ret> + Sync),
+    model_args: Args,
+    constraints: Data,
+    proposal: &(impl GenFn<ProposalArgs,ProposalData,ProposalRet> + Sync),
+    proposal_args: ProposalArgs,
+    num_samples: u32,
+    num_ret_samples: u32
+) -> (Vec<Trace<Args,Data,Ret>>, Vec<usize>, f64) {
+    let (traces, weights, log_ml_estimate) = par_importance_sampling_with_proposal(
+        rng, model, model_args, constraints, proposal, proposal_args, num_samples
+    );
+    (traces, resample_indices(rng, &weights, num_ret_samples), log_ml_estimate)
+}
+
+fn resample_indices(rng: &mut impl Rng, log_normalized_weights: &[f64], num_ret_samples: u32) -> Vec<usize> {
+    let table = AliasTable::from_log_weights(log_normalized_weights);
+    (0..num_ret_samples).map(|_| table.sample(rng)).collect::<Vec<usize>>()
 }
\ No newline at end of file
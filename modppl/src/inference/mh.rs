@@ -1,12 +1,15 @@
 use std::sync::{Arc,Weak};
-use rand::{distributions::Uniform, rngs::ThreadRng, Rng};
-use crate::{Trace,GenFn,AddrMap,ArgDiff};
+use rand::{distributions::Uniform, Rng};
+use crate::{Trace,GenFn,AddrMap,ArgDiff,Error,DynTrie,Distribution,normal};
+use crate::gfi::central_difference_jacobian;
+use super::continuous::{ContinuousTarget, continuous_choices, flatten, with_coords};
 
 
 /// Perform a Metropolis-Hastings update that proposes new values for some subset of random choices in the given `trace` under the `model` using the given `proposal` generative function.
 /// 
 /// The `proposal` shares the same trace data structure as the `model`, but must accept a `Weak` reference to the `trace` as its first argument and return an empty tuple `()`.
 pub fn metropolis_hastings<Args: Clone + 'static,Data: Clone + 'static,Ret: Clone + 'static,ProposalArgs: Clone>(
+    rng: &mut impl Rng,
     model: &impl GenFn<Args,Data,Ret>,
     trace: Trace<Args,Data,Ret>,
     proposal: &impl GenFn<(Weak<Trace<Args,Data,Ret>>,ProposalArgs),Data,()>,
@@ -16,15 +19,19 @@ pub fn metropolis_hastings<Args: Clone + 'static,Data: Clone + 'static,Ret: Clon
 
     let trace = Arc::new(trace);
     let proposal_args_forward = (Arc::downgrade(&trace), proposal_args.clone());
-    let (fwd_choices, fwd_weight) = proposal.propose(proposal_args_forward);
+    let (fwd_choices, fwd_weight) = proposal.propose(rng, proposal_args_forward);
     let trace = Arc::into_inner(trace).unwrap();
 
     let args = trace.args.clone();
-    let (trace, discard, weight) = model.update(trace, args.clone(), ArgDiff::NoChange, fwd_choices);
+    let (trace, discard, weight) = match model.try_update(rng, trace, args.clone(), ArgDiff::NoChange, fwd_choices) {
+        Ok(result) => result,
+        Err(Error::InvalidParameters(_)) => { return (prev_trace, false); }  // proposal left the support of the model
+        Err(err) => { panic!("metropolis_hastings: {err}"); }
+    };
 
     let trace = Arc::new(trace);
     let proposal_args_backward = (Arc::downgrade(&trace), proposal_args);
-    let bwd_weight = proposal.assess(proposal_args_backward, discard);
+    let bwd_weight = proposal.assess(rng, proposal_args_backward, discard);
     let trace = Arc::into_inner(trace).unwrap();
 
     // dbg!(weight);
@@ -32,7 +39,7 @@ pub fn metropolis_hastings<Args: Clone + 'static,Data: Clone + 'static,Ret: Clon
     // dbg!(bwd_weight);
 
     let alpha = weight - fwd_weight + bwd_weight;
-    if ThreadRng::default().sample(Uniform::new(0_f64, 1_f64)).ln() < alpha {
+    if rng.sample(Uniform::new(0_f64, 1_f64)).ln() < alpha {
         (trace, true)
     } else {
         (prev_trace, false)
@@ -41,25 +48,31 @@ pub fn metropolis_hastings<Args: Clone + 'static,Data: Clone + 'static,Ret: Clon
 
 /// Alias for `metropolis_hastings`.
 pub fn mh<Args: Clone + 'static,Data: Clone + 'static,Ret: Clone + 'static,ProposalArgs: Clone>(
+    rng: &mut impl Rng,
     model: &impl GenFn<Args,Data,Ret>,
     trace: Trace<Args,Data,Ret>,
     proposal: &impl GenFn<(Weak<Trace<Args,Data,Ret>>,ProposalArgs),Data,()>,
     proposal_args: ProposalArgs
 ) -> (Trace<Args,Data,Ret>, bool) {
-    metropolis_hastings(model, trace, proposal, proposal_args)
+    metropolis_hastings(rng, model, trace, proposal, proposal_args)
 }
 
 
 /// Perform a Metropolis-Hastings update that proposes new values for some `mask` of random choices in the given `trace` under the `model` using the internal proposal.
 pub fn regenerative_metropolis_hastings<Args: Clone + 'static,Data: Clone + 'static,Ret: Clone + 'static>(
+    rng: &mut impl Rng,
     model: &impl GenFn<Args,Data,Ret>,
     trace: Trace<Args,Data,Ret>,
     mask: &AddrMap,
 ) -> (Trace<Args,Data,Ret>, bool) {
     let prev_trace = trace.clone();
     let args = trace.args.clone();
-    let (trace, weight) = model.regenerate(trace, args, ArgDiff::NoChange, mask);
-    if ThreadRng::default().sample(Uniform::new(0_f64, 1_f64)).ln() < weight {
+    let (trace, weight) = match model.try_regenerate(rng, trace, args, ArgDiff::NoChange, mask) {
+        Ok(result) => result,
+        Err(Error::InvalidParameters(_)) => { return (prev_trace, false); }
+        Err(err) => { panic!("regenerative_metropolis_hastings: {err}"); }
+    };
+    if rng.sample(Uniform::new(0_f64, 1_f64)).ln() < weight {
         (trace, true)
     } else {
         (prev_trace, false)
@@ -68,9 +81,191 @@ pub fn regenerative_metropolis_hastings<Args: Clone + 'static,Data: Clone + 'sta
 
 /// Alias for `regenerative_metropolis_hastings`.
 pub fn regen_mh<Args: Clone + 'static,Data: Clone + 'static,Ret: Clone + 'static>(
+    rng: &mut impl Rng,
     model: &impl GenFn<Args,Data,Ret>,
     trace: Trace<Args,Data,Ret>,
     mask: &AddrMap,
 ) -> (Trace<Args,Data,Ret>, bool) {
-    regenerative_metropolis_hastings(model, trace, mask)
-}
\ No newline at end of file
+    regenerative_metropolis_hastings(rng, model, trace, mask)
+}
+
+
+/// Perform a Metropolis-adjusted Langevin (MALA) update of the continuous choices in the given `trace` selected by `selection`.
+/// 
+/// Proposes `x' ~ N(x + step_size^2 / 2 * grad(x), step_size^2 I)` using the gradient of `trace.logjp` (see `GenFn::choice_gradients`),
+/// and accepts or rejects the proposal with a Metropolis-Hastings correction. Only choices of type `f64` or `DVector<f64>` are updated.
+pub fn metropolis_adjusted_langevin<Args: Clone + 'static,Ret: Clone + 'static>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    selection: &AddrMap,
+    step_size: f64
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    let prev_trace = trace.clone();
+    let mut target = ContinuousTarget::new(rng, model, trace, selection);
+    let x = target.coords();
+    let x_grad = target.grad().to_vec();
+    let x_new = x.iter().zip(&x_grad)
+        .map(|(x, g)| normal.random(rng, (x + step_size*step_size/2. * g, step_size)))
+        .collect::<Vec<f64>>();
+    if !target.move_to(rng, &x_new) {
+        return (prev_trace, false);
+    }
+
+    let fwd_weight = langevin_logpdf(&x_new, &x, &x_grad, step_size);
+    let bwd_weight = langevin_logpdf(&x, &x_new, target.grad(), step_size);
+    let alpha = target.logjp() - prev_trace.logjp - fwd_weight + bwd_weight;
+    if rng.sample(Uniform::new(0_f64, 1_f64)).ln() < alpha {
+        (target.into_trace(), true)
+    } else {
+        (prev_trace, false)
+    }
+}
+
+/// Alias for `metropolis_adjusted_langevin`.
+pub fn mala<Args: Clone + 'static,Ret: Clone + 'static>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    selection: &AddrMap,
+    step_size: f64
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    metropolis_adjusted_langevin(rng, model, trace, selection, step_size)
+}
+
+/// Log density of the Langevin proposal `to ~ N(from + step_size^2 / 2 * from_grad, step_size^2 I)`.
+fn langevin_logpdf(to: &[f64], from: &[f64], from_grad: &[f64], step_size: f64) -> f64 {
+    to.iter().zip(from).zip(from_grad)
+        .map(|((to, from), g)| normal.logpdf(to, (from + step_size*step_size/2. * g, step_size)))
+        .sum()
+}
+
+
+/// Perform an involutive MCMC update of the given `trace`, as described in:
+///
+/// > Automating Involutive MCMC using Probabilistic and Differentiable Programming.
+/// > Cusumano-Towner, M. F., Lew, A. K., and Mansinghka, V. K.
+/// > arXiv:2007.09871 (2020).
+///
+/// Auxiliary choices are drawn from the `proposal` (which receives a `Weak` reference to the `trace`, as in `metropolis_hastings`),
+/// and the `involution` maps the choices of the model and the auxiliary choices to constraints for the new model trace
+/// (which must determine every choice that isn't in the previous trace), the auxiliary choices of the reverse move, and the
+/// log-abs-determinant of its Jacobian. Applying the `involution` to its own output (with the new model choices) must recover its input.
+///
+/// Moves may change the number of choices (reversible jump), as long as the involution preserves the total number of continuous
+/// (`f64` or `DVector<f64>`) coordinates among the rewritten model choices and the auxiliary choices. The Jacobian is taken from
+/// those model and auxiliary coordinates to the coordinates of the constraints and reverse auxiliary choices; it can be checked
+/// against `estimate_involution_log_abs_det`.
+///
+/// Panics if the update of the `model` fails for any reason other than invalid parameters (see `try_involutive_metropolis_hastings`).
+pub fn involutive_metropolis_hastings<Args: Clone + 'static,Ret: Clone + 'static,ProposalArgs: Clone>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    proposal: &impl GenFn<(Weak<Trace<Args,DynTrie,Ret>>,ProposalArgs),DynTrie,()>,
+    proposal_args: ProposalArgs,
+    involution: impl Fn(&DynTrie, &DynTrie) -> (DynTrie, DynTrie, f64)
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    match try_involutive_metropolis_hastings(rng, model, trace, proposal, proposal_args, involution) {
+        Ok(result) => result,
+        Err(err) => { panic!("involutive_metropolis_hastings: {err}"); }
+    }
+}
+
+/// Like `involutive_metropolis_hastings`, but returns an `Error` if the update of the `model` fails for any reason other than
+/// invalid parameters (which rejects the move), or `Error::InvalidArguments` if the `involution` leaves a new choice of the model
+/// unconstrained.
+pub fn try_involutive_metropolis_hastings<Args: Clone + 'static,Ret: Clone + 'static,ProposalArgs: Clone>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    proposal: &impl GenFn<(Weak<Trace<Args,DynTrie,Ret>>,ProposalArgs),DynTrie,()>,
+    proposal_args: ProposalArgs,
+    involution: impl Fn(&DynTrie, &DynTrie) -> (DynTrie, DynTrie, f64)
+) -> Result<(Trace<Args,DynTrie,Ret>, bool), Error> {
+    let trace = Arc::new(trace);
+    let (fwd_choices, fwd_weight) = proposal.propose(rng, (Arc::downgrade(&trace), proposal_args.clone()));
+    let trace = Arc::into_inner(trace).unwrap();
+
+    let (constraints, bwd_choices, log_abs_det) = involution(&trace.data, &fwd_choices);
+    let args = trace.args.clone();
+    let (new_trace, _, weight) = match model.try_update(rng, trace.clone(), args, ArgDiff::NoChange, constraints) {
+        Ok(result) => result,
+        Err(Error::InvalidParameters(_)) => { return Ok((trace, false)); }
+        Err(err) => { return Err(err); }
+    };
+    // any choice sampled by the update (rather than determined by the involution) isn't accounted for in the weight
+    if (new_trace.logjp - trace.logjp - weight).abs() > 1e-8 && weight != f64::NEG_INFINITY {
+        return Err(Error::InvalidArguments("the involution must constrain every new choice of the model".to_string()));
+    }
+
+    let new_trace = Arc::new(new_trace);
+    let bwd_weight = proposal.assess(rng, (Arc::downgrade(&new_trace), proposal_args), bwd_choices);
+    let new_trace = Arc::into_inner(new_trace).unwrap();
+
+    let alpha = weight - fwd_weight + bwd_weight + log_abs_det;
+    if rng.sample(Uniform::new(0_f64, 1_f64)).ln() < alpha {
+        Ok((new_trace, true))
+    } else {
+        Ok((trace, false))
+    }
+}
+
+/// Alias for `involutive_metropolis_hastings`.
+pub fn involutive_mh<Args: Clone + 'static,Ret: Clone + 'static,ProposalArgs: Clone>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    proposal: &impl GenFn<(Weak<Trace<Args,DynTrie,Ret>>,ProposalArgs),DynTrie,()>,
+    proposal_args: ProposalArgs,
+    involution: impl Fn(&DynTrie, &DynTrie) -> (DynTrie, DynTrie, f64)
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    involutive_metropolis_hastings(rng, model, trace, proposal, proposal_args, involution)
+}
+
+/// Estimate the log-abs-determinant of the Jacobian of the `involution` at the choices of the `trace` and the auxiliary
+/// `fwd_choices` by central differences, eg. to check the log-abs-determinant returned by the `involution` in tests.
+///
+/// Costs two evaluations of the `involution` per continuous coordinate, plus an update of the `model` to find the choices it discards.
+/// Returns `Error::InvalidArguments` if the `involution` doesn't preserve the number of continuous coordinates.
+pub fn estimate_involution_log_abs_det<Args: Clone + 'static,Ret: Clone + 'static>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: &Trace<Args,DynTrie,Ret>,
+    fwd_choices: &DynTrie,
+    involution: impl Fn(&DynTrie, &DynTrie) -> (DynTrie, DynTrie, f64)
+) -> Result<f64, Error> {
+    let (constraints, bwd_choices, _) = involution(&trace.data, fwd_choices);
+    let (_, discard, _) = model.try_update(rng, trace.clone(), trace.args.clone(), ArgDiff::NoChange, constraints.clone())?;
+
+    let data = &trace.data;
+    let mut model_in = continuous_choices(data, &constraints.schema());
+    model_in.extend(continuous_choices(data, &discard.schema()));
+    model_in.sort();
+    model_in.dedup();
+    let aux_in = continuous_choices(fwd_choices, &fwd_choices.schema());
+    let model_out = continuous_choices(&constraints, &constraints.schema());
+    let aux_out = continuous_choices(&bwd_choices, &bwd_choices.schema());
+
+    let mut x = flatten(data, &model_in);
+    let num_model_coords = x.len();
+    x.extend(flatten(fwd_choices, &aux_in));
+    let num_out_coords = flatten(&constraints, &model_out).len() + flatten(&bwd_choices, &aux_out).len();
+    if x.len() != num_out_coords {
+        return Err(Error::InvalidArguments(format!(
+            "the involution maps {} continuous coordinates to {num_out_coords}", x.len())));
+    }
+    if x.is_empty() {
+        return Ok(0.);
+    }
+
+    let jacobian = central_difference_jacobian(&x, |coords| {
+        let data = with_coords(data, &model_in, &coords[..num_model_coords]);
+        let fwd_choices = with_coords(fwd_choices, &aux_in, &coords[num_model_coords..]);
+        let (constraints, bwd_choices, _) = involution(&data, &fwd_choices);
+        let mut y = flatten(&constraints, &model_out);
+        y.extend(flatten(&bwd_choices, &aux_out));
+        y
+    });
+    Ok(jacobian.determinant().abs().ln())
+}
@@ -1,10 +1,39 @@
 ///
 pub mod importance;
 ///
+pub mod enumeration;
+///
 pub mod mh;
 ///
+pub mod gibbs;
+///
+pub mod hmc;
+///
+pub mod nuts;
+///
 pub mod particle_filter;
+///
+pub mod pmmh;
+///
+pub mod resampling;
+///
+pub mod slice;
+///
+pub mod tempering;
+
+mod continuous;
+mod particle_gibbs;
 
-pub use self::importance::{importance_sampling, importance_resampling};
-pub use self::mh::{metropolis_hastings, mh, regenerative_metropolis_hastings, regen_mh};
-pub use self::particle_filter::ParticleSystem;
\ No newline at end of file
+pub use self::importance::{importance_sampling, importance_resampling, importance_sampling_with_proposal, importance_resampling_with_proposal};
+#[cfg(feature = "parallel")]
+pub use self::importance::{par_importance_sampling, par_importance_sampling_with_proposal, par_importance_resampling, par_importance_resampling_with_proposal};
+pub use self::enumeration::{enumerate, ChoiceGrid};
+pub use self::mh::{metropolis_hastings, mh, regenerative_metropolis_hastings, regen_mh, metropolis_adjusted_langevin, mala, involutive_metropolis_hastings, try_involutive_metropolis_hastings, involutive_mh, estimate_involution_log_abs_det};
+pub use self::gibbs::{enumerative_gibbs, gibbs};
+pub use self::slice::{elliptical_slice_sampling, try_elliptical_slice_sampling, elliptical_slice, slice_sampling, slice_sample};
+pub use self::hmc::{hamiltonian_monte_carlo, hmc};
+pub use self::nuts::NoUTurnSampler;
+pub use self::particle_filter::ParticleSystem;
+pub use self::resampling::ResamplingScheme;
+pub use self::pmmh::{estimate_log_marginal_likelihood, particle_marginal_metropolis_hastings, pmmh};
+pub use self::tempering::{annealed_importance_sampling, tempered_smc};
\ No newline at end of file
@@ -0,0 +1,300 @@
+use rand::{distributions::Uniform, Rng};
+use crate::{Trace, GenFn, AddrMap, DynTrie};
+use super::continuous::{ContinuousTarget, PhasePoint, sample_momentum, leapfrog};
+
+
+// Dual-averaging constants recommended by Hoffman & Gelman (2014).
+const GAMMA: f64 = 0.05;
+const T0: f64 = 10.;
+const KAPPA: f64 = 0.75;
+
+// Energy error beyond which a trajectory is considered divergent.
+const MAX_ENERGY_ERROR: f64 = 1000.;
+
+
+/// No-U-Turn Sampler (NUTS) over a selection of continuous choices, as described in:
+///
+/// > The No-U-Turn Sampler: Adaptively Setting Path Lengths in Hamiltonian Monte Carlo.
+/// > Hoffman, M. D. and Gelman, A.
+/// > Journal of Machine Learning Research 15 (2014).
+///
+/// Trajectory lengths are chosen automatically during each `step`, while the `step_size`
+/// (and optionally a diagonal `inv_mass` matrix) are tuned with `warmup`.
+/// Only choices of type `f64` or `DVector<f64>` are updated, so `step`s compose with
+/// other kernels (eg. `mh` over discrete choices) acting on the rest of the trace.
+pub struct NoUTurnSampler {
+    /// Leapfrog step size.
+    pub step_size: f64,
+
+    /// Diagonal of the inverse mass matrix, or `None` for the identity.
+    pub inv_mass: Option<Vec<f64>>,
+
+    /// Maximum depth of the trajectory tree (at most `2^max_depth` leapfrog steps per `step`).
+    pub max_depth: u32,
+
+    /// Mean acceptance statistic targeted when adapting the `step_size` during `warmup`.
+    pub target_accept: f64,
+
+    /// Whether `warmup` also estimates `inv_mass` from the variance of the warmup samples.
+    pub adapt_mass: bool
+}
+
+impl NoUTurnSampler {
+    /// Construct a sampler with an initial `step_size`, an identity mass matrix,
+    /// a `max_depth` of 10, and a `target_accept` of 0.8.
+    pub fn new(step_size: f64) -> Self {
+        NoUTurnSampler { step_size, inv_mass: None, max_depth: 10, target_accept: 0.8, adapt_mass: false }
+    }
+
+    /// Perform a NUTS update of the continuous choices in the given `trace` selected by `selection`.
+    ///
+    /// Return the new trace and the mean acceptance statistic of the trajectory.
+    /// If the model is undefined at the selected sample, the trajectory is rejected:
+    /// the `trace` is returned unchanged with an acceptance statistic of 0.
+    pub fn step<Args: Clone + 'static,Ret: Clone + 'static>(&self,
+        rng: &mut impl Rng,
+        model: &impl GenFn<Args,DynTrie,Ret>,
+        trace: Trace<Args,DynTrie,Ret>,
+        selection: &AddrMap
+    ) -> (Trace<Args,DynTrie,Ret>, f64) {
+        let (trace, accept_stat, _) = self.transition(rng, model, trace, selection);
+        (trace, accept_stat)
+    }
+
+    /// Run `num_iters` NUTS updates from the given `trace`, adapting the `step_size` by dual averaging
+    /// towards `target_accept`, and return the final trace.
+    ///
+    /// If `adapt_mass` is set, `inv_mass` is estimated from the samples in the middle half of the warmup,
+    /// after which the step size adaptation restarts.
+    pub fn warmup<Args: Clone + 'static,Ret: Clone + 'static>(&mut self,
+        rng: &mut impl Rng,
+        model: &impl GenFn<Args,DynTrie,Ret>,
+        mut trace: Trace<Args,DynTrie,Ret>,
+        selection: &AddrMap,
+        num_iters: u32
+    ) -> Trace<Args,DynTrie,Ret> {
+        let (window_start, window_end) = if self.adapt_mass { (num_iters / 4, 3 * num_iters / 4) } else { (num_iters, num_iters) };
+        let mut adaptation = DualAveraging::new(self.step_size);
+        let mut variance = RunningVariance::new();
+        for iter in 0..num_iters {
+            let (new_trace, accept_stat, coords) = self.transition(rng, model, trace, selection);
+            trace = new_trace;
+            self.step_size = adaptation.update(accept_stat, self.target_accept);
+            if (window_start..window_end).contains(&iter) {
+                variance.push(&coords);
+            }
+            if iter + 1 == window_end && variance.count > 0 {
+                self.inv_mass = Some(variance.regularized());
+                adaptation = DualAveraging::new(self.step_size);
+            }
+        }
+        if num_iters > 0 {
+            self.step_size = adaptation.final_step_size();
+        }
+        trace
+    }
+
+    fn transition<Args: Clone + 'static,Ret: Clone + 'static>(&self,
+        rng: &mut impl Rng,
+        model: &impl GenFn<Args,DynTrie,Ret>,
+        trace: Trace<Args,DynTrie,Ret>,
+        selection: &AddrMap
+    ) -> (Trace<Args,DynTrie,Ret>, f64, Vec<f64>) {
+        let prev_trace = trace.clone();
+        let mut target = ContinuousTarget::new(rng, model, trace, selection);
+        let dim = target.coords().len();
+        let inv_mass = match &self.inv_mass {
+            Some(inv_mass) => {
+                assert_eq!(inv_mass.len(), dim, "nuts: inv_mass has {} entries for {} coordinates", inv_mass.len(), dim);
+                inv_mass.clone()
+            }
+            None => vec![1.; dim]
+        };
+
+        let init = PhasePoint::new(&target, sample_momentum(rng, &inv_mass));
+        let init_x = init.x.clone();
+        let h0 = init.log_density(&inv_mass);
+        let log_u = h0 + rng.sample(Uniform::new(0_f64, 1_f64)).ln();
+
+        let mut minus = init.clone();
+        let mut plus = init.clone();
+        let mut sample = init;
+        let mut n = 1.;
+        let mut alpha = 0.;
+        let mut n_alpha = 0.;
+        for depth in 0..self.max_depth {
+            let forward = rng.sample(Uniform::new(0_f64, 1_f64)) < 0.5;
+            let (start, v) = if forward { (&plus, 1.) } else { (&minus, -1.) };
+            let ctx = TreeContext { log_u, h0, step_size: v * self.step_size, inv_mass: &inv_mass };
+            let subtree = self.build_tree(rng, &mut target, start, depth, &ctx);
+            alpha += subtree.alpha;
+            n_alpha += subtree.n_alpha;
+            if forward { plus = subtree.plus; } else { minus = subtree.minus; }
+            if !subtree.no_turn {
+                break;
+            }
+            if rng.sample(Uniform::new(0_f64, 1_f64)) < subtree.n / n {
+                sample = subtree.sample;
+            }
+            n += subtree.n;
+            if !no_u_turn(&minus, &plus, &inv_mass) {
+                break;
+            }
+        }
+
+        if !target.move_to(rng, &sample.x) {
+            // the model couldn't be replayed at the sample, so reject the whole trajectory
+            return (prev_trace, 0., init_x);
+        }
+        (target.into_trace(), if n_alpha > 0. { alpha / n_alpha } else { 0. }, sample.x)
+    }
+
+    fn build_tree<Args: Clone,Ret: Clone,G: GenFn<Args,DynTrie,Ret>>(&self,
+        rng: &mut impl Rng,
+        target: &mut ContinuousTarget<Args,Ret,G>,
+        point: &PhasePoint,
+        depth: u32,
+        ctx: &TreeContext
+    ) -> Tree {
+        if depth == 0 {
+            return match leapfrog(rng, target, point, ctx.step_size, ctx.inv_mass) {
+                Some(next) => {
+                    let h = next.log_density(ctx.inv_mass);
+                    let alpha = (h - ctx.h0).exp().min(1.);
+                    Tree {
+                        minus: next.clone(),
+                        plus: next.clone(),
+                        sample: next,
+                        n: if ctx.log_u <= h { 1. } else { 0. },
+                        no_turn: h > ctx.log_u - MAX_ENERGY_ERROR,
+                        alpha: if alpha.is_nan() { 0. } else { alpha },
+                        n_alpha: 1.
+                    }
+                }
+                None => Tree {
+                    minus: point.clone(),
+                    plus: point.clone(),
+                    sample: point.clone(),
+                    n: 0.,
+                    no_turn: false,
+                    alpha: 0.,
+                    n_alpha: 1.
+                }
+            };
+        }
+
+        let mut tree = self.build_tree(rng, target, point, depth - 1, ctx);
+        if !tree.no_turn {
+            return tree;
+        }
+        let edge = if ctx.step_size > 0. { &tree.plus } else { &tree.minus };
+        let other = self.build_tree(rng, target, &edge.clone(), depth - 1, ctx);
+        if ctx.step_size > 0. { tree.plus = other.plus; } else { tree.minus = other.minus; }
+        if other.n > 0. && rng.sample(Uniform::new(0_f64, 1_f64)) < other.n / (tree.n + other.n) {
+            tree.sample = other.sample;
+        }
+        tree.n += other.n;
+        tree.alpha += other.alpha;
+        tree.n_alpha += other.n_alpha;
+        tree.no_turn = other.no_turn && no_u_turn(&tree.minus, &tree.plus, ctx.inv_mass);
+        tree
+    }
+}
+
+
+/// Quantities shared by every node while building a trajectory tree.
+struct TreeContext<'a> {
+    log_u: f64,
+    h0: f64,
+    step_size: f64,
+    inv_mass: &'a [f64]
+}
+
+/// A (sub)trajectory built by `NoUTurnSampler::build_tree`.
+struct Tree {
+    minus: PhasePoint,
+    plus: PhasePoint,
+    sample: PhasePoint,
+    n: f64,
+    no_turn: bool,
+    alpha: f64,
+    n_alpha: f64
+}
+
+/// Return `true` if neither end of the trajectory from `minus` to `plus` has started to double back.
+fn no_u_turn(minus: &PhasePoint, plus: &PhasePoint, inv_mass: &[f64]) -> bool {
+    let mut dot_minus = 0.;
+    let mut dot_plus = 0.;
+    for (i, m_inv) in inv_mass.iter().enumerate() {
+        let dx = plus.x[i] - minus.x[i];
+        dot_minus += dx * m_inv * minus.p[i];
+        dot_plus += dx * m_inv * plus.p[i];
+    }
+    dot_minus >= 0. && dot_plus >= 0.
+}
+
+/// Dual-averaging step size adaptation (Hoffman & Gelman, 2014, Algorithm 5).
+struct DualAveraging {
+    mu: f64,
+    log_step_size_bar: f64,
+    h_bar: f64,
+    count: f64
+}
+
+impl DualAveraging {
+    fn new(step_size: f64) -> Self {
+        DualAveraging { mu: (10. * step_size).ln(), log_step_size_bar: 0., h_bar: 0., count: 0. }
+    }
+
+    /// Incorporate the acceptance statistic of the latest step and return the next step size.
+    fn update(&mut self, accept_stat: f64, target_accept: f64) -> f64 {
+        self.count += 1.;
+        let eta = 1. / (self.count + T0);
+        self.h_bar = (1. - eta) * self.h_bar + eta * (target_accept - accept_stat);
+        let log_step_size = self.mu - self.count.sqrt() / GAMMA * self.h_bar;
+        let weight = self.count.powf(-KAPPA);
+        self.log_step_size_bar = weight * log_step_size + (1. - weight) * self.log_step_size_bar;
+        log_step_size.exp()
+    }
+
+    fn final_step_size(&self) -> f64 {
+        self.log_step_size_bar.exp()
+    }
+}
+
+/// Welford's online estimate of the per-coordinate variance.
+struct RunningVariance {
+    count: usize,
+    mean: Vec<f64>,
+    m2: Vec<f64>
+}
+
+impl RunningVariance {
+    fn new() -> Self {
+        RunningVariance { count: 0, mean: vec![], m2: vec![] }
+    }
+
+    fn push(&mut self, x: &[f64]) {
+        if self.count == 0 {
+            self.mean = vec![0.; x.len()];
+            self.m2 = vec![0.; x.len()];
+        }
+        self.count += 1;
+        for (i, x) in x.iter().enumerate() {
+            let delta = x - self.mean[i];
+            self.mean[i] += delta / self.count as f64;
+            self.m2[i] += delta * (x - self.mean[i]);
+        }
+    }
+
+    /// The sample variance, shrunk towards `1e-3` as in Stan.
+    fn regularized(&self) -> Vec<f64> {
+        let n = self.count as f64;
+        self.m2.iter()
+            .map(|m2| {
+                let var = if self.count > 1 { m2 / (n - 1.) } else { 1. };
+                (n / (n + 5.)) * var + 1e-3 * (5. / (n + 5.))
+            })
+            .collect()
+    }
+}
@@ -1,61 +1,80 @@
 // mostly copied verbatim from: https://github.com/OpenGen/GenTL/blob/main/include/gentl/inference/particle_filter.h
 
-use rand::rngs::ThreadRng;
-use crate::{Trace,GenFn,ArgDiff,Distribution,categorical,logsumexp};
+use std::sync::{Arc,Weak};
+use rand::Rng;
+#[cfg(feature = "parallel")]
+use rand::{SeedableRng, rngs::StdRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use crate::{Trace,GenFn,ArgDiff,Merge,logsumexp};
+use super::resampling::ResamplingScheme;
 
 
 /// Basic particle filter for generative functions with a time parameter as the first input argument.
-pub struct ParticleSystem<Args: Clone,Data: Clone,Ret: Clone,F: GenFn<(i64,Args),Data,Ret>> {
-    num_particles: usize,
-    model: Box<F>,
+/// 
+/// All randomness (trace generation, extension, and resampling) is drawn from the owned `rng`.
+pub struct ParticleSystem<Args: Clone,Data: Clone,Ret: Clone,F: GenFn<(i64,Args),Data,Ret>,R: Rng> {
+    pub(crate) num_particles: usize,
+    pub(crate) model: Box<F>,
 
     /// Persistent traces contained within the system
     pub traces: Vec<Trace<(i64,Args),Data,Ret>>,
 
-    log_weights: Vec<f64>,
+    pub(crate) log_weights: Vec<f64>,
     log_normalized_weights: Vec<f64>,
-    two_times_log_normalized_weights: Vec<f64>,
-    normalized_weights: Vec<f64>,
+    pub(crate) normalized_weights: Vec<f64>,
 
     parents: Vec<usize>,
-    rng: ThreadRng,
+    pub(crate) resampling: ResamplingScheme,
+    ess_threshold: Option<f64>,
+    pub(crate) rng: R,
 
-    log_ml_estimate: f64
+    pub(crate) log_ml_estimate: f64
 }
 
-impl<Args: Clone,Data: Clone,Ret: Clone,F: GenFn<(i64,Args),Data,Ret>> ParticleSystem<Args,Data,Ret,F> {
-    fn normalize_weights(&mut self) -> f64 {
+impl<Args: Clone,Data: Clone,Ret: Clone,F: GenFn<(i64,Args),Data,Ret>,R: Rng> ParticleSystem<Args,Data,Ret,F,R> {
+    pub(crate) fn normalize_weights(&mut self) -> f64 {
         let log_total_weight = logsumexp(&self.log_weights);
         for i in 0..self.num_particles {
             self.log_normalized_weights[i] = self.log_weights[i] - log_total_weight;
-            self.two_times_log_normalized_weights[i] = 2.0 * self.log_normalized_weights[i];
             self.normalized_weights[i] = self.log_normalized_weights[i].exp();
         }
         log_total_weight
     }
 
-    fn multinomial_resampling(&mut self) {
-        for i in 0..self.num_particles {
-            self.parents[i] = categorical.random(&mut self.rng, self.normalized_weights.clone()) as usize;
-        }
-    }
-
     /// Construct a new particle filter under the `model` with `num_particles` particles.
-    pub fn new(model: F, num_particles: usize, rng: ThreadRng) -> Self {
+    ///
+    /// Particles are resampled with `ResamplingScheme::Multinomial` unless changed by `with_resampling`,
+    /// and only when `resample` is called unless a threshold is set by `with_ess_threshold`.
+    pub fn new(model: F, num_particles: usize, rng: R) -> Self {
         ParticleSystem {
             num_particles,
             model: Box::new(model),
             traces: vec![],
             log_weights: vec![0.; num_particles],
             log_normalized_weights: vec![0.; num_particles],
-            two_times_log_normalized_weights: vec![0.; num_particles],
             normalized_weights: vec![0.; num_particles],
             parents: vec![0; num_particles],
-            rng: rng,
+            resampling: ResamplingScheme::default(),
+            ess_threshold: None,
+            rng,
             log_ml_estimate: 0.
         }
     }
 
+    /// Use the given `scheme` in subsequent calls to `resample`.
+    pub fn with_resampling(mut self, scheme: ResamplingScheme) -> Self {
+        self.resampling = scheme;
+        self
+    }
+
+    /// Automatically resample at the start of each `step` whenever the effective sample size
+    /// falls below `threshold * num_particles` (see `maybe_resample`).
+    pub fn with_ess_threshold(mut self, threshold: f64) -> Self {
+        self.ess_threshold = Some(threshold);
+        self
+    }
+
     /// Initialize the particle filter by generating `self.num_particles` traces from the `model` with `(1, args)`.
     pub fn init_step(
         &mut self,
@@ -63,20 +82,26 @@ impl<Args: Clone,Data: Clone,Ret: Clone,F: GenFn<(i64,Args),Data,Ret>> ParticleS
         constraints: Data
     ) {
         for i in 0..self.num_particles {
-            let (trace, log_weight) = self.model.generate((1, args.clone()), constraints.clone());
+            let (trace, log_weight) = self.model.generate(&mut self.rng, (1, args.clone()), constraints.clone());
             self.traces.push(trace);
             self.log_weights[i] = log_weight;
         }
     }
 
     /// Extend the current filter from `t` to `t+1` with new `constraints`.
-    pub fn step(self, constraints: Data) -> Self {
+    ///
+    /// If an ESS threshold was set with `with_ess_threshold`, the particles are first resampled when
+    /// their effective sample size falls below it. Otherwise the weights of the particles carry over.
+    pub fn step(mut self, constraints: Data) -> Self {
+        if let Some(threshold) = self.ess_threshold {
+            self.maybe_resample(threshold);
+        }
         let mut tmp_traces = vec![];
         let mut tmp_log_weights = vec![];
         for (i, trace) in self.traces.into_iter().enumerate() {
             let args = trace.args.clone();
             let new_args = (args.0 + 1, args.1);
-            let (new_trace, _, log_weight) = self.model.update(trace, new_args, ArgDiff::Extend, constraints.clone());
+            let (new_trace, _, log_weight) = self.model.update(&mut self.rng, trace, new_args, ArgDiff::Extend, constraints.clone());
             tmp_traces.push(new_trace);
             tmp_log_weights.push(self.log_weights[i] + log_weight);
         }
@@ -86,25 +111,77 @@ impl<Args: Clone,Data: Clone,Ret: Clone,F: GenFn<(i64,Args),Data,Ret>> ParticleS
             traces: tmp_traces,
             log_weights: tmp_log_weights,
             log_normalized_weights: self.log_normalized_weights,
-            two_times_log_normalized_weights: self.two_times_log_normalized_weights,
             normalized_weights: self.normalized_weights,
             parents: self.parents,
+            resampling: self.resampling,
+            ess_threshold: self.ess_threshold,
             rng: self.rng,
             log_ml_estimate: self.log_ml_estimate
         }
     }
 
-    /// Calculate the effective sample size (ESS) with the current paticle weights.
+    /// Extend the current filter from `t` to `t+1` with new `constraints`, using a custom `proposal`
+    /// for the new step's latent choices instead of the model's internal proposal.
+    ///
+    /// Like the proposals of `metropolis_hastings`, the `proposal` accepts a `Weak` reference to each (unextended) trace
+    /// as its first argument. Its choices are merged into the `constraints` of the new step, and the particle weights are
+    /// incremented by the model's update weight minus the proposal's weight. Resamples first as in `step`.
+    pub fn step_with_proposal<ProposalArgs: Clone,ProposalData>(
+        mut self,
+        constraints: Data,
+        proposal: &impl GenFn<(Weak<Trace<(i64,Args),Data,Ret>>,ProposalArgs),ProposalData,()>,
+        proposal_args: ProposalArgs
+    ) -> Self where Data: Merge<ProposalData> {
+        if let Some(threshold) = self.ess_threshold {
+            self.maybe_resample(threshold);
+        }
+        let traces = std::mem::take(&mut self.traces);
+        for (i, trace) in traces.into_iter().enumerate() {
+            let trace = Arc::new(trace);
+            let (choices, proposal_weight) = proposal.propose(&mut self.rng, (Arc::downgrade(&trace), proposal_args.clone()));
+            let trace = Arc::into_inner(trace).unwrap();
+
+            let mut step_constraints = constraints.clone();
+            step_constraints.merge(choices);
+            let args = trace.args.clone();
+            let new_args = (args.0 + 1, args.1);
+            let (new_trace, _, log_weight) = self.model.update(&mut self.rng, trace, new_args, ArgDiff::Extend, step_constraints);
+            self.traces.push(new_trace);
+            self.log_weights[i] += log_weight - proposal_weight;
+        }
+        self
+    }
+
+    /// Return the current log weights of the particles (unnormalized), in the order of `traces`.
+    pub fn log_weights(&self) -> &[f64] {
+        &self.log_weights
+    }
+
+    /// Calculate the effective sample size (ESS) with the current particle weights.
     pub fn effective_sample_size(&self) -> f64 {
-        (-logsumexp(&self.two_times_log_normalized_weights)).exp()
+        let two_times_log_weights = self.log_weights.iter().map(|w| 2.0 * w).collect::<Vec<f64>>();
+        (2.0 * logsumexp(&self.log_weights) - logsumexp(&two_times_log_weights)).exp()
+    }
+
+    /// Resample (as in `resample`) only if the effective sample size is below `ess_threshold * num_particles`.
+    ///
+    /// Return whether the particles were resampled.
+    pub fn maybe_resample(&mut self, ess_threshold: f64) -> bool {
+        if self.effective_sample_size() < ess_threshold * self.num_particles as f64 {
+            self.resample();
+            true
+        } else {
+            false
+        }
     }
 
-    /// Perform multinomial resampling based on the normalized particle weights, and return the log total weight.
+    /// Resample the particles based on their normalized weights using the configured `ResamplingScheme`,
+    /// and return the log total weight.
     pub fn resample(&mut self) -> f64 {
         let log_total_weight = self.normalize_weights();
         self.log_ml_estimate += log_total_weight - (self.num_particles as f64).ln();
 
-        self.multinomial_resampling();
+        self.parents = self.resampling.resample(&mut self.rng, &self.normalized_weights, self.num_particles);
 
         let mut tmp_traces = vec![];
         for i in 0..self.num_particles {
@@ -115,8 +192,131 @@ impl<Args: Clone,Data: Clone,Ret: Clone,F: GenFn<(i64,Args),Data,Ret>> ParticleS
         log_total_weight
     }
 
+    /// Apply an MCMC `kernel` (eg. a closure wrapping `mh` or `regen_mh`) to every trace, and return the fraction of accepted moves.
+    ///
+    /// The `kernel` receives the owned `rng`, the `model`, and a trace, and returns the new trace and whether the move was accepted.
+    /// The particle weights are left unchanged, so the `kernel` must leave the current target `p(data; (t, args))` invariant
+    /// (typically applied just after `resample`, as in resample-move SMC).
+    pub fn rejuvenate(
+        &mut self,
+        mut kernel: impl FnMut(&mut R, &F, Trace<(i64,Args),Data,Ret>) -> (Trace<(i64,Args),Data,Ret>, bool)
+    ) -> f64 {
+        let mut num_accepted = 0;
+        let traces = std::mem::take(&mut self.traces);
+        for trace in traces {
+            let (new_trace, accepted) = kernel(&mut self.rng, &self.model, trace);
+            if accepted {
+                num_accepted += 1;
+            }
+            self.traces.push(new_trace);
+        }
+        num_accepted as f64 / self.num_particles as f64
+    }
+
     /// Return the current log marginal likelihood estimate from the particles.
     pub fn log_marginal_likelihood_estimate(&self) -> f64 {
         self.log_ml_estimate + logsumexp(&self.log_weights) - (self.num_particles as f64).ln()
     }
-}
\ No newline at end of file
+}
+
+/// Parallel variants of the particle filter steps, enabled by the `parallel` feature.
+///
+/// Each particle draws from its own `StdRng` stream seeded from the owned `rng`, so results are
+/// deterministic given the seed regardless of the number of threads (though they differ from the serial steps).
+#[cfg(feature = "parallel")]
+impl<Args,Data,Ret,F,R> ParticleSystem<Args,Data,Ret,F,R>
+where
+    Args: Clone + Send + Sync,
+    Data: Clone + Send + Sync,
+    Ret: Clone + Send,
+    F: GenFn<(i64,Args),Data,Ret> + Sync,
+    R: Rng
+{
+    fn particle_rngs(&mut self) -> Vec<StdRng> {
+        (0..self.num_particles).map(|_| StdRng::seed_from_u64(self.rng.gen())).collect()
+    }
+
+    /// Parallel variant of `init_step`.
+    pub fn par_init_step(&mut self, args: Args, constraints: Data) {
+        let rngs = self.particle_rngs();
+        let model = &self.model;
+        let (traces, log_weights): (Vec<_>, Vec<_>) = rngs.into_par_iter()
+            .map(|mut rng| model.generate(&mut rng, (1, args.clone()), constraints.clone()))
+            .unzip();
+        self.traces = traces;
+        self.log_weights = log_weights;
+    }
+
+    /// Parallel variant of `step`.
+    pub fn par_step(mut self, constraints: Data) -> Self {
+        if let Some(threshold) = self.ess_threshold {
+            self.maybe_resample(threshold);
+        }
+        let rngs = self.particle_rngs();
+        let model = &self.model;
+        let traces = std::mem::take(&mut self.traces);
+        let (traces, log_weights): (Vec<_>, Vec<_>) = traces.into_par_iter()
+            .zip(rngs)
+            .zip(self.log_weights.par_iter())
+            .map(|((trace, mut rng), prev_log_weight)| {
+                let args = trace.args.clone();
+                let new_args = (args.0 + 1, args.1);
+                let (new_trace, _, log_weight) = model.update(&mut rng, trace, new_args, ArgDiff::Extend, constraints.clone());
+                (new_trace, prev_log_weight + log_weight)
+            })
+            .unzip();
+        self.traces = traces;
+        self.log_weights = log_weights;
+        self
+    }
+
+    /// Parallel variant of `step_with_proposal`.
+    pub fn par_step_with_proposal<ProposalArgs: Clone + Send + Sync,ProposalData>(
+        mut self,
+        constraints: Data,
+        proposal: &(impl GenFn<(Weak<Trace<(i64,Args),Data,Ret>>,ProposalArgs),ProposalData,()> + Sync),
+        proposal_args: ProposalArgs
+    ) -> Self where Data: Merge<ProposalData> {
+        if let Some(threshold) = self.ess_threshold {
+            self.maybe_resample(threshold);
+        }
+        let rngs = self.particle_rngs();
+        let model = &self.model;
+        let traces = std::mem::take(&mut self.traces);
+        let (traces, log_weights): (Vec<_>, Vec<_>) = traces.into_par_iter()
+            .zip(rngs)
+            .zip(self.log_weights.par_iter())
+            .map(|((trace, mut rng), prev_log_weight)| {
+                let trace = Arc::new(trace);
+                let (choices, proposal_weight) = proposal.propose(&mut rng, (Arc::downgrade(&trace), proposal_args.clone()));
+                let trace = Arc::into_inner(trace).unwrap();
+
+                let mut step_constraints = constraints.clone();
+                step_constraints.merge(choices);
+                let args = trace.args.clone();
+                let new_args = (args.0 + 1, args.1);
+                let (new_trace, _, log_weight) = model.update(&mut rng, trace, new_args, ArgDiff::Extend, step_constraints);
+                (new_trace, prev_log_weight + log_weight - proposal_weight)
+            })
+            .unzip();
+        self.traces = traces;
+        self.log_weights = log_weights;
+        self
+    }
+
+    /// Parallel variant of `rejuvenate`, where the `kernel` receives the `StdRng` stream of each particle.
+    pub fn par_rejuvenate(
+        &mut self,
+        kernel: impl Fn(&mut StdRng, &F, Trace<(i64,Args),Data,Ret>) -> (Trace<(i64,Args),Data,Ret>, bool) + Sync
+    ) -> f64 {
+        let rngs = self.particle_rngs();
+        let model = &self.model;
+        let traces = std::mem::take(&mut self.traces);
+        let (traces, accepted): (Vec<_>, Vec<_>) = traces.into_par_iter()
+            .zip(rngs)
+            .map(|(trace, mut rng)| kernel(&mut rng, model, trace))
+            .unzip();
+        self.traces = traces;
+        accepted.into_iter().filter(|accepted| *accepted).count() as f64 / self.num_particles as f64
+    }
+}
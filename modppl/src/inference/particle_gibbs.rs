@@ -0,0 +1,86 @@
+use rand::Rng;
+use crate::{Trace, GenFn, ArgDiff, DynTrie, DynParticles, Distribution, categorical_logits};
+use crate::modeling::dyngenfn::selected_weight;
+
+
+impl<State: Clone,R: Rng> DynParticles<State,R> {
+    /// Perform a conditional SMC sweep (a particle Gibbs move) that keeps the `reference` trajectory
+    /// in the first particle while the other particles are extended and resampled as usual, and
+    /// return a new trajectory drawn from the final particles. See:
+    ///
+    /// > Particle Markov chain Monte Carlo methods.
+    /// > Andrieu, C., Doucet, A., and Holenstein, R.
+    /// > Journal of the Royal Statistical Society: Series B 72 (2010).
+    ///
+    /// The `observations` hold the constraints of each step (as passed to `init_step` and `step`), and must match
+    /// the corresponding choices of the `reference`. Any particles in the system are replaced.
+    ///
+    /// If `ancestor_sampling` is set, the ancestor of the reference is resampled at every step in proportion to
+    /// each particle's weight times the density of the remaining reference steps given that particle
+    /// (Lindsten, Jordan, and Schön, 2014), which greatly improves mixing of the early states at a cost of `O(T^2)` updates.
+    pub fn conditional_smc(&mut self,
+        reference: &Trace<(i64,State),Vec<DynTrie>,Vec<State>>,
+        observations: Vec<DynTrie>,
+        ancestor_sampling: bool
+    ) -> Trace<(i64,State),Vec<DynTrie>,Vec<State>> {
+        let num_steps = observations.len();
+        assert!(num_steps >= 1);
+        assert_eq!(reference.data.len(), num_steps, "conditional_smc: reference has {} steps for {} observations", reference.data.len(), num_steps);
+        let init_state = reference.args.1.clone();
+        self.traces.clear();
+        self.log_ml_estimate = 0.;
+
+        // the weight of the reference is that of its observations, rescored in the extended trace
+        let (ref_trace, _) = self.model.generate(&mut self.rng, (1, init_state.clone()), vec![reference.data[0].clone()]);
+        self.log_weights[0] = selected_weight(ref_trace.data.last().unwrap(), &observations[0].schema());
+        self.traces.push(ref_trace);
+        for i in 1..self.num_particles {
+            let (trace, log_weight) = self.model.generate(&mut self.rng, (1, init_state.clone()), vec![observations[0].clone()]);
+            self.traces.push(trace);
+            self.log_weights[i] = log_weight;
+        }
+
+        for (t, step_observations) in observations.iter().enumerate().skip(1) {
+            let log_total_weight = self.normalize_weights();
+            self.log_ml_estimate += log_total_weight - (self.num_particles as f64).ln();
+
+            let ref_ancestor = if ancestor_sampling { self.sample_reference_ancestor(reference, t) } else { 0 };
+            let ancestors = std::iter::once(ref_ancestor)
+                .chain(self.resampling.resample(&mut self.rng, &self.normalized_weights, self.num_particles - 1))
+                .collect::<Vec<usize>>();
+            let parents = ancestors.into_iter().map(|a| self.traces[a].clone()).collect::<Vec<_>>();
+
+            self.traces.clear();
+            for (i, parent) in parents.into_iter().enumerate() {
+                let new_args = (parent.args.0 + 1, parent.args.1.clone());
+                if i == 0 {
+                    let (trace, _, _) = self.model.update(&mut self.rng, parent, new_args, ArgDiff::Extend, vec![reference.data[t].clone()]);
+                    self.log_weights[0] = selected_weight(trace.data.last().unwrap(), &step_observations.schema());
+                    self.traces.push(trace);
+                } else {
+                    let (trace, _, log_weight) = self.model.update(&mut self.rng, parent, new_args, ArgDiff::Extend, vec![step_observations.clone()]);
+                    self.traces.push(trace);
+                    self.log_weights[i] = log_weight;
+                }
+            }
+        }
+
+        self.normalize_weights();
+        let k = categorical_logits.random(&mut self.rng, self.log_weights.clone()) as usize;
+        self.traces[k].clone()
+    }
+
+    /// Sample the ancestor of the reference at step `t`, with probability proportional to the weight of each particle
+    /// times the joint density of the reference steps `t..` continued from that particle.
+    fn sample_reference_ancestor(&mut self, reference: &Trace<(i64,State),Vec<DynTrie>,Vec<State>>, t: usize) -> usize {
+        let num_steps = reference.data.len();
+        let future = reference.data[t..].to_vec();
+        let log_probs = (0..self.num_particles).map(|i| {
+            let trace = self.traces[i].clone();
+            let new_args = (trace.args.0 + (num_steps - t) as i64, trace.args.1.clone());
+            let (_, _, log_future) = self.model.update(&mut self.rng, trace, new_args, ArgDiff::Extend, future.clone());
+            self.log_weights[i] + log_future
+        }).collect::<Vec<f64>>();
+        categorical_logits.random(&mut self.rng, log_probs) as usize
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::{Arc,Weak};
+use rand::{distributions::Uniform, Rng, SeedableRng, rngs::StdRng};
+use crate::{Trace, GenFn, ArgDiff, Error, ParticleSystem};
+
+
+/// Estimate the log marginal likelihood of a sequence of `observations` under a `model` with a time parameter
+/// as the first input argument (see `ParticleSystem`), by running a particle filter with `num_particles` particles
+/// that resamples before every step. Panics if `observations` is empty.
+pub fn estimate_log_marginal_likelihood<Args: Clone,Data: Clone,Ret: Clone>(
+    rng: &mut impl Rng,
+    model: impl GenFn<(i64,Args),Data,Ret>,
+    args: Args,
+    observations: &[Data],
+    num_particles: usize
+) -> f64 {
+    assert!(!observations.is_empty(), "estimate_log_marginal_likelihood: at least one observation (for the initial step) is required");
+    let mut filter = ParticleSystem::new(model, num_particles, StdRng::seed_from_u64(rng.gen()));
+    filter.init_step(args, observations[0].clone());
+    for constraints in observations[1..].iter() {
+        filter.resample();
+        filter = filter.step(constraints.clone());
+    }
+    filter.log_marginal_likelihood_estimate()
+}
+
+/// Perform a particle marginal Metropolis-Hastings (PMMH) update of the static parameters in `param_trace`, as described in:
+///
+/// > Particle Markov chain Monte Carlo methods.
+/// > Andrieu, C., Doucet, A., and Holenstein, R.
+/// > Journal of the Royal Statistical Society: Series B 72 (2010).
+///
+/// The parameters are the return value of the `param_model` (their prior), and are proposed with the `proposal` as in
+/// `metropolis_hastings`. The `make_model` factory builds the state-space model and its (initial) arguments from proposed
+/// parameters, whose marginal likelihood of the `observations` is estimated with `estimate_log_marginal_likelihood`.
+/// The proposal is accepted or rejected using this estimate in place of the exact marginal likelihood.
+///
+/// Hand-written models can capture the parameters directly, while `DynUnfold` models can receive them through their state.
+///
+/// `log_ml_estimate` is the estimate for the current parameters (eg. from `estimate_log_marginal_likelihood`), and
+/// must be reused (not re-estimated) between updates for the chain to target the exact posterior.
+/// Return the new parameter trace, its log marginal likelihood estimate, and whether the proposal was accepted.
+#[allow(clippy::too_many_arguments)]
+pub fn particle_marginal_metropolis_hastings<
+    PArgs: Clone + 'static,PData: Clone + 'static,Params: Clone + 'static,ProposalArgs: Clone,
+    Args: Clone,Data: Clone,Ret: Clone,F: GenFn<(i64,Args),Data,Ret>
+>(
+    rng: &mut impl Rng,
+    param_model: &impl GenFn<PArgs,PData,Params>,
+    param_trace: Trace<PArgs,PData,Params>,
+    log_ml_estimate: f64,
+    proposal: &impl GenFn<(Weak<Trace<PArgs,PData,Params>>,ProposalArgs),PData,()>,
+    proposal_args: ProposalArgs,
+    make_model: impl Fn(&Params) -> (F, Args),
+    observations: &[Data],
+    num_particles: usize
+) -> (Trace<PArgs,PData,Params>, f64, bool) {
+    let prev_trace = param_trace.clone();
+
+    let param_trace = Arc::new(param_trace);
+    let (fwd_choices, fwd_weight) = proposal.propose(rng, (Arc::downgrade(&param_trace), proposal_args.clone()));
+    let param_trace = Arc::into_inner(param_trace).unwrap();
+
+    let param_args = param_trace.args.clone();
+    let (param_trace, discard, weight) = match param_model.try_update(rng, param_trace, param_args, ArgDiff::NoChange, fwd_choices) {
+        Ok(result) => result,
+        Err(Error::InvalidParameters(_)) => { return (prev_trace, log_ml_estimate, false); }
+        Err(err) => { panic!("particle_marginal_metropolis_hastings: {err}"); }
+    };
+
+    let param_trace = Arc::new(param_trace);
+    let bwd_weight = proposal.assess(rng, (Arc::downgrade(&param_trace), proposal_args), discard);
+    let param_trace = Arc::into_inner(param_trace).unwrap();
+
+    let (model, args) = make_model(param_trace.retv.as_ref().unwrap());
+    let new_log_ml_estimate = estimate_log_marginal_likelihood(rng, model, args, observations, num_particles);
+
+    let alpha = weight - fwd_weight + bwd_weight + new_log_ml_estimate - log_ml_estimate;
+    if rng.sample(Uniform::new(0_f64, 1_f64)).ln() < alpha {
+        (param_trace, new_log_ml_estimate, true)
+    } else {
+        (prev_trace, log_ml_estimate, false)
+    }
+}
+
+/// Alias for `particle_marginal_metropolis_hastings`.
+#[allow(clippy::too_many_arguments)]
+pub fn pmmh<
+    PArgs: Clone + 'static,PData: Clone + 'static,Params: Clone + 'static,ProposalArgs: Clone,
+    Args: Clone,Data: Clone,Ret: Clone,F: GenFn<(i64,Args),Data,Ret>
+>(
+    rng: &mut impl Rng,
+    param_model: &impl GenFn<PArgs,PData,Params>,
+    param_trace: Trace<PArgs,PData,Params>,
+    log_ml_estimate: f64,
+    proposal: &impl GenFn<(Weak<Trace<PArgs,PData,Params>>,ProposalArgs),PData,()>,
+    proposal_args: ProposalArgs,
+    make_model: impl Fn(&Params) -> (F, Args),
+    observations: &[Data],
+    num_particles: usize
+) -> (Trace<PArgs,PData,Params>, f64, bool) {
+    particle_marginal_metropolis_hastings(rng, param_model, param_trace, log_ml_estimate, proposal, proposal_args, make_model, observations, num_particles)
+}
@@ -0,0 +1,107 @@
+use rand::{distributions::Uniform, Rng};
+
+
+/// Scheme used to select the parents of a population of particles from their normalized weights.
+///
+/// Every scheme is unbiased (particle `i` has `num_samples * weights[i]` children in expectation)
+/// and runs in `O(N)` time. See:
+///
+/// > Comparison of Resampling Schemes for Particle Filtering.
+/// > Douc, R., Cappé, O., and Moulines, E.
+/// > Image and Signal Processing and Analysis (2005).
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum ResamplingScheme {
+    /// Independent draws from the categorical distribution of the weights.
+    #[default]
+    Multinomial,
+
+    /// A single uniform offset shared by `N` evenly spaced points.
+    Systematic,
+
+    /// One independent uniform draw within each of `N` evenly spaced strata.
+    Stratified,
+
+    /// `floor(N * w_i)` deterministic copies of each particle, with the remainder drawn multinomially
+    /// from the residual weights.
+    Residual
+}
+
+impl ResamplingScheme {
+    /// Sample `num_samples` parent indices from the normalized `weights`.
+    ///
+    /// The parents are not in random order: every scheme returns them sorted, except `Residual`, which
+    /// returns the sorted deterministic copies followed by the sorted residual draws. Shuffle them if
+    /// their positions must be exchangeable.
+    pub fn resample(&self, rng: &mut impl Rng, weights: &[f64], num_samples: usize) -> Vec<usize> {
+        let mut parents = Vec::with_capacity(num_samples);
+        if num_samples == 0 {
+            return parents;
+        }
+        assert!(!weights.is_empty(), "resample: no weights to resample from");
+        let n = num_samples as f64;
+        match self {
+            ResamplingScheme::Multinomial => {
+                let points = sorted_uniforms(rng, num_samples);
+                inverse_cdf(weights, points.into_iter(), &mut parents);
+            }
+            ResamplingScheme::Systematic => {
+                let u = rng.sample(Uniform::new(0_f64, 1_f64));
+                inverse_cdf(weights, (0..num_samples).map(|k| (k as f64 + u) / n), &mut parents);
+            }
+            ResamplingScheme::Stratified => {
+                let points = (0..num_samples)
+                    .map(|k| (k as f64 + rng.sample(Uniform::new(0_f64, 1_f64))) / n)
+                    .collect::<Vec<f64>>();
+                inverse_cdf(weights, points.into_iter(), &mut parents);
+            }
+            ResamplingScheme::Residual => {
+                let mut residuals = Vec::with_capacity(weights.len());
+                for (i, w) in weights.iter().enumerate() {
+                    let expected = n * w;
+                    let copies = (expected.floor() as usize).min(num_samples - parents.len());
+                    parents.resize(parents.len() + copies, i);
+                    residuals.push((expected - copies as f64).max(0.));
+                }
+                let num_residual = num_samples - parents.len();
+                if num_residual > 0 {
+                    let total = residuals.iter().sum::<f64>();
+                    if total > 0. {
+                        residuals.iter_mut().for_each(|r| *r /= total);
+                    } else {
+                        residuals = weights.to_vec();
+                    }
+                    let points = sorted_uniforms(rng, num_residual);
+                    inverse_cdf(&residuals, points.into_iter(), &mut parents);
+                }
+            }
+        }
+        parents
+    }
+}
+
+/// Draw `num_samples` sorted `U(0,1)` variates in `O(N)` time from normalized cumulative sums of exponentials.
+fn sorted_uniforms(rng: &mut impl Rng, num_samples: usize) -> Vec<f64> {
+    let mut sums = Vec::with_capacity(num_samples);
+    let mut total = 0.;
+    for _ in 0..=num_samples {
+        total -= (1. - rng.sample(Uniform::new(0_f64, 1_f64))).ln();
+        sums.push(total);
+    }
+    sums.pop();
+    sums.iter_mut().for_each(|s| *s /= total);
+    sums
+}
+
+/// Append the index of the cumulative `weights` bucket containing each of the sorted `points`.
+fn inverse_cdf(weights: &[f64], points: impl Iterator<Item=f64>, parents: &mut Vec<usize>) {
+    let last = weights.len() - 1;
+    let mut i = 0;
+    let mut cumulative = weights[0];
+    for u in points {
+        while u >= cumulative && i < last {
+            i += 1;
+            cumulative += weights[i];
+        }
+        parents.push(i);
+    }
+}
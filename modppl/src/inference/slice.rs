@@ -0,0 +1,183 @@
+use std::sync::Arc;
+use std::f64::consts::PI;
+use rand::{distributions::Uniform, Rng};
+use nalgebra::{DVector, DMatrix};
+use crate::{Trace, GenFn, ArgDiff, DynTrie, Error, Differentiable, Distribution};
+use crate::modeling::dists::{Normal, MvNormal};
+use super::gibbs::check_recorded_logpdf;
+
+
+/// Gaussian distributions, whose draws can be rotated around their mean by `elliptical_slice_sampling`.
+pub trait GaussianPrior<T,U>: Distribution<T,U> {
+    /// Return the mean of the distribution with parameters `params`.
+    fn mean(&self, params: &U) -> T;
+}
+
+impl GaussianPrior<f64,(f64,f64)> for Normal {
+    fn mean(&self, params: &(f64,f64)) -> f64 {
+        params.0
+    }
+}
+
+impl GaussianPrior<DVector<f64>,(DVector<f64>,DMatrix<f64>)> for MvNormal {
+    fn mean(&self, params: &(DVector<f64>,DMatrix<f64>)) -> DVector<f64> {
+        params.0.clone()
+    }
+}
+
+/// Perform an elliptical slice sampling update of the choice at `addr` in the given `trace`, as described in:
+///
+/// > Elliptical slice sampling.
+/// > Murray, I., Adams, R. P., and MacKay, D. J. C.
+/// > Proceedings of the 13th International Conference on Artificial Intelligence and Statistics (2010).
+///
+/// The choice must be drawn from the Gaussian `prior` (`normal` or `mvnormal`) with parameters `params`.
+/// A new value is found on the ellipse through the current value and a fresh draw from the prior by shrinking
+/// a bracket of angles until the rest of the model (evaluated with `GenFn::update`) is above a random slice,
+/// so the update has no tuning parameters and is always accepted.
+///
+/// Return `false` (leaving the trace unchanged) only if the bracket shrinks to nothing, eg. if the model is undefined near the current value.
+/// Panics if `prior.logpdf` with `params` doesn't match the log density recorded at `addr` (see `try_elliptical_slice_sampling`).
+pub fn elliptical_slice_sampling<Args: Clone + 'static,Ret: Clone + 'static,V: Differentiable + Clone + Send + Sync + 'static,U: Clone>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    addr: &str,
+    prior: &impl GaussianPrior<V,U>,
+    params: &U
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    match try_elliptical_slice_sampling(rng, model, trace, addr, prior, params) {
+        Ok(result) => result,
+        Err(err) => { panic!("elliptical_slice_sampling: {err}"); }
+    }
+}
+
+/// Like `elliptical_slice_sampling`, but returns `Error::InvalidParameters` if `prior.logpdf` with `params` doesn't match
+/// the log density recorded at `addr` (and `Error::MissingAddress` or `Error::TypeMismatch` if there's no choice of type `V` at `addr`).
+pub fn try_elliptical_slice_sampling<Args: Clone + 'static,Ret: Clone + 'static,V: Differentiable + Clone + Send + Sync + 'static,U: Clone>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    addr: &str,
+    prior: &impl GaussianPrior<V,U>,
+    params: &U
+) -> Result<(Trace<Args,DynTrie,Ret>, bool), Error> {
+    let x = trace.data.try_read::<V>(addr)?;
+    check_recorded_logpdf(&trace.data, addr, &x, prior, params)?;
+    let prior_logpdf = prior.logpdf(&x, params.clone());
+    let mean = prior.mean(params).to_coords();
+    let centered = x.to_coords().iter().zip(&mean).map(|(x, m)| x - m).collect::<Vec<f64>>();
+    let nu = prior.random(rng, params.clone()).to_coords().iter().zip(&mean).map(|(x, m)| x - m).collect::<Vec<f64>>();
+
+    // the log likelihood (everything but the prior) must rise above the slice
+    let log_slice = rng.sample(Uniform::new(0_f64, 1_f64)).ln() - prior_logpdf;
+
+    let mut theta = rng.sample(Uniform::new(0., 2. * PI));
+    let (mut theta_min, mut theta_max) = (theta - 2. * PI, theta);
+    while theta_max - theta_min > 1e-12 {
+        let coords = centered.iter().zip(&nu).zip(&mean)
+            .map(|((x, nu), m)| x * theta.cos() + nu * theta.sin() + m)
+            .collect::<Vec<f64>>();
+        let x_new = x.with_coords(&coords);
+        let mut constraints = DynTrie::new();
+        constraints.observe(addr, Arc::new(x_new.clone()));
+        let args = trace.args.clone();
+        match model.try_update(rng, trace.clone(), args, ArgDiff::NoChange, constraints) {
+            Ok((new_trace, _, weight)) if weight - prior.logpdf(&x_new, params.clone()) > log_slice => {
+                return Ok((new_trace, true));
+            }
+            Ok(_) | Err(Error::InvalidParameters(_)) => { }
+            Err(err) => { return Err(err); }
+        }
+        if theta < 0. { theta_min = theta; } else { theta_max = theta; }
+        theta = rng.sample(Uniform::new(theta_min, theta_max));
+    }
+    Ok((trace, false))
+}
+
+/// Alias for `elliptical_slice_sampling`.
+pub fn elliptical_slice<Args: Clone + 'static,Ret: Clone + 'static,V: Differentiable + Clone + Send + Sync + 'static,U: Clone>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    addr: &str,
+    prior: &impl GaussianPrior<V,U>,
+    params: &U
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    elliptical_slice_sampling(rng, model, trace, addr, prior, params)
+}
+
+/// Perform a univariate slice sampling update of the continuous (`f64`) choice at `addr` in the given `trace`, as described in:
+///
+/// > Slice sampling.
+/// > Neal, R. M.
+/// > The Annals of Statistics 31 (2003).
+///
+/// An interval of size `width` is randomly placed around the current value and stepped out (by at most `max_steps_out` steps of `width`)
+/// until both ends are below a random slice of `trace.logjp`, which is evaluated with `GenFn::update`. A new value is then drawn uniformly
+/// from the interval, shrinking it towards the current value until a point within the slice is found, so the update is always accepted.
+/// Values outside the support of the model (eg. a negative scale) are outside of the slice.
+///
+/// The sampler is valid for any `width`, but is most efficient when `width` is on the order of the posterior scale of the choice.
+/// Return `false` (leaving the trace unchanged) only if the interval shrinks to nothing.
+pub fn slice_sampling<Args: Clone + 'static,Ret: Clone + 'static>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    addr: &str,
+    width: f64,
+    max_steps_out: u32
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    assert!(width > 0., "slice_sampling: width must be positive");
+    let x = trace.data.read::<f64>(addr);
+
+    // the log density of `x_new` relative to the current trace, or `None` if the model is undefined at `x_new`
+    let update_to = |rng: &mut _, x_new: f64| {
+        let mut constraints = DynTrie::new();
+        constraints.observe(addr, Arc::new(x_new));
+        let args = trace.args.clone();
+        match model.try_update(rng, trace.clone(), args, ArgDiff::NoChange, constraints) {
+            Ok((new_trace, _, weight)) => Some((new_trace, weight)),
+            Err(Error::InvalidParameters(_)) => None,
+            Err(err) => { panic!("slice_sampling: {err}"); }
+        }
+    };
+    let log_slice = rng.sample(Uniform::new(0_f64, 1_f64)).ln();
+    let in_slice = |rng: &mut _, x_new: f64| update_to(rng, x_new).is_some_and(|(_, weight)| weight > log_slice);
+
+    let mut left = x - width * rng.sample(Uniform::new(0_f64, 1_f64));
+    let mut right = left + width;
+    let mut left_steps = (max_steps_out as f64 * rng.sample(Uniform::new(0_f64, 1_f64))) as u32;
+    let mut right_steps = max_steps_out.saturating_sub(left_steps + 1);
+    while left_steps > 0 && in_slice(rng, left) {
+        left -= width;
+        left_steps -= 1;
+    }
+    while right_steps > 0 && in_slice(rng, right) {
+        right += width;
+        right_steps -= 1;
+    }
+
+    while right - left > 1e-12 * width {
+        let x_new = rng.sample(Uniform::new(left, right));
+        if let Some((new_trace, weight)) = update_to(rng, x_new) {
+            if weight > log_slice {
+                return (new_trace, true);
+            }
+        }
+        if x_new < x { left = x_new; } else { right = x_new; }
+    }
+    (trace, false)
+}
+
+/// Alias for `slice_sampling`.
+pub fn slice_sample<Args: Clone + 'static,Ret: Clone + 'static>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    addr: &str,
+    width: f64,
+    max_steps_out: u32
+) -> (Trace<Args,DynTrie,Ret>, bool) {
+    slice_sampling(rng, model, trace, addr, width, max_steps_out)
+}
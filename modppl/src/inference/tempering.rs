@@ -0,0 +1,167 @@
+use rand::{distributions::Uniform, Rng};
+use crate::{Trace, GenFn, ArgDiff, AddrMap, DynTrie, Error, logsumexp};
+use crate::modeling::dyngenfn::selected_weight;
+use super::resampling::ResamplingScheme;
+
+
+// Smallest increment of `beta` taken by `tempered_smc` before giving up on reaching the target effective sample size.
+const MIN_BETA_INCREMENT: f64 = 1e-6;
+
+/// Performs inference for a `GenFn` via annealed importance sampling (AIS), as described in:
+///
+/// > Annealed Importance Sampling.
+/// > Neal, R. M.
+/// > Statistics and Computing 11 (2001).
+///
+/// Each of the `num_samples` traces starts from the prior (the internal proposal of `model` given the `observations`) and is
+/// annealed through the tempered targets `p(x) p(y|x)^beta` for each `beta` in `betas` (increasing and ending at `1`),
+/// applying `num_mcmc_steps` regenerative Metropolis-Hastings moves over `selection` at each temperature.
+/// The `selection` should cover the latent choices of `model`, not the `observations`.
+///
+/// Returns a tuple of (as in `importance_sampling`):
+/// 1. a vector of the annealed traces.
+/// 2. the log of their normalized weights.
+/// 3. the log marginal likelihood estimate of the `observations` under the `model`.
+#[allow(clippy::too_many_arguments)]
+pub fn annealed_importance_sampling<Args: Clone,Ret: Clone>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    model_args: Args,
+    observations: DynTrie,
+    num_samples: u32,
+    betas: &[f64],
+    selection: &AddrMap,
+    num_mcmc_steps: u32
+) -> (Vec<Trace<Args,DynTrie,Ret>>, Vec<f64>, f64) {
+    assert!(betas.windows(2).all(|pair| pair[0] < pair[1]), "annealed_importance_sampling: betas must be increasing");
+    assert!(betas.last() == Some(&1.), "annealed_importance_sampling: betas must end at 1");
+    let schema = observations.schema();
+    let mut traces = vec![];
+    let mut log_weights = vec![];
+    for _ in 0..num_samples {
+        let (mut trace, _) = model.generate(rng, model_args.clone(), observations.clone());
+        let mut log_weight = 0.;
+        let mut prev_beta = 0.;
+        for &beta in betas {
+            log_weight += (beta - prev_beta) * selected_weight(&trace.data, &schema);
+            for _ in 0..num_mcmc_steps {
+                trace = tempered_regen_mh(rng, model, trace, selection, &schema, beta);
+            }
+            prev_beta = beta;
+        }
+        traces.push(trace);
+        log_weights.push(log_weight);
+    }
+    let log_total_weight = logsumexp(&log_weights);
+    let log_normalized_weights = log_weights.iter().map(|w| w - log_total_weight).collect::<Vec<f64>>();
+    (traces, log_normalized_weights, log_total_weight - (num_samples as f64).ln())
+}
+
+/// Performs inference for a `GenFn` with a sequential Monte Carlo sampler over the tempered targets `p(x) p(y|x)^beta`,
+/// moving `beta` from `0` (the prior) to `1` (the posterior given the `observations`), as described in:
+///
+/// > Sequential Monte Carlo Samplers.
+/// > Del Moral, P., Doucet, A., and Jasra, A.
+/// > Journal of the Royal Statistical Society: Series B 68 (2006).
+///
+/// Each increment of `beta` is chosen adaptively (by bisection) so that the effective sample size of the reweighted
+/// particles is `target_ess * num_particles`, after which the particles are resampled and rejuvenated with `num_mcmc_steps`
+/// regenerative Metropolis-Hastings moves over `selection` (which should cover the latent choices of `model`).
+///
+/// Returns a tuple of:
+/// 1. a vector of `num_particles` traces approximately distributed according to the posterior.
+/// 2. the increasing sequence of `beta`s, ending at `1` (eg. to reuse as a schedule for `annealed_importance_sampling`).
+/// 3. the log marginal likelihood estimate of the `observations` under the `model`.
+///
+/// Returns `Error::DegenerateWeights` if no increment of `beta` of at least `1e-6` keeps the effective sample size
+/// at the target, eg. because the observations have zero likelihood under most of the particles.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn tempered_smc<Args: Clone,Ret: Clone>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    model_args: Args,
+    observations: DynTrie,
+    num_particles: usize,
+    target_ess: f64,
+    selection: &AddrMap,
+    num_mcmc_steps: u32
+) -> Result<(Vec<Trace<Args,DynTrie,Ret>>, Vec<f64>, f64), Error> {
+    assert!(0. < target_ess && target_ess < 1., "tempered_smc: target_ess must be in (0, 1)");
+    let schema = observations.schema();
+    let mut traces = (0..num_particles)
+        .map(|_| model.generate(rng, model_args.clone(), observations.clone()).0)
+        .collect::<Vec<_>>();
+    let mut betas = vec![];
+    let mut beta = 0.;
+    let mut log_ml_estimate = 0.;
+    while beta < 1. {
+        let log_likelihoods = traces.iter().map(|trace| selected_weight(&trace.data, &schema)).collect::<Vec<f64>>();
+        let next_beta = next_temperature(&log_likelihoods, beta, target_ess * num_particles as f64)?;
+        let log_weights = log_likelihoods.iter().map(|ll| (next_beta - beta) * ll).collect::<Vec<f64>>();
+        let log_total_weight = logsumexp(&log_weights);
+        log_ml_estimate += log_total_weight - (num_particles as f64).ln();
+        beta = next_beta;
+        betas.push(beta);
+
+        let weights = log_weights.iter().map(|w| (w - log_total_weight).exp()).collect::<Vec<f64>>();
+        let parents = ResamplingScheme::Systematic.resample(rng, &weights, num_particles);
+        traces = parents.into_iter().map(|i| {
+            let mut trace = traces[i].clone();
+            for _ in 0..num_mcmc_steps {
+                trace = tempered_regen_mh(rng, model, trace, selection, &schema, beta);
+            }
+            trace
+        }).collect();
+    }
+    Ok((traces, betas, log_ml_estimate))
+}
+
+/// The effective sample size of equally weighted particles reweighted by `exp(delta * log_likelihoods)`.
+fn reweighted_ess(log_likelihoods: &[f64], delta: f64) -> f64 {
+    let log_weights = log_likelihoods.iter().map(|ll| delta * ll).collect::<Vec<f64>>();
+    let two_times_log_weights = log_weights.iter().map(|w| 2.0 * w).collect::<Vec<f64>>();
+    (2.0 * logsumexp(&log_weights) - logsumexp(&two_times_log_weights)).exp()
+}
+
+/// The largest `beta` in `(prev_beta, 1]` whose reweighted effective sample size is at least `target_ess`.
+fn next_temperature(log_likelihoods: &[f64], prev_beta: f64, target_ess: f64) -> Result<f64, Error> {
+    if reweighted_ess(log_likelihoods, 1. - prev_beta) >= target_ess {
+        return Ok(1.);
+    }
+    let (mut lo, mut hi) = (0., 1. - prev_beta);
+    for _ in 0..50 {
+        let mid = (lo + hi) / 2.;
+        if reweighted_ess(log_likelihoods, mid) >= target_ess { lo = mid; } else { hi = mid; }
+    }
+    if lo < MIN_BETA_INCREMENT {
+        return Err(Error::DegenerateWeights(format!(
+            "tempered_smc: no increment of beta >= {MIN_BETA_INCREMENT} from {prev_beta} keeps an effective sample size of {target_ess}")));
+    }
+    Ok(prev_beta + lo)
+}
+
+/// Regenerative Metropolis-Hastings targeting `p(x) p(y|x)^beta`, where the observations `y` are the choices in `schema`.
+fn tempered_regen_mh<Args: Clone,Ret: Clone>(
+    rng: &mut impl Rng,
+    model: &impl GenFn<Args,DynTrie,Ret>,
+    trace: Trace<Args,DynTrie,Ret>,
+    selection: &AddrMap,
+    schema: &AddrMap,
+    beta: f64
+) -> Trace<Args,DynTrie,Ret> {
+    let prev_log_likelihood = selected_weight(&trace.data, schema);
+    let prev_trace = trace.clone();
+    let args = trace.args.clone();
+    let (trace, weight) = match model.try_regenerate(rng, trace, args, ArgDiff::NoChange, selection) {
+        Ok(result) => result,
+        Err(Error::InvalidParameters(_)) => { return prev_trace; }
+        Err(err) => { panic!("tempered_regen_mh: {err}"); }
+    };
+    // the regenerate weight includes the full change in log likelihood
+    let alpha = weight - (1. - beta) * (selected_weight(&trace.data, schema) - prev_log_likelihood);
+    if rng.sample(Uniform::new(0_f64, 1_f64)).ln() < alpha {
+        trace
+    } else {
+        prev_trace
+    }
+}
@@ -18,6 +18,9 @@ pub mod prelude;
 /// Definition of the Generative Function Interface (GFI).
 pub mod gfi;
 
+/// Error type returned by the fallible (`try_*`) methods.
+pub mod error;
+
 /// Utilities for parsing addresses (keys used in the `Trie` data structure).
 pub mod address;
 
@@ -47,25 +50,55 @@ pub fn logsumexp(xs: &Vec<f64>) -> f64 {
 // modeling libs
 pub use trie::Trie;
 pub use address::{SplitAddr, AddrMap, normalize_addr};
-pub use gfi::{Trace, GenFn, ArgDiff};
+pub use gfi::{Trace, GenFn, ArgDiff, Differentiable, Merge};
+pub use error::Error;
 pub use modeling::dists::{
     u01,Distribution,
+    ln_gamma,ln_beta,ln_factorial,
     bernoulli,
     uniform_continuous,
     uniform,
     uniform_discrete,
     categorical,
+    categorical_logits,
+    AliasTable,
     normal,
     mvnormal,
     geometric,
     poisson,
     gamma,
-    beta
+    beta,
+    exponential,
+    laplace,
+    cauchy,
+    half_cauchy,
+    student_t,
+    lognormal,
+    inv_gamma,
+    half_normal,
+    binomial,
+    neg_binomial,
+    beta_binomial,
+    hypergeometric,
+    multinomial,
+    dirichlet,
+    wishart,
+    inv_wishart,
+    lkj_cholesky
 };
 pub use modeling::dyngenfn::{DynTrie,DynTrace,DynGenFn,DynGenFnHandler};
 pub use modeling::dynunfold::{DynUnfold,DynParticles};
 
 // inference libs
-pub use inference::{importance_sampling, importance_resampling};
-pub use inference::{metropolis_hastings, mh, regenerative_metropolis_hastings, regen_mh};
-pub use inference::ParticleSystem;
\ No newline at end of file
+pub use inference::{importance_sampling, importance_resampling, importance_sampling_with_proposal, importance_resampling_with_proposal};
+#[cfg(feature = "parallel")]
+pub use inference::{par_importance_sampling, par_importance_sampling_with_proposal, par_importance_resampling, par_importance_resampling_with_proposal};
+pub use inference::{enumerate, ChoiceGrid};
+pub use inference::{metropolis_hastings, mh, regenerative_metropolis_hastings, regen_mh, metropolis_adjusted_langevin, mala, involutive_metropolis_hastings, try_involutive_metropolis_hastings, involutive_mh, estimate_involution_log_abs_det};
+pub use inference::{enumerative_gibbs, gibbs};
+pub use inference::{elliptical_slice_sampling, try_elliptical_slice_sampling, elliptical_slice, slice_sampling, slice_sample};
+pub use inference::{hamiltonian_monte_carlo, hmc};
+pub use inference::NoUTurnSampler;
+pub use inference::{ParticleSystem, ResamplingScheme};
+pub use inference::{estimate_log_marginal_likelihood, particle_marginal_metropolis_hastings, pmmh};
+pub use inference::{annealed_importance_sampling, tempered_smc};
\ No newline at end of file
@@ -1,5 +1,6 @@
-use rand::rngs::ThreadRng;
+use rand::Rng;
 use super::{Distribution,u01};
+use crate::Error;
 
 
 /// Bernoulli distribution type
@@ -13,7 +14,17 @@ impl Distribution<bool,f64> for Bernoulli {
         (if *a { p } else { 1. - p }).ln()
     }
 
-    fn random(&self, rng: &mut ThreadRng, p: f64) -> bool {
+    fn random(&self, rng: &mut impl Rng, p: f64) -> bool {
         p > u01(rng)
     }
+
+    fn check_params(&self, p: &f64) -> Result<(), Error> {
+        if (0. ..=1.).contains(p) { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("bernoulli requires 0 <= p <= 1, got p = {p}")))
+        }
+    }
+
+    fn support(&self, p: &f64) -> Option<Vec<bool>> {
+        Some([false, true].into_iter().filter(|&a| if a { *p > 0. } else { *p < 1. }).collect())
+    }
 }
\ No newline at end of file
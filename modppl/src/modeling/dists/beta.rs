@@ -1,6 +1,7 @@
-use rand::rngs::ThreadRng;
-use super::Distribution;
-use compute::functions::gamma;
+use rand::Rng;
+use super::{Distribution,ln_beta,xlogy};
+use crate::Error;
+use compute::functions::digamma;
 use rand_distr::{
     Distribution as _,
     Beta as BetaSampler
@@ -16,13 +17,34 @@ pub const beta: Beta = Beta { };
 impl Distribution<f64,(f64,f64)> for Beta {
     fn logpdf(&self, x: &f64, params: (f64,f64)) -> f64 {
         let (a,b) = params;
-        let beta_f = gamma(a + b)/(gamma(a)*gamma(b));
-        (beta_f * x.powf(a-1.)*(1.-x).powf(b-1.)).ln()
+        if *x < 0. || *x > 1. {
+            return f64::NEG_INFINITY;
+        }
+        xlogy(a - 1., *x) + xlogy(b - 1., 1. - x) - ln_beta(a, b)
     }
 
-    fn random(&self, rng: &mut ThreadRng, params: (f64,f64)) -> f64 {
+    fn random(&self, rng: &mut impl Rng, params: (f64,f64)) -> f64 {
         let (a, b) = params;
         let beta_sampler = BetaSampler::new(a, b).ok().unwrap();
         beta_sampler.sample(rng)
     }
+
+    fn logpdf_grad(&self, x: &f64, params: (f64,f64)) -> Option<(f64,(f64,f64))> {
+        let (a, b) = params;
+        if *x <= 0. || *x >= 1. {
+            return None;
+        }
+        let digamma_ab = digamma(a + b);
+        let d_x = (a - 1.)/x - (b - 1.)/(1. - x);
+        let d_a = x.ln() - digamma(a) + digamma_ab;
+        let d_b = (1. - x).ln() - digamma(b) + digamma_ab;
+        Some((d_x, (d_a, d_b)))
+    }
+
+    fn check_params(&self, params: &(f64,f64)) -> Result<(), Error> {
+        let (a, b) = *params;
+        if a > 0. && b > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("beta requires a > 0 and b > 0, got (a, b) = ({a}, {b})")))
+        }
+    }
 }
\ No newline at end of file
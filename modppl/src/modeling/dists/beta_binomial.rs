@@ -0,0 +1,38 @@
+use rand::Rng;
+use super::{Distribution,ln_beta,ln_choose,beta,binomial};
+use crate::Error;
+
+
+/// Beta-binomial distribution type
+pub struct BetaBinomial { }
+
+/// Instantiation of the beta-binomial distribution, the binomial distribution with `n` trials and a `beta(a, b)` success probability
+pub const beta_binomial: BetaBinomial = BetaBinomial { };
+
+impl Distribution<i64,(i64,f64,f64)> for BetaBinomial {
+    fn logpdf(&self, k: &i64, params: (i64,f64,f64)) -> f64 {
+        let (n, a, b) = params;
+        if *k < 0 || *k > n {
+            return f64::NEG_INFINITY;
+        }
+        ln_choose(n, *k) + ln_beta(*k as f64 + a, (n - k) as f64 + b) - ln_beta(a, b)
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (i64,f64,f64)) -> i64 {
+        let (n, a, b) = params;
+        let p = beta.random(rng, (a, b));
+        binomial.random(rng, (n, p))
+    }
+
+    fn check_params(&self, params: &(i64,f64,f64)) -> Result<(), Error> {
+        let (n, a, b) = *params;
+        if n >= 0 && a > 0. && b > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("beta_binomial requires n >= 0, a > 0 and b > 0, got (n, a, b) = ({n}, {a}, {b})")))
+        }
+    }
+
+    fn support(&self, params: &(i64,f64,f64)) -> Option<Vec<i64>> {
+        let (n, _, _) = *params;
+        Some((0..=n).collect())
+    }
+}
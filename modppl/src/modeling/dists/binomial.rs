@@ -0,0 +1,42 @@
+use rand::Rng;
+use super::{Distribution,ln_choose,xlogy};
+use crate::Error;
+use rand_distr::{
+    Distribution as _,
+    Binomial as BinomialSampler
+};
+
+
+/// Binomial distribution type
+pub struct Binomial { }
+
+/// Instantiation of the binomial distribution, the number of successes in `n` trials with success probability `p`
+pub const binomial: Binomial = Binomial { };
+
+impl Distribution<i64,(i64,f64)> for Binomial {
+    fn logpdf(&self, k: &i64, params: (i64,f64)) -> f64 {
+        let (n, p) = params;
+        if *k < 0 || *k > n {
+            return f64::NEG_INFINITY;
+        }
+        ln_choose(n, *k) + xlogy(*k as f64, p) + xlogy((n - k) as f64, 1. - p)
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (i64,f64)) -> i64 {
+        let (n, p) = params;
+        let binomial_sampler = BinomialSampler::new(n as u64, p).ok().unwrap();
+        binomial_sampler.sample(rng) as i64
+    }
+
+    fn check_params(&self, params: &(i64,f64)) -> Result<(), Error> {
+        let (n, p) = *params;
+        if n >= 0 && (0. ..=1.).contains(&p) { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("binomial requires n >= 0 and 0 <= p <= 1, got (n, p) = ({n}, {p})")))
+        }
+    }
+
+    fn support(&self, params: &(i64,f64)) -> Option<Vec<i64>> {
+        let (n, p) = *params;
+        Some((0..=n).filter(|&k| (p > 0. || k == 0) && (p < 1. || k == n)).collect())
+    }
+}
@@ -1,6 +1,7 @@
-use rand::rngs::ThreadRng;
+use rand::{distributions::Uniform, Rng};
 use approx;
 use super::{Distribution,u01};
+use crate::{Error,logsumexp};
 
 
 /// Categorical distribution type
@@ -19,7 +20,7 @@ impl Distribution<i64,Vec<f64>> for Categorical {
         }
     }
 
-    fn random(&self, rng: &mut ThreadRng, probs: Vec<f64>) -> i64 {
+    fn random(&self, rng: &mut impl Rng, probs: Vec<f64>) -> i64 {
         approx::assert_abs_diff_eq!(probs.iter().sum::<f64>(), 1.0, epsilon = 1e-8);
         let u = u01(rng);
         let mut t = 0.;
@@ -30,4 +31,125 @@ impl Distribution<i64,Vec<f64>> for Categorical {
         }
         return x - 1;
     }
-}
\ No newline at end of file
+
+    fn check_params(&self, probs: &Vec<f64>) -> Result<(), Error> {
+        if probs.iter().any(|p| p.is_nan() || *p < 0.) {
+            Err(Error::InvalidParameters(format!("categorical requires non-negative probs, got {probs:?}")))
+        } else if (probs.iter().sum::<f64>() - 1.).abs() > 1e-8 {
+            Err(Error::InvalidParameters(format!("categorical requires probs that sum to 1, got {probs:?}")))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn support(&self, probs: &Vec<f64>) -> Option<Vec<i64>> {
+        Some((0..probs.len() as i64).filter(|&i| probs[i as usize] > 0.).collect())
+    }
+}
+
+/// Categorical distribution type, parameterized by unnormalized log weights
+pub struct CategoricalLogits { }
+
+/// Instantiation of the categorical distribution over `0..logits.len()` with probabilities proportional to `exp(logits)`
+///
+/// Each draw normalizes the `logits` in `O(n)` and then binary searches their cumulative sum.
+/// Use an `AliasTable` to draw repeatedly from the same `logits`.
+pub const categorical_logits: CategoricalLogits = CategoricalLogits { };
+
+impl Distribution<i64,Vec<f64>> for CategoricalLogits {
+    fn logpdf(&self, x: &i64, logits: Vec<f64>) -> f64 {
+        if *x < 0 || *x >= logits.len() as i64 {
+            return f64::NEG_INFINITY;
+        }
+        logits[*x as usize] - logsumexp(&logits)
+    }
+
+    fn random(&self, rng: &mut impl Rng, logits: Vec<f64>) -> i64 {
+        let log_total = logsumexp(&logits);
+        let cumsum = logits.iter()
+            .scan(0., |t, logit| { *t += (logit - log_total).exp(); Some(*t) })
+            .collect::<Vec<f64>>();
+        // scale by the rounded total so that `u` always falls below the last cumulative weight
+        let u = u01(rng) * cumsum[cumsum.len() - 1];
+        cumsum.partition_point(|t| *t <= u) as i64
+    }
+
+    fn check_params(&self, logits: &Vec<f64>) -> Result<(), Error> {
+        if logits.iter().any(|l| l.is_nan() || *l == f64::INFINITY) {
+            Err(Error::InvalidParameters(format!("categorical_logits requires logits that are finite or -inf, got {logits:?}")))
+        } else if logits.iter().all(|l| *l == f64::NEG_INFINITY) {
+            Err(Error::InvalidParameters(format!("categorical_logits requires at least one finite logit, got {logits:?}")))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn support(&self, logits: &Vec<f64>) -> Option<Vec<i64>> {
+        Some((0..logits.len() as i64).filter(|&i| logits[i as usize] > f64::NEG_INFINITY).collect())
+    }
+}
+
+/// Pre-built sampler for repeated `O(1)` draws of an index with probability proportional to fixed weights,
+/// using the alias method as described in:
+///
+/// > A linear algorithm for generating random numbers with a given distribution.
+/// > Vose, M. D.
+/// > IEEE Transactions on Software Engineering 17 (1991).
+///
+/// Building the table takes `O(n)` time.
+#[derive(Debug,Clone)]
+pub struct AliasTable {
+    probs: Vec<f64>,
+    aliases: Vec<usize>
+}
+
+impl AliasTable {
+    /// Build a table from non-negative (unnormalized) `weights`, at least one of which is positive.
+    pub fn new(weights: &[f64]) -> Self {
+        assert!(weights.iter().all(|w| w.is_finite() && *w >= 0.), "AliasTable: weights must be finite and non-negative, got {weights:?}");
+        let total = weights.iter().sum::<f64>();
+        assert!(total > 0., "AliasTable: at least one weight must be positive");
+
+        // split the scaled weights into columns of height 1, each shared by at most two indices
+        let n = weights.len();
+        let mut probs = weights.iter().map(|w| w * n as f64 / total).collect::<Vec<f64>>();
+        let mut aliases = (0..n).collect::<Vec<usize>>();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) = (0..n).partition(|&i| probs[i] < 1.);
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            aliases[s] = l;
+            probs[l] -= 1. - probs[s];
+            if probs[l] < 1. {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // whatever is left over is only short of a full column by rounding
+        for i in small.into_iter().chain(large) {
+            probs[i] = 1.;
+        }
+        AliasTable { probs, aliases }
+    }
+
+    /// Build a table from unnormalized `log_weights`, at least one of which is finite.
+    pub fn from_log_weights(log_weights: &[f64]) -> Self {
+        let log_total = logsumexp(&log_weights.to_vec());
+        AliasTable::new(&log_weights.iter().map(|w| (w - log_total).exp()).collect::<Vec<f64>>())
+    }
+
+    /// Sample an index with probability proportional to its weight.
+    pub fn sample(&self, rng: &mut impl Rng) -> usize {
+        let i = rng.sample(Uniform::new(0, self.probs.len()));
+        if u01(rng) < self.probs[i] { i } else { self.aliases[i] }
+    }
+
+    /// Return the number of weights in the table.
+    pub fn len(&self) -> usize {
+        self.probs.len()
+    }
+
+    /// Return `true` if the table has no weights.
+    pub fn is_empty(&self) -> bool {
+        self.probs.is_empty()
+    }
+}
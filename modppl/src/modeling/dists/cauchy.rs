@@ -0,0 +1,76 @@
+use rand::Rng;
+use super::Distribution;
+use crate::Error;
+use std::f64::consts::PI;
+use rand_distr::{
+    Distribution as _,
+    Cauchy as CauchySampler
+};
+
+
+/// Cauchy distribution type
+pub struct Cauchy { }
+
+/// Instantiation of the Cauchy distribution
+pub const cauchy: Cauchy = Cauchy { };
+
+impl Distribution<f64,(f64,f64)> for Cauchy {
+    fn logpdf(&self, x: &f64, params: (f64,f64)) -> f64 {
+        let (loc, scale) = params;
+        let z = (x - loc) / scale;
+        -(z*z).ln_1p() - (PI*scale).ln()
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (f64,f64)) -> f64 {
+        let (loc, scale) = params;
+        let cauchy_sampler = CauchySampler::new(loc, scale).ok().unwrap();
+        cauchy_sampler.sample(rng)
+    }
+
+    fn logpdf_grad(&self, x: &f64, params: (f64,f64)) -> Option<(f64,(f64,f64))> {
+        let (loc, scale) = params;
+        let z = (x - loc) / scale;
+        let d_x = -2.*z/(scale*(1. + z*z));
+        Some((d_x, (-d_x, (z*z - 1.)/(scale*(1. + z*z)))))
+    }
+
+    fn check_params(&self, params: &(f64,f64)) -> Result<(), Error> {
+        let (_, scale) = *params;
+        if scale > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("cauchy requires scale > 0, got scale = {scale}")))
+        }
+    }
+}
+
+/// Half-Cauchy distribution type
+pub struct HalfCauchy { }
+
+/// Instantiation of the half-Cauchy distribution, the Cauchy distribution centered at zero and folded onto `x >= 0`
+pub const half_cauchy: HalfCauchy = HalfCauchy { };
+
+impl Distribution<f64,f64> for HalfCauchy {
+    fn logpdf(&self, x: &f64, scale: f64) -> f64 {
+        if *x < 0. {
+            return f64::NEG_INFINITY;
+        }
+        cauchy.logpdf(x, (0., scale)) + 2_f64.ln()
+    }
+
+    fn random(&self, rng: &mut impl Rng, scale: f64) -> f64 {
+        cauchy.random(rng, (0., scale)).abs()
+    }
+
+    fn logpdf_grad(&self, x: &f64, scale: f64) -> Option<(f64,f64)> {
+        if *x < 0. {
+            return None;
+        }
+        let (d_x, (_, d_scale)) = cauchy.logpdf_grad(x, (0., scale))?;
+        Some((d_x, d_scale))
+    }
+
+    fn check_params(&self, scale: &f64) -> Result<(), Error> {
+        if *scale > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("half_cauchy requires scale > 0, got scale = {scale}")))
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use rand::Rng;
+use super::{Distribution,ln_gamma,xlogy,gamma};
+use crate::Error;
+use compute::functions::digamma;
+use nalgebra::DVector;
+
+
+/// Dirichlet distribution type
+pub struct Dirichlet { }
+
+/// Instantiation of the Dirichlet distribution over the probability simplex, with concentrations `alpha`
+pub const dirichlet: Dirichlet = Dirichlet { };
+
+impl Distribution<DVector<f64>,DVector<f64>> for Dirichlet {
+    fn logpdf(&self, x: &DVector<f64>, alpha: DVector<f64>) -> f64 {
+        if x.len() != alpha.len() || x.iter().any(|v| *v < 0.) || (x.sum() - 1.).abs() > 1e-8 {
+            return f64::NEG_INFINITY;
+        }
+        let ln_beta = alpha.iter().map(|a| ln_gamma(*a)).sum::<f64>() - ln_gamma(alpha.sum());
+        x.iter().zip(alpha.iter()).map(|(x, a)| xlogy(a - 1., *x)).sum::<f64>() - ln_beta
+    }
+
+    fn random(&self, rng: &mut impl Rng, alpha: DVector<f64>) -> DVector<f64> {
+        // normalized gamma variables
+        let y = alpha.map(|a| gamma.random(rng, (a, 1.)));
+        let total = y.sum();
+        y / total
+    }
+
+    fn logpdf_grad(&self, x: &DVector<f64>, alpha: DVector<f64>) -> Option<(DVector<f64>,DVector<f64>)> {
+        if x.iter().any(|x| *x <= 0.) {
+            return None;
+        }
+        let digamma_total = digamma(alpha.sum());
+        let d_x = alpha.zip_map(x, |a, x| (a - 1.)/x);
+        let d_alpha = alpha.zip_map(x, |a, x| x.ln() - digamma(a) + digamma_total);
+        Some((d_x, d_alpha))
+    }
+
+    fn check_params(&self, alpha: &DVector<f64>) -> Result<(), Error> {
+        if !alpha.is_empty() && alpha.iter().all(|a| *a > 0.) { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("dirichlet requires a non-empty alpha > 0, got alpha = {:?}", alpha.as_slice())))
+        }
+    }
+}
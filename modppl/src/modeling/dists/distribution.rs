@@ -1,11 +1,78 @@
-use rand::{Rng,rngs::ThreadRng};
+use rand::Rng;
+use std::f64::consts::PI;
+use crate::Error;
 
 
 /// Sample a random variable uniformly in the interval [0., 1.].
-pub fn u01(rng: &mut ThreadRng) -> f64 {
+pub fn u01(rng: &mut impl Rng) -> f64 {
     rng.sample(rand::distributions::Uniform::new(0., 1.))
 }
 
+// Lanczos approximation coefficients (g = 7, n = 9)
+const LANCZOS_G: f64 = 7.;
+const LANCZOS_COEFS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7
+];
+
+/// Return `ln|Γ(x)|`, computed in log space so that it stays finite where `Γ(x)` overflows.
+pub fn ln_gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        // reflection formula
+        (PI / (PI * x).sin().abs()).ln() - ln_gamma(1. - x)
+    } else {
+        let (t, series) = lanczos(x);
+        0.5 * (2. * PI).ln() + (x - 0.5) * t.ln() - t + series.ln()
+    }
+}
+
+/// Return `ln B(a, b) = ln Γ(a) + ln Γ(b) - ln Γ(a + b)`.
+pub fn ln_beta(a: f64, b: f64) -> f64 {
+    let (a, b) = if a < b { (a, b) } else { (b, a) };
+    if b < 0.5 {
+        return ln_gamma(a) + ln_gamma(b) - ln_gamma(a + b);
+    } else if a < 0.5 {
+        // B(a, b) = B(a + 1, b) (a + b) / a
+        return ln_beta(a + 1., b) + ((a + b) / a).ln();
+    }
+    // cancel the large terms of `ln Γ(b) - ln Γ(a + b)` analytically, which matters when `b >> a`
+    let (_, series_b) = lanczos(b);
+    let (t_ab, series_ab) = lanczos(a + b);
+    ln_gamma(a) + (b - 0.5) * (-a / t_ab).ln_1p() - a * t_ab.ln() + a + (series_b / series_ab).ln()
+}
+
+/// Return the shifted argument `t` and the series of the Lanczos approximation of `Γ(x)` for `x >= 0.5`.
+fn lanczos(x: f64) -> (f64, f64) {
+    let x = x - 1.;
+    let series = LANCZOS_COEFS[1..].iter().enumerate()
+        .fold(LANCZOS_COEFS[0], |acc, (i, c)| acc + c / (x + (i + 1) as f64));
+    (x + LANCZOS_G + 0.5, series)
+}
+
+/// Return `ln(k!)` for `k >= 0`.
+pub fn ln_factorial(k: i64) -> f64 {
+    debug_assert!(k >= 0);
+    ln_gamma(k as f64 + 1.)
+}
+
+/// Return the log binomial coefficient `ln C(n, k)` for `0 <= k <= n`.
+pub(crate) fn ln_choose(n: i64, k: i64) -> f64 {
+    debug_assert!(0 <= k && k <= n);
+    -((n + 1) as f64).ln() - ln_beta((k + 1) as f64, (n - k + 1) as f64)
+}
+
+/// Return `a * ln(x)`, taking `0 * ln(0) = 0`.
+pub(crate) fn xlogy(a: f64, x: f64) -> f64 {
+    if a == 0. { 0. } else { a * x.ln() }
+}
+
 /// Trait for sampling distributions with an analytically calculable probability density function (pdf).
 pub trait Distribution<T,U> {
 
@@ -13,6 +80,23 @@ pub trait Distribution<T,U> {
     fn logpdf(&self, x: &T, params: U) -> f64;
 
     /// Sample a random value `x ~ p(. ; params)`.
-    fn random(&self, rng: &mut ThreadRng, params: U) -> T;
+    fn random(&self, rng: &mut impl Rng, params: U) -> T;
+
+    /// Return the gradients of `logpdf` with respect to `x` and to each of the `params`,
+    /// or `None` if the distribution isn't differentiable (or `x` is outside its support).
+    fn logpdf_grad(&self, _x: &T, _params: U) -> Option<(T, U)> {
+        None
+    }
+
+    /// Return `Error::InvalidParameters` if `params` are outside the domain of the distribution.
+    fn check_params(&self, _params: &U) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Return every value with non-zero probability under `params` if there are finitely many,
+    /// otherwise `None` (eg. for continuous or unbounded distributions).
+    fn support(&self, _params: &U) -> Option<Vec<T>> {
+        None
+    }
 
 }
\ No newline at end of file
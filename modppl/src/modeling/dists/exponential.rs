@@ -0,0 +1,41 @@
+use rand::Rng;
+use super::Distribution;
+use crate::Error;
+use rand_distr::{
+    Distribution as _,
+    Exp as ExpSampler
+};
+
+
+/// Exponential distribution type
+pub struct Exponential { }
+
+/// Instantiation of the exponential distribution
+pub const exponential: Exponential = Exponential { };
+
+impl Distribution<f64,f64> for Exponential {
+    fn logpdf(&self, x: &f64, rate: f64) -> f64 {
+        if *x < 0. {
+            return f64::NEG_INFINITY;
+        }
+        rate.ln() - rate*x
+    }
+
+    fn random(&self, rng: &mut impl Rng, rate: f64) -> f64 {
+        let exp_sampler = ExpSampler::new(rate).ok().unwrap();
+        exp_sampler.sample(rng)
+    }
+
+    fn logpdf_grad(&self, x: &f64, rate: f64) -> Option<(f64,f64)> {
+        if *x < 0. {
+            return None;
+        }
+        Some((-rate, 1./rate - x))
+    }
+
+    fn check_params(&self, rate: &f64) -> Result<(), Error> {
+        if *rate > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("exponential requires rate > 0, got rate = {rate}")))
+        }
+    }
+}
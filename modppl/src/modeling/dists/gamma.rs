@@ -1,6 +1,7 @@
-use rand::rngs::ThreadRng;
-use super::Distribution;
-use compute::functions::gamma as gamma_f;
+use rand::Rng;
+use super::{Distribution,ln_gamma,xlogy};
+use crate::Error;
+use compute::functions::digamma;
 use rand_distr::{
     Distribution as _,
     Gamma as GammaSampler
@@ -16,12 +17,33 @@ pub const gamma: Gamma = Gamma { };
 impl Distribution<f64,(f64,f64)> for Gamma {
     fn logpdf(&self, x: &f64, params: (f64,f64)) -> f64 {
         let (a, b) = params;
-        (a-1.)*x.ln() - x/b - gamma_f(a).ln() - a*b.ln() 
+        if *x < 0. {
+            return f64::NEG_INFINITY;
+        }
+        xlogy(a - 1., *x) - x/b - ln_gamma(a) - a*b.ln()
     }
 
-    fn random(&self, rng: &mut ThreadRng, params: (f64,f64)) -> f64 {
+    fn random(&self, rng: &mut impl Rng, params: (f64,f64)) -> f64 {
         let (a, b) = params;
         let gamma_sampler = GammaSampler::new(a, b).ok().unwrap();
         gamma_sampler.sample(rng)
     }
+
+    fn logpdf_grad(&self, x: &f64, params: (f64,f64)) -> Option<(f64,(f64,f64))> {
+        let (a, b) = params;
+        if *x <= 0. {
+            return None;
+        }
+        let d_x = (a - 1.)/x - 1./b;
+        let d_a = x.ln() - digamma(a) - b.ln();
+        let d_b = x/(b*b) - a/b;
+        Some((d_x, (d_a, d_b)))
+    }
+
+    fn check_params(&self, params: &(f64,f64)) -> Result<(), Error> {
+        let (a, b) = *params;
+        if a > 0. && b > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("gamma requires shape > 0 and scale > 0, got (shape, scale) = ({a}, {b})")))
+        }
+    }
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
-use rand::rngs::ThreadRng;
+use rand::Rng;
 use super::Distribution;
+use crate::Error;
 use rand_distr::{
     Distribution as _,
     Geometric as GeometricSampler
@@ -15,12 +16,21 @@ pub const geometric: Geometric = Geometric { };
 impl Distribution<i64,f64> for Geometric {
     fn logpdf(&self, k: &i64, p: f64) -> f64 {
         debug_assert!(0. < p && p < 1.);
-        ((1. - p).powf(*k as f64)*p).ln()
+        if *k < 0 {
+            return f64::NEG_INFINITY;
+        }
+        (*k as f64)*(-p).ln_1p() + p.ln()
     }
 
-    fn random(&self, rng: &mut ThreadRng, p: f64) -> i64 {
+    fn random(&self, rng: &mut impl Rng, p: f64) -> i64 {
         debug_assert!(0. < p && p < 1.);
         let geometric_sampler = GeometricSampler::new(p).ok().unwrap();
         geometric_sampler.sample(rng) as i64
     }
+
+    fn check_params(&self, p: &f64) -> Result<(), Error> {
+        if 0. < *p && *p < 1. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("geometric requires 0 < p < 1, got p = {p}")))
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,45 @@
+use rand::Rng;
+use super::{Distribution,ln_choose};
+use crate::Error;
+use rand_distr::{
+    Distribution as _,
+    Hypergeometric as HypergeometricSampler
+};
+
+
+/// Hypergeometric distribution type
+pub struct Hypergeometric { }
+
+/// Instantiation of the hypergeometric distribution, the number of successes in `n` draws without replacement
+/// from a population of size `total` with `successes` successes, with parameters `(total, successes, n)`
+pub const hypergeometric: Hypergeometric = Hypergeometric { };
+
+impl Distribution<i64,(i64,i64,i64)> for Hypergeometric {
+    fn logpdf(&self, k: &i64, params: (i64,i64,i64)) -> f64 {
+        let (total, successes, n) = params;
+        if *k < 0.max(n + successes - total) || *k > n.min(successes) {
+            return f64::NEG_INFINITY;
+        }
+        ln_choose(successes, *k) + ln_choose(total - successes, n - k) - ln_choose(total, n)
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (i64,i64,i64)) -> i64 {
+        let (total, successes, n) = params;
+        let hypergeometric_sampler = HypergeometricSampler::new(total as u64, successes as u64, n as u64).ok().unwrap();
+        hypergeometric_sampler.sample(rng) as i64
+    }
+
+    fn check_params(&self, params: &(i64,i64,i64)) -> Result<(), Error> {
+        let (total, successes, n) = *params;
+        if 0 <= successes && successes <= total && 0 <= n && n <= total { Ok(()) } else {
+            Err(Error::InvalidParameters(format!(
+                "hypergeometric requires 0 <= successes <= total and 0 <= n <= total, got (total, successes, n) = ({total}, {successes}, {n})"
+            )))
+        }
+    }
+
+    fn support(&self, params: &(i64,i64,i64)) -> Option<Vec<i64>> {
+        let (total, successes, n) = *params;
+        Some((0.max(n + successes - total)..=n.min(successes)).collect())
+    }
+}
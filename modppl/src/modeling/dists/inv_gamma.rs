@@ -0,0 +1,49 @@
+use rand::Rng;
+use super::{Distribution,ln_gamma};
+use crate::Error;
+use compute::functions::digamma;
+use rand_distr::{
+    Distribution as _,
+    Gamma as GammaSampler
+};
+
+
+/// Inverse gamma distribution type
+pub struct InvGamma { }
+
+/// Instantiation of the inverse gamma distribution, with parameters `(shape, scale)`
+pub const inv_gamma: InvGamma = InvGamma { };
+
+impl Distribution<f64,(f64,f64)> for InvGamma {
+    fn logpdf(&self, x: &f64, params: (f64,f64)) -> f64 {
+        let (a, b) = params;
+        if *x <= 0. {
+            return f64::NEG_INFINITY;
+        }
+        a*b.ln() - ln_gamma(a) - (a + 1.)*x.ln() - b/x
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (f64,f64)) -> f64 {
+        let (a, b) = params;
+        let gamma_sampler = GammaSampler::new(a, 1.).ok().unwrap();
+        b / gamma_sampler.sample(rng)
+    }
+
+    fn logpdf_grad(&self, x: &f64, params: (f64,f64)) -> Option<(f64,(f64,f64))> {
+        let (a, b) = params;
+        if *x <= 0. {
+            return None;
+        }
+        let d_x = (b/x - a - 1.)/x;
+        let d_a = b.ln() - digamma(a) - x.ln();
+        let d_b = a/b - 1./x;
+        Some((d_x, (d_a, d_b)))
+    }
+
+    fn check_params(&self, params: &(f64,f64)) -> Result<(), Error> {
+        let (a, b) = *params;
+        if a > 0. && b > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("inv_gamma requires shape > 0 and scale > 0, got (shape, scale) = ({a}, {b})")))
+        }
+    }
+}
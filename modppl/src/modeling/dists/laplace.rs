@@ -0,0 +1,38 @@
+use rand::Rng;
+use rand::distributions::Open01;
+use super::Distribution;
+use crate::Error;
+
+
+/// Laplace distribution type
+pub struct Laplace { }
+
+/// Instantiation of the Laplace distribution
+pub const laplace: Laplace = Laplace { };
+
+impl Distribution<f64,(f64,f64)> for Laplace {
+    fn logpdf(&self, x: &f64, params: (f64,f64)) -> f64 {
+        let (loc, scale) = params;
+        -(x - loc).abs()/scale - (2.*scale).ln()
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (f64,f64)) -> f64 {
+        let (loc, scale) = params;
+        // inverse cdf, excluding u = ±0.5 where it diverges
+        let u = rng.sample::<f64,_>(Open01) - 0.5;
+        loc - scale * u.signum() * (-2. * u.abs()).ln_1p()
+    }
+
+    fn logpdf_grad(&self, x: &f64, params: (f64,f64)) -> Option<(f64,(f64,f64))> {
+        let (loc, scale) = params;
+        let s = (x - loc).signum();
+        Some((-s/scale, (s/scale, ((x - loc).abs()/scale - 1.)/scale)))
+    }
+
+    fn check_params(&self, params: &(f64,f64)) -> Result<(), Error> {
+        let (_, scale) = *params;
+        if scale > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("laplace requires scale > 0, got scale = {scale}")))
+        }
+    }
+}
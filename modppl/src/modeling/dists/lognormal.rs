@@ -0,0 +1,38 @@
+use rand::Rng;
+use super::{Distribution,normal};
+use crate::Error;
+
+
+/// Log-normal distribution type
+pub struct LogNormal { }
+
+/// Instantiation of the log-normal distribution, whose log is Gaussian with parameters `(mu, std)`
+pub const lognormal: LogNormal = LogNormal { };
+
+impl Distribution<f64,(f64,f64)> for LogNormal {
+    fn logpdf(&self, x: &f64, params: (f64,f64)) -> f64 {
+        if *x <= 0. {
+            return f64::NEG_INFINITY;
+        }
+        normal.logpdf(&x.ln(), params) - x.ln()
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (f64,f64)) -> f64 {
+        normal.random(rng, params).exp()
+    }
+
+    fn logpdf_grad(&self, x: &f64, params: (f64,f64)) -> Option<(f64,(f64,f64))> {
+        if *x <= 0. {
+            return None;
+        }
+        let (d_log_x, d_params) = normal.logpdf_grad(&x.ln(), params)?;
+        Some(((d_log_x - 1.)/x, d_params))
+    }
+
+    fn check_params(&self, params: &(f64,f64)) -> Result<(), Error> {
+        let (_, std) = *params;
+        if std > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("lognormal requires std > 0, got std = {std}")))
+        }
+    }
+}
@@ -9,9 +9,24 @@ mod poisson;
 mod geometric;
 mod gamma;
 mod beta;
+mod exponential;
+mod laplace;
+mod cauchy;
+mod student_t;
+mod lognormal;
+mod inv_gamma;
+mod binomial;
+mod neg_binomial;
+mod beta_binomial;
+mod hypergeometric;
+mod multinomial;
+mod dirichlet;
+mod wishart;
+mod lkj;
 
 
-pub use self::distribution::{u01,Distribution};
+pub use self::distribution::{u01,Distribution,ln_gamma,ln_beta,ln_factorial};
+pub(crate) use self::distribution::{xlogy,ln_choose};
 pub use {
     self::bernoulli::*,
     self::uniform::*,
@@ -21,5 +36,19 @@ pub use {
     self::geometric::*,
     self::poisson::*,
     self::beta::*,
-    self::gamma::*
+    self::gamma::*,
+    self::exponential::*,
+    self::laplace::*,
+    self::cauchy::*,
+    self::student_t::*,
+    self::lognormal::*,
+    self::inv_gamma::*,
+    self::binomial::*,
+    self::neg_binomial::*,
+    self::beta_binomial::*,
+    self::hypergeometric::*,
+    self::multinomial::*,
+    self::dirichlet::*,
+    self::wishart::*,
+    self::lkj::*
 };
\ No newline at end of file
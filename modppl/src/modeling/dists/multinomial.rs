@@ -0,0 +1,77 @@
+use rand::Rng;
+use super::{Distribution,ln_factorial,xlogy,binomial};
+use crate::Error;
+
+
+/// Multinomial distribution type
+pub struct Multinomial { }
+
+/// Instantiation of the multinomial distribution, the counts of each category in `n` independent draws from `categorical(probs)`
+pub const multinomial: Multinomial = Multinomial { };
+
+impl Distribution<Vec<i64>,(i64,Vec<f64>)> for Multinomial {
+    fn logpdf(&self, counts: &Vec<i64>, params: (i64,Vec<f64>)) -> f64 {
+        let (n, probs) = params;
+        if counts.len() != probs.len() || counts.iter().any(|k| *k < 0) || counts.iter().sum::<i64>() != n {
+            return f64::NEG_INFINITY;
+        }
+        ln_factorial(n) + counts.iter().zip(&probs)
+            .map(|(k, p)| xlogy(*k as f64, *p) - ln_factorial(*k))
+            .sum::<f64>()
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (i64,Vec<f64>)) -> Vec<i64> {
+        let (n, probs) = params;
+        // draw each count conditioned on the counts before it, where the last
+        // non-zero category has exactly all of the remaining probability
+        let mut remaining_probs = probs.iter().rev()
+            .scan(0., |total, p| { *total += p; Some(*total) })
+            .collect::<Vec<f64>>();
+        remaining_probs.reverse();
+        let mut remaining_n = n;
+        let mut counts = vec![0; probs.len()];
+        for ((count, p), remaining_p) in counts.iter_mut().zip(&probs).zip(remaining_probs) {
+            if remaining_n == 0 {
+                break;
+            }
+            *count = if *p >= remaining_p { remaining_n } else { binomial.random(rng, (remaining_n, p / remaining_p)) };
+            remaining_n -= *count;
+        }
+        counts
+    }
+
+    fn check_params(&self, params: &(i64,Vec<f64>)) -> Result<(), Error> {
+        let (n, probs) = params;
+        if *n < 0 {
+            Err(Error::InvalidParameters(format!("multinomial requires n >= 0, got n = {n}")))
+        } else if probs.iter().any(|p| p.is_nan() || *p < 0.) {
+            Err(Error::InvalidParameters(format!("multinomial requires non-negative probs, got {probs:?}")))
+        } else if (probs.iter().sum::<f64>() - 1.).abs() > 1e-8 {
+            Err(Error::InvalidParameters(format!("multinomial requires probs that sum to 1, got {probs:?}")))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn support(&self, params: &(i64,Vec<f64>)) -> Option<Vec<Vec<i64>>> {
+        let (n, probs) = params;
+        let mut support = vec![];
+        push_compositions(*n, probs, &mut vec![], &mut support);
+        Some(support)
+    }
+}
+
+/// Push every assignment of `n` counts to the categories with non-zero `probs`, after the `prefix` of counts.
+fn push_compositions(n: i64, probs: &[f64], prefix: &mut Vec<i64>, support: &mut Vec<Vec<i64>>) {
+    match probs {
+        [] => if n == 0 { support.push(prefix.clone()); },
+        [p, rest @ ..] => {
+            let max_count = if *p > 0. { n } else { 0 };
+            for k in 0..=max_count {
+                prefix.push(k);
+                push_compositions(n - k, rest, prefix, support);
+                prefix.pop();
+            }
+        }
+    }
+}
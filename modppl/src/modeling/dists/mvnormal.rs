@@ -1,5 +1,6 @@
-use rand::rngs::ThreadRng;
+use rand::Rng;
 use super::{Distribution,normal};
+use crate::Error;
 use std::f64::consts::PI;
 use nalgebra::{DVector,DMatrix};
 
@@ -14,14 +15,21 @@ impl Distribution<DVector<f64>,(DVector<f64>,DMatrix<f64>)> for MvNormal {
     fn logpdf(&self, x: &DVector<f64>, params: (DVector<f64>,DMatrix<f64>)) -> f64 {
         let (mu, cov) = params;
         let k = mu.len() as f64;
-        let cov_det = cov.determinant();
-        let cov_inv = cov.clone().try_inverse().unwrap();
         let centered_x = x - mu;
-        let mahalanobis_squared = (centered_x.transpose() * cov_inv * centered_x).trace();
-        -(k*(2.*PI).ln() + cov_det.ln() + mahalanobis_squared)/2.
+        let (log_det, mahalanobis_squared) = match cov.clone().cholesky() {
+            Some(c) => {
+                let log_det = 2. * c.l_dirty().diagonal().iter().map(|d| d.ln()).sum::<f64>();
+                (log_det, centered_x.dot(&c.solve(&centered_x)))
+            },
+            None => {
+                let cov_inv = cov.clone().try_inverse().unwrap();
+                (cov.determinant().ln(), (centered_x.transpose() * cov_inv * &centered_x).trace())
+            }
+        };
+        -(k*(2.*PI).ln() + log_det + mahalanobis_squared)/2.
     }
 
-    fn random(&self, rng: &mut ThreadRng, params: (DVector<f64>,DMatrix<f64>)) -> DVector<f64> {
+    fn random(&self, rng: &mut impl Rng, params: (DVector<f64>,DMatrix<f64>)) -> DVector<f64> {
         let (mu, cov) = params;
         let transform: DMatrix<f64>;
         match cov.clone().cholesky() {
@@ -36,4 +44,19 @@ impl Distribution<DVector<f64>,(DVector<f64>,DMatrix<f64>)> for MvNormal {
         let samples = transform * &mu.map(|_| normal.random(rng, (0.,1.))) + mu;
         samples
     }
+
+    fn logpdf_grad(&self, x: &DVector<f64>, params: (DVector<f64>,DMatrix<f64>)) -> Option<(DVector<f64>,(DVector<f64>,DMatrix<f64>))> {
+        let (mu, cov) = params;
+        let cov_inv = cov.try_inverse()?;
+        let d_mu = &cov_inv * (x - mu);
+        let d_cov = (&d_mu * d_mu.transpose() - cov_inv)/2.;
+        Some((-&d_mu, (d_mu, d_cov)))
+    }
+
+    fn check_params(&self, params: &(DVector<f64>,DMatrix<f64>)) -> Result<(), Error> {
+        let (mu, cov) = params;
+        if cov.is_square() && cov.nrows() == mu.len() { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("mvnormal requires a {}x{} cov, got {}x{}", mu.len(), mu.len(), cov.nrows(), cov.ncols())))
+        }
+    }
 }
\ No newline at end of file
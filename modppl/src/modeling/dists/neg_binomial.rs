@@ -0,0 +1,46 @@
+use rand::Rng;
+use super::{Distribution,ln_beta};
+use crate::Error;
+use rand_distr::{
+    Distribution as _,
+    Gamma as GammaSampler,
+    Poisson as PoissonSampler
+};
+
+
+/// Negative binomial distribution type
+pub struct NegBinomial { }
+
+/// Instantiation of the negative binomial distribution, the number of failures before the `r`-th success with success probability `p`
+pub const neg_binomial: NegBinomial = NegBinomial { };
+
+impl Distribution<i64,(f64,f64)> for NegBinomial {
+    fn logpdf(&self, k: &i64, params: (f64,f64)) -> f64 {
+        let (r, p) = params;
+        if *k < 0 {
+            return f64::NEG_INFINITY;
+        }
+        let k = *k as f64;
+        // Γ(k + r) / (k! Γ(r)) = 1 / ((k + r) B(r, k + 1))
+        -ln_beta(r, k + 1.) - (k + r).ln() + r*p.ln() + k*(-p).ln_1p()
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (f64,f64)) -> i64 {
+        let (r, p) = params;
+        // poisson with a gamma-distributed rate
+        let gamma_sampler = GammaSampler::new(r, (1. - p)/p).ok().unwrap();
+        let rate = gamma_sampler.sample(rng);
+        if rate == 0. {
+            return 0;
+        }
+        let poisson_sampler = PoissonSampler::new(rate).ok().unwrap();
+        poisson_sampler.sample(rng) as i64
+    }
+
+    fn check_params(&self, params: &(f64,f64)) -> Result<(), Error> {
+        let (r, p) = *params;
+        if r > 0. && 0. < p && p < 1. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("neg_binomial requires r > 0 and 0 < p < 1, got (r, p) = ({r}, {p})")))
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use rand::rngs::ThreadRng;
+use rand::Rng;
 use super::{Distribution,u01};
+use crate::Error;
 use std::f64::consts::PI;
 
 
@@ -16,7 +17,7 @@ impl Distribution<f64,(f64,f64)> for Normal {
         -(z.abs().powf(2.) + (2.*PI).ln())/2. - std.ln()
     }
 
-    fn random(&self, rng: &mut ThreadRng, params: (f64,f64)) -> f64 {
+    fn random(&self, rng: &mut impl Rng, params: (f64,f64)) -> f64 {
         let (mu, std) = params;
         let u: f64 = u01(rng) * 2. - 1.;
         let v: f64 = u01(rng) * 2. - 1.;
@@ -25,4 +26,50 @@ impl Distribution<f64,(f64,f64)> for Normal {
         let c = (-2. * r.ln() / r).sqrt();
         return u * c * std + mu;
     }
+
+    fn logpdf_grad(&self, x: &f64, params: (f64,f64)) -> Option<(f64,(f64,f64))> {
+        let (mu, std) = params;
+        let z = (x - mu) / std;
+        Some((-z/std, (z/std, (z*z - 1.)/std)))
+    }
+
+    fn check_params(&self, params: &(f64,f64)) -> Result<(), Error> {
+        let (_, std) = *params;
+        if std > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("normal requires std > 0, got std = {std}")))
+        }
+    }
+}
+
+/// Half-Gaussian distribution type
+pub struct HalfNormal { }
+
+/// Instantiation of the half-Gaussian distribution, the Gaussian distribution centered at zero and folded onto `x >= 0`
+pub const half_normal: HalfNormal = HalfNormal { };
+
+impl Distribution<f64,f64> for HalfNormal {
+    fn logpdf(&self, x: &f64, std: f64) -> f64 {
+        if *x < 0. {
+            return f64::NEG_INFINITY;
+        }
+        normal.logpdf(x, (0., std)) + 2_f64.ln()
+    }
+
+    fn random(&self, rng: &mut impl Rng, std: f64) -> f64 {
+        normal.random(rng, (0., std)).abs()
+    }
+
+    fn logpdf_grad(&self, x: &f64, std: f64) -> Option<(f64,f64)> {
+        if *x < 0. {
+            return None;
+        }
+        let (d_x, (_, d_std)) = normal.logpdf_grad(x, (0., std))?;
+        Some((d_x, d_std))
+    }
+
+    fn check_params(&self, std: &f64) -> Result<(), Error> {
+        if *std > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("half_normal requires std > 0, got std = {std}")))
+        }
+    }
 }
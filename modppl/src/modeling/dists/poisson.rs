@@ -1,5 +1,6 @@
-use rand::rngs::ThreadRng;
-use super::Distribution;
+use rand::Rng;
+use super::{Distribution,ln_factorial,xlogy};
+use crate::Error;
 use rand_distr::{
     Distribution as _,
     Poisson as PoissonSampler
@@ -14,11 +15,20 @@ pub const poisson: Poisson = Poisson { };
 
 impl Distribution<i64,f64> for Poisson {
     fn logpdf(&self, k: &i64, rate: f64) -> f64 {
-        (*k as f64)*rate.ln() - rate - (1..=*k).map(|v| (v as f64).ln()).sum::<f64>()
+        if *k < 0 {
+            return f64::NEG_INFINITY;
+        }
+        xlogy(*k as f64, rate) - rate - ln_factorial(*k)
     }
 
-    fn random(&self, rng: &mut ThreadRng, rate: f64) -> i64 {
+    fn random(&self, rng: &mut impl Rng, rate: f64) -> i64 {
         let poisson_sampler = PoissonSampler::new(rate).ok().unwrap();
         poisson_sampler.sample(rng) as i64
     }
+
+    fn check_params(&self, rate: &f64) -> Result<(), Error> {
+        if *rate > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("poisson requires rate > 0, got rate = {rate}")))
+        }
+    }
 }
\ No newline at end of file
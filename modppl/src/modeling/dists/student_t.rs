@@ -0,0 +1,47 @@
+use rand::Rng;
+use super::{Distribution,ln_beta};
+use crate::Error;
+use compute::functions::digamma;
+use rand_distr::{
+    Distribution as _,
+    StudentT as StudentTSampler
+};
+
+
+/// Student's t-distribution type
+pub struct StudentT { }
+
+/// Instantiation of the location-scale Student's t-distribution, with parameters `(nu, loc, scale)`
+pub const student_t: StudentT = StudentT { };
+
+impl Distribution<f64,(f64,f64,f64)> for StudentT {
+    fn logpdf(&self, x: &f64, params: (f64,f64,f64)) -> f64 {
+        let (nu, loc, scale) = params;
+        let z = (x - loc) / scale;
+        // Γ((nu + 1)/2) / (Γ(nu/2) sqrt(pi)) = 1 / B(1/2, nu/2), which stays accurate for large nu
+        -ln_beta(0.5, nu/2.) - nu.ln()/2. - scale.ln() - (nu + 1.)/2.*(z*z/nu).ln_1p()
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (f64,f64,f64)) -> f64 {
+        let (nu, loc, scale) = params;
+        let student_t_sampler = StudentTSampler::new(nu).ok().unwrap();
+        loc + scale*student_t_sampler.sample(rng)
+    }
+
+    fn logpdf_grad(&self, x: &f64, params: (f64,f64,f64)) -> Option<(f64,(f64,f64,f64))> {
+        let (nu, loc, scale) = params;
+        let z = (x - loc) / scale;
+        let d_x = -(nu + 1.)*z/(scale*(nu + z*z));
+        let d_nu = (digamma((nu + 1.)/2.) - digamma(nu/2.) - 1./nu - (z*z/nu).ln_1p())/2.
+            + (nu + 1.)*z*z/(2.*nu*(nu + z*z));
+        let d_scale = ((nu + 1.)*z*z/(nu + z*z) - 1.)/scale;
+        Some((d_x, (d_nu, -d_x, d_scale)))
+    }
+
+    fn check_params(&self, params: &(f64,f64,f64)) -> Result<(), Error> {
+        let (nu, _, scale) = *params;
+        if nu > 0. && scale > 0. { Ok(()) } else {
+            Err(Error::InvalidParameters(format!("student_t requires nu > 0 and scale > 0, got (nu, scale) = ({nu}, {scale})")))
+        }
+    }
+}
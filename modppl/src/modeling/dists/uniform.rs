@@ -1,11 +1,14 @@
 use std::fmt::Display;
-use rand::rngs::ThreadRng;
+use rand::Rng;
 use super::{Distribution,u01};
+use crate::Error;
 
 
-fn check_bounds<T: PartialOrd + Display>(a: T, b: T) {
+fn check_bounds<T: PartialOrd + Display>(a: T, b: T) -> Result<(), Error> {
     if a >= b {
-        panic!("a >= b in [a, b] = [{}, {}]; b > a is required.", a, b);
+        Err(Error::InvalidParameters(format!("a >= b in [a, b] = [{}, {}]; b > a is required.", a, b)))
+    } else {
+        Ok(())
     }
 }
 
@@ -21,15 +24,28 @@ pub const uniform: UniformContinuous = uniform_continuous;
 impl Distribution<f64,(f64,f64)> for UniformContinuous {
     fn logpdf(&self, x: &f64, params: (f64,f64)) -> f64 {
         let (a, b) = params;
-        check_bounds(a, b);
+        if let Err(e) = check_bounds(a, b) { panic!("{e}"); }
         if a <= *x && *x <= b { -(b - a).ln() } else { f64::NEG_INFINITY }
     }
 
-    fn random(&self, rng: &mut ThreadRng, params: (f64,f64)) -> f64 {
+    fn random(&self, rng: &mut impl Rng, params: (f64,f64)) -> f64 {
         let (a, b) = params;
-        check_bounds(a, b);
+        if let Err(e) = check_bounds(a, b) { panic!("{e}"); }
         u01(rng) * (b - a) + a
     }
+
+    fn logpdf_grad(&self, x: &f64, params: (f64,f64)) -> Option<(f64,(f64,f64))> {
+        let (a, b) = params;
+        if *x < a || *x > b {
+            return None;
+        }
+        let w = b - a;
+        Some((0., (1./w, -1./w)))
+    }
+
+    fn check_params(&self, params: &(f64,f64)) -> Result<(), Error> {
+        check_bounds(params.0, params.1)
+    }
 }
 
 
@@ -42,13 +58,21 @@ pub const uniform_discrete: UniformDiscrete = UniformDiscrete { };
 impl Distribution<i64,(i64,i64)> for UniformDiscrete {
     fn logpdf(&self, x: &i64, params: (i64,i64)) -> f64 {
         let (a, b) = params;
-        check_bounds(a, b);
+        if let Err(e) = check_bounds(a, b) { panic!("{e}"); }
         if a <= *x && *x <= b { -((b - a + 1) as f64).ln() } else { f64::NEG_INFINITY }
     }
 
-    fn random(&self, rng: &mut ThreadRng, params: (i64,i64)) -> i64 {
+    fn random(&self, rng: &mut impl Rng, params: (i64,i64)) -> i64 {
         let (a, b) = params;
-        check_bounds(a, b);
+        if let Err(e) = check_bounds(a, b) { panic!("{e}"); }
         (u01(rng) * (b - a + 1) as f64).trunc() as i64 + a
     }
+
+    fn check_params(&self, params: &(i64,i64)) -> Result<(), Error> {
+        check_bounds(params.0, params.1)
+    }
+
+    fn support(&self, params: &(i64,i64)) -> Option<Vec<i64>> {
+        Some((params.0..=params.1).collect())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,139 @@
+use rand::Rng;
+use super::{Distribution,ln_gamma,normal,gamma};
+use crate::Error;
+use std::f64::consts::PI;
+use compute::functions::digamma;
+use nalgebra::{DMatrix,Cholesky,Dyn};
+
+
+/// Return the log multivariate gamma function `ln Γ_p(a)`.
+fn ln_multigamma(a: f64, p: usize) -> f64 {
+    (p*(p - 1)) as f64/4.*PI.ln() + (0..p).map(|j| ln_gamma(a - j as f64/2.)).sum::<f64>()
+}
+
+/// Return the derivative of `ln_multigamma` with respect to `a`.
+fn multidigamma(a: f64, p: usize) -> f64 {
+    (0..p).map(|j| digamma(a - j as f64/2.)).sum::<f64>()
+}
+
+fn log_det(chol: &Cholesky<f64,Dyn>) -> f64 {
+    2.*chol.l_dirty().diagonal().iter().map(|d| d.ln()).sum::<f64>()
+}
+
+fn check_wishart_params(name: &str, df: f64, scale: &DMatrix<f64>) -> Result<(), Error> {
+    let p = scale.nrows();
+    if !scale.is_square() || p == 0 {
+        Err(Error::InvalidParameters(format!("{name} requires a non-empty square scale, got {}x{}", scale.nrows(), scale.ncols())))
+    } else if df <= (p - 1) as f64 {
+        Err(Error::InvalidParameters(format!("{name} requires df > {}, got df = {df}", p - 1)))
+    } else if scale.clone().cholesky().is_none() {
+        Err(Error::InvalidParameters(format!("{name} requires a positive definite scale, got {scale}")))
+    } else {
+        Ok(())
+    }
+}
+
+/// Wishart distribution type
+pub struct Wishart { }
+
+/// Instantiation of the Wishart distribution over positive definite matrices, with parameters `(df, scale)`
+pub const wishart: Wishart = Wishart { };
+
+impl Distribution<DMatrix<f64>,(f64,DMatrix<f64>)> for Wishart {
+    fn logpdf(&self, x: &DMatrix<f64>, params: (f64,DMatrix<f64>)) -> f64 {
+        let (df, scale) = params;
+        let p = scale.nrows();
+        if x.shape() != scale.shape() {
+            return f64::NEG_INFINITY;
+        }
+        let (Some(x_chol), Some(scale_chol)) = (x.clone().cholesky(), scale.cholesky()) else {
+            return f64::NEG_INFINITY;
+        };
+        let p_f = p as f64;
+        (df - p_f - 1.)/2.*log_det(&x_chol) - scale_chol.solve(x).trace()/2.
+            - df*p_f/2.*2_f64.ln() - df/2.*log_det(&scale_chol) - ln_multigamma(df/2., p)
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (f64,DMatrix<f64>)) -> DMatrix<f64> {
+        let (df, scale) = params;
+        let p = scale.nrows();
+        // Bartlett decomposition
+        let a = DMatrix::from_fn(p, p, |i, j| {
+            if i == j {
+                (2.*gamma.random(rng, ((df - i as f64)/2., 1.))).sqrt()
+            } else if i > j {
+                normal.random(rng, (0., 1.))
+            } else {
+                0.
+            }
+        });
+        let la = scale.cholesky().unwrap().l() * a;
+        let x = &la * la.transpose();
+        (&x + x.transpose())/2.
+    }
+
+    fn logpdf_grad(&self, x: &DMatrix<f64>, params: (f64,DMatrix<f64>)) -> Option<(DMatrix<f64>,(f64,DMatrix<f64>))> {
+        let (df, scale) = params;
+        let p = scale.nrows();
+        let p_f = p as f64;
+        let x_chol = x.clone().cholesky()?;
+        let scale_chol = scale.cholesky()?;
+        let scale_inv = scale_chol.inverse();
+        let d_x = (x_chol.inverse()*(df - p_f - 1.) - &scale_inv)/2.;
+        let d_df = (log_det(&x_chol) - p_f*2_f64.ln() - log_det(&scale_chol) - multidigamma(df/2., p))/2.;
+        let d_scale = (&scale_inv * x * &scale_inv - scale_inv*df)/2.;
+        Some((d_x, (d_df, d_scale)))
+    }
+
+    fn check_params(&self, params: &(f64,DMatrix<f64>)) -> Result<(), Error> {
+        let (df, scale) = params;
+        check_wishart_params("wishart", *df, scale)
+    }
+}
+
+/// Inverse Wishart distribution type
+pub struct InvWishart { }
+
+/// Instantiation of the inverse Wishart distribution over positive definite matrices, with parameters `(df, scale)`
+pub const inv_wishart: InvWishart = InvWishart { };
+
+impl Distribution<DMatrix<f64>,(f64,DMatrix<f64>)> for InvWishart {
+    fn logpdf(&self, x: &DMatrix<f64>, params: (f64,DMatrix<f64>)) -> f64 {
+        let (df, scale) = params;
+        let p = scale.nrows();
+        if x.shape() != scale.shape() {
+            return f64::NEG_INFINITY;
+        }
+        let (Some(x_chol), Some(scale_chol)) = (x.clone().cholesky(), scale.clone().cholesky()) else {
+            return f64::NEG_INFINITY;
+        };
+        let p_f = p as f64;
+        df/2.*log_det(&scale_chol) - df*p_f/2.*2_f64.ln() - ln_multigamma(df/2., p)
+            - (df + p_f + 1.)/2.*log_det(&x_chol) - x_chol.solve(&scale).trace()/2.
+    }
+
+    fn random(&self, rng: &mut impl Rng, params: (f64,DMatrix<f64>)) -> DMatrix<f64> {
+        let (df, scale) = params;
+        let scale_inv = scale.cholesky().unwrap().inverse();
+        let x = wishart.random(rng, (df, scale_inv)).cholesky().unwrap().inverse();
+        (&x + x.transpose())/2.
+    }
+
+    fn logpdf_grad(&self, x: &DMatrix<f64>, params: (f64,DMatrix<f64>)) -> Option<(DMatrix<f64>,(f64,DMatrix<f64>))> {
+        let (df, scale) = params;
+        let p = scale.nrows();
+        let p_f = p as f64;
+        let x_chol = x.clone().cholesky()?;
+        let scale_chol = scale.clone().cholesky()?;
+        let x_inv = x_chol.inverse();
+        let d_x = (&x_inv * &scale * &x_inv - &x_inv*(df + p_f + 1.))/2.;
+        let d_df = (log_det(&scale_chol) - p_f*2_f64.ln() - multidigamma(df/2., p) - log_det(&x_chol))/2.;
+        let d_scale = (scale_chol.inverse()*df - x_inv)/2.;
+        Some((d_x, (d_df, d_scale)))
+    }
+
+    fn check_params(&self, params: &(f64,DMatrix<f64>)) -> Result<(), Error> {
+        let (df, scale) = params;
+        check_wishart_params("inv_wishart", *df, scale)
+    }
+}
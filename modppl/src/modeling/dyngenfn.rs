@@ -1,9 +1,10 @@
 use std::sync::Arc;
-use std::any::Any;
-use rand::rngs::ThreadRng;
-use crate::AddrMap;
+use std::any::{Any,type_name};
+use rand::{Rng,RngCore};
+use nalgebra::{DVector,DMatrix};
+use crate::{AddrMap,Error};
 use crate::modeling::dists::Distribution;
-use crate::{Trie,GenFn,ArgDiff,Trace};
+use crate::{Trie,GenFn,ArgDiff,Trace,Differentiable};
 
 
 ///
@@ -15,52 +16,127 @@ pub type DynTrace<Args,Ret> = Trace<Args,DynTrie,Ret>;
 impl DynTrie {
     /// Cast the inner `dyn Any` at `addr` into type `V` at runtime.
     pub fn read<V: 'static + Clone>(&self, addr: &str) -> V {
-        match self.search(addr) {
-            Some(v) => {
-                let v_typed = v
-                    .ref_inner()
-                    .unwrap()
-                    .downcast_ref::<V>();
-                match v_typed {
-                    Some(v) => { v.clone() }
-                    None => {
-                        panic!("read: failed when downcasting type at address \"{}\"", addr);
-                    }
-                }
-            }
-            None => {
-                panic!("read: failed when searching empty address \"{}\"", addr);
-            }
+        match self.try_read(addr) {
+            Ok(v) => v,
+            Err(err) => panic!("read: {err}")
+        }
+    }
+
+    /// Cast the inner `dyn Any` at `addr` into type `V` at runtime.
+    /// 
+    /// Return `Error::MissingAddress` if `addr` holds no value, or `Error::TypeMismatch` if the value isn't a `V`.
+    pub fn try_read<V: 'static + Clone>(&self, addr: &str) -> Result<V, Error> {
+        let v = self.try_search(addr)?
+            .ref_inner()
+            .ok_or_else(|| Error::MissingAddress(addr.to_string()))?;
+        match v.downcast_ref::<V>() {
+            Some(v) => Ok(v.clone()),
+            None => Err(Error::TypeMismatch { addr: addr.to_string(), expected: type_name::<V>() })
         }
     }
 }
 
+/// Downcast the inner `value` found at `addr`, returning an `Error` if it is absent or not a `V`.
+fn downcast_at<V: Send + Sync + 'static>(value: Option<&Arc<dyn Any + Send + Sync>>, addr: &str) -> Result<Arc<V>, Error> {
+    match value {
+        Some(v) => v.clone().downcast::<V>()
+            .map_err(|_| Error::TypeMismatch { addr: addr.to_string(), expected: type_name::<V>() }),
+        None => Err(Error::MissingAddress(addr.to_string()))
+    }
+}
+
+/// Flatten `v` into its coordinates if it has one of the `Differentiable` types of the values or
+/// parameters of the built-in distributions.
+fn any_coords(v: &dyn Any) -> Option<Vec<f64>> {
+    fn coords<D: Differentiable + 'static>(v: &dyn Any) -> Option<Vec<f64>> {
+        v.downcast_ref::<D>().map(|v| v.to_coords())
+    }
+    coords::<f64>(v)
+        .or_else(|| coords::<(f64,f64)>(v))
+        .or_else(|| coords::<(f64,f64,f64)>(v))
+        .or_else(|| coords::<DVector<f64>>(v))
+        .or_else(|| coords::<DMatrix<f64>>(v))
+        .or_else(|| coords::<(DVector<f64>,DMatrix<f64>)>(v))
+        .or_else(|| coords::<(f64,DMatrix<f64>)>(v))
+}
+
+/// Clone the inner value at `addr` in `data` if it is a `V`.
+fn downcast_inner<V: Clone + 'static>(data: &DynTrie, addr: &str) -> Option<V> {
+    data.search(addr)?.ref_inner()?.downcast_ref::<V>().cloned()
+}
+
 /// Incremental computational state of a `trace` during the execution of the different `GenFn` methods with a `DynGenFn`.
+///
+/// Each variant holds the first `Error` raised by `sample_at` or `trace_at`, which is returned by the
+/// enclosing `try_*` method once the execution finishes.
 pub enum DynGenFnHandler<'a,A,T> {
     /// State for executing `GenFn::simulate` in a `DynGenFn`.
     Simulate {
         ///
-        prng: &'a mut ThreadRng,
+        prng: &'a mut dyn RngCore,
         ///
         trace: DynTrace<A,T>,
+        ///
+        error: Option<Error>,
     },
 
     /// State for executing `GenFn::generate` in a `DynGenFn`.
     Generate {
         ///
-        prng: &'a mut ThreadRng,
+        prng: &'a mut dyn RngCore,
         ///
         trace: DynTrace<A,T>,
         ///
         weight: f64,
         ///
         constraints: DynTrie,
+        ///
+        error: Option<Error>,
+    },
+
+    /// State for executing `GenFn::generate` in a `DynGenFn` while searching for the next choice to enumerate (see `enumerate`).
+    Enumerate {
+        ///
+        prng: &'a mut dyn RngCore,
+        ///
+        trace: DynTrace<A,T>,
+        ///
+        weight: f64,
+        ///
+        constraints: DynTrie,
+        ///
+        selection: &'a AddrMap,
+        /// The address of the first unconstrained choice in the `selection`, with the finite `Distribution::support` of its distribution (if any).
+        #[allow(clippy::type_complexity)]
+        next: Option<(String, Option<Vec<Arc<dyn Any + Send + Sync>>>)>,
+        ///
+        error: Option<Error>,
+    },
+
+    /// State for replaying a `trace` in a `DynGenFn` while accumulating the gradients of the `logpdf` of its choices
+    /// (see `GenFn::choice_gradients`).
+    Gradients {
+        ///
+        prng: &'a mut dyn RngCore,
+        /// The replayed trace.
+        trace: &'a DynTrace<A,T>,
+        /// Choices replayed in place of their values in `trace` (eg. a perturbed choice).
+        perturbation: &'a DynTrie,
+        ///
+        selection: &'a AddrMap,
+        /// The gradients of the `logpdf` of each choice with respect to its value, if accumulated.
+        grads: Option<DynTrie>,
+        /// The coordinates of the parameters of each choice (or the `logjp` of each subtrace) in execution order,
+        /// with the gradient of its `logpdf` (or `logjp`) with respect to them.
+        params: Vec<(Vec<f64>, Vec<f64>)>,
+        ///
+        error: Option<Error>,
     },
 
     /// State for executing `GenFn::update` in a `DynGenFn`.
     Update {
         ///
-        prng: &'a mut ThreadRng,
+        prng: &'a mut dyn RngCore,
         ///
         trace: DynTrace<A,T>,
         ///
@@ -72,13 +148,15 @@ pub enum DynGenFnHandler<'a,A,T> {
         ///
         discard: DynTrie,
         ///
-        visitor: AddrMap
+        visitor: AddrMap,
+        ///
+        error: Option<Error>
     },
 
     /// State for executing `GenFn::regenerate` in a `DynGenFn`.
     Regenerate {
         ///
-        prng: &'a mut ThreadRng,
+        prng: &'a mut dyn RngCore,
         ///
         trace: DynTrace<A,T>,
         ///
@@ -88,7 +166,9 @@ pub enum DynGenFnHandler<'a,A,T> {
         ///
         weight: f64,
         ///
-        visitor: AddrMap
+        visitor: AddrMap,
+        ///
+        error: Option<Error>
     }
 }
 
@@ -97,19 +177,105 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
     /// Sample a random value from a distribution and observe it in the `self.trace.data` trie as a weighted leaf node.
     /// 
     /// Return a clone of the sampled value.
+    ///
+    /// If this raises an `Error` (or one was raised earlier), it is held by the handler and a fallback value is returned
+    /// so that the execution can finish: the value at `addr` in the constraints or the previous trace if there is one,
+    /// and otherwise a draw from `dist`.
     pub fn sample_at<
         V: Clone + Send + Sync + 'static,
         W: Clone + 'static
     >(&mut self, dist: &impl Distribution<V,W>, args: W, addr: &str) -> V {
+        if self.error().is_none() {
+            match self.try_sample_at(dist, args.clone(), addr) {
+                Ok(x) => { return x; }
+                Err(err) => { *self.error() = Some(err); }
+            }
+        }
+        let (prng, prev) = match self {
+            DynGenFnHandler::Simulate { prng, .. } => (prng, None),
+            DynGenFnHandler::Generate { prng, constraints, .. }
+            | DynGenFnHandler::Enumerate { prng, constraints, .. } => (prng, downcast_inner::<V>(constraints, addr)),
+            DynGenFnHandler::Update { prng, trace, constraints, .. } => {
+                (prng, downcast_inner::<V>(constraints, addr).or_else(|| downcast_inner::<V>(&trace.data, addr)))
+            }
+            DynGenFnHandler::Regenerate { prng, trace, .. } => (prng, downcast_inner::<V>(&trace.data, addr)),
+            DynGenFnHandler::Gradients { prng, trace, .. } => (prng, downcast_inner::<V>(&trace.data, addr)),
+        };
+        match prev {
+            Some(x) => x,
+            None => dist.random(prng, args)
+        }
+    }
+
+    /// Recursively sample a trace from another `gen_fn`.
+    /// 
+    /// Insert its `subtrace.data` trie as a descendant of the current `trace.data` trie.
+    /// Insert its `retv` as an inner value of the `trace.data` trie.
+    /// 
+    /// Return a clone of the `retv`.
+    ///
+    /// If this raises an `Error` (or one was raised earlier), it is held by the handler and a fallback value is returned
+    /// so that the execution can finish: the previous `retv` at `addr` if there is one, and otherwise `Y::default()`.
+    pub fn trace_at<
+        X: Clone + 'static,
+        Y: Clone + Default + Send + Sync + 'static
+    >(&mut self, gen_fn: &impl GenFn<X,DynTrie,Y>, args: X, addr: &str) -> Y {
+        if self.error().is_none() {
+            match self.try_trace_at(gen_fn, args.clone(), addr) {
+                Ok(retv) => { return retv; }
+                Err(err) => { *self.error() = Some(err); }
+            }
+        }
+        let prev = match self {
+            DynGenFnHandler::Simulate { .. } | DynGenFnHandler::Generate { .. } | DynGenFnHandler::Enumerate { .. } => None,
+            DynGenFnHandler::Update { trace, .. } | DynGenFnHandler::Regenerate { trace, .. } => downcast_inner::<Y>(&trace.data, addr),
+            DynGenFnHandler::Gradients { trace, .. } => downcast_inner::<Y>(&trace.data, addr),
+        };
+        prev.unwrap_or_default()
+    }
+
+    /// The first `Error` raised during the execution, if any.
+    fn error(&mut self) -> &mut Option<Error> {
+        match self {
+            DynGenFnHandler::Simulate { error, .. }
+            | DynGenFnHandler::Generate { error, .. }
+            | DynGenFnHandler::Enumerate { error, .. }
+            | DynGenFnHandler::Gradients { error, .. }
+            | DynGenFnHandler::Update { error, .. }
+            | DynGenFnHandler::Regenerate { error, .. } => error
+        }
+    }
+
+    /// Take the first `Error` raised during the execution, if any.
+    pub(crate) fn take_error(&mut self) -> Result<(), Error> {
+        match self.error().take() {
+            Some(err) => Err(err),
+            None => Ok(())
+        }
+    }
+
+    fn try_sample_at<
+        V: Clone + Send + Sync + 'static,
+        W: Clone + 'static
+    >(&mut self, dist: &impl Distribution<V,W>, args: W, addr: &str) -> Result<V, Error> {
+        dist.check_params(&args)?;
+        if let DynGenFnHandler::Enumerate { constraints, selection, next, .. } = self {
+            if next.is_none() && constraints.search(addr).is_none() && selection.search(addr).is_some() {
+                let support = dist.support(&args)
+                    .map(|values| values.into_iter().map(|v| Arc::new(v) as Arc<dyn Any + Send + Sync>).collect());
+                *next = Some((addr.to_string(), support));
+            }
+        }
         match self {
             DynGenFnHandler::Simulate {
                 prng,
                 trace,
+                ..
             } => {
                 let x = dist.random(prng, args.clone());
                 let logp = dist.logpdf(&x, args);
-                trace.data.w_observe(addr, Arc::new(x.clone()), logp);
-                x
+                trace.data.try_w_observe(addr, Arc::new(x.clone()), logp)?;
+                Ok(x)
             }
 
             DynGenFnHandler::Generate {
@@ -117,14 +283,19 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                 trace,
                 weight,
                 constraints,
+                ..
+            }
+            | DynGenFnHandler::Enumerate {
+                prng,
+                trace,
+                weight,
+                constraints,
+                ..
             } => {
                 let (x, logp) = match constraints.remove(addr) {
                     Some(choice) => {
                         debug_assert!(choice.is_leaf());
-                        let x = choice
-                            .expect_inner(&format!("error: no value found in {addr}"))
-                            .downcast::<V>()
-                            .expect(&format!("error: downcast failed at {addr}"));
+                        let x = downcast_at::<V>(choice.ref_inner(), addr)?;
                         let logp = dist.logpdf(x.as_ref(), args);
                         *weight += logp;
                         (x, logp)
@@ -136,8 +307,8 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                     }
                 };
 
-                trace.data.w_observe(addr, x.clone(), logp);
-                x.as_ref().clone()
+                trace.data.try_w_observe(addr, x.clone(), logp)?;
+                Ok(x.as_ref().clone())
             }
 
             DynGenFnHandler::Update {
@@ -147,7 +318,8 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                 constraints,
                 weight,
                 discard,
-                visitor
+                visitor,
+                ..
             } => {
                 visitor.visit(addr);
 
@@ -156,12 +328,9 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                         debug_assert!(choice.is_leaf());
                         if let Some(call) = trace.data.remove(addr) {
                             *weight -= call.weight();
-                            discard.insert(addr, call);
+                            discard.try_insert(addr, call)?;
                         };
-                        let x = choice
-                            .expect_inner(&format!("error: no value found in {addr}"))
-                            .downcast::<V>()
-                            .expect(&format!("error: downcast failed at {addr}"));
+                        let x = downcast_at::<V>(choice.ref_inner(), addr)?;
                         let logp = dist.logpdf(x.as_ref(), args);
                         *diff = ArgDiff::Unknown;
                         *weight += logp;
@@ -172,27 +341,19 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                             Some(call) => {
                                 match diff {
                                     ArgDiff::NoChange => {
-                                        let x = call
-                                            .clone()
-                                            .expect_inner(&format!("error: no value found in {addr}"))
-                                            .downcast::<V>()
-                                            .expect(&format!("error: downcast failed at {addr}"));
-                                        trace.data.insert(addr, call);
-                                        return x.as_ref().clone();
+                                        let x = downcast_at::<V>(call.ref_inner(), addr)?;
+                                        trace.data.try_insert(addr, call)?;
+                                        return Ok(x.as_ref().clone());
                                     }
                                     ArgDiff::Unknown => {
                                         let prev_logp = call.weight();
-                                        let x = call
-                                            .clone()
-                                            .expect_inner(&format!("error: no value found in {addr}"))
-                                            .downcast::<V>()
-                                            .expect(&format!("error: downcast failed at {addr}"));
+                                        let x = downcast_at::<V>(call.ref_inner(), addr)?;
                                         let logp = dist.logpdf(x.as_ref(), args);
                                         *weight += logp - prev_logp;
                                         (x, logp)
                                     }
                                     _ => {
-                                        panic!("update: ArgDiff::Extend not supported");
+                                        return Err(Error::UnsupportedDiff(diff.clone()));
                                     }
                                 }
                             }
@@ -206,8 +367,8 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                     }
                 };
 
-                trace.data.w_observe(addr, x.clone(), logp);
-                x.as_ref().clone()
+                trace.data.try_w_observe(addr, x.clone(), logp)?;
+                Ok(x.as_ref().clone())
             }
 
             DynGenFnHandler::Regenerate {
@@ -216,7 +377,8 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                 diff,
                 mask,
                 weight,
-                visitor
+                visitor,
+                ..
             } => {
                 visitor.visit(addr);
 
@@ -234,27 +396,19 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                             Some(call) => {
                                 match diff {
                                     ArgDiff::NoChange => {
-                                        let x = call
-                                            .clone()
-                                            .expect_inner(&format!("error: no value found in {addr}"))
-                                            .downcast::<V>()
-                                            .expect(&format!("error: downcast failed at {addr}"));
-                                        trace.data.insert(addr, call);
-                                        return x.as_ref().clone();
+                                        let x = downcast_at::<V>(call.ref_inner(), addr)?;
+                                        trace.data.try_insert(addr, call)?;
+                                        return Ok(x.as_ref().clone());
                                     }
                                     ArgDiff::Unknown => {
                                         let prev_logp = call.weight();
-                                        let x = call
-                                            .clone()
-                                            .expect_inner(&format!("error: no value found in {addr}"))
-                                            .downcast::<V>()
-                                            .expect(&format!("error: downcast failed at {addr}"));
+                                        let x = downcast_at::<V>(call.ref_inner(), addr)?;
                                         let logp = dist.logpdf(x.as_ref(), args);
                                         *weight += logp - prev_logp;
                                         (x, logp)
                                     }
                                     _ => {
-                                        panic!("regenerate: ArgDiff::Extend not supported");
+                                        return Err(Error::UnsupportedDiff(diff.clone()));
                                     }
                                 }
                             }
@@ -268,64 +422,94 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                     }
                 };
 
-                trace.data.w_observe(addr, x.clone(), logp);
-                x.as_ref().clone()
+                trace.data.try_w_observe(addr, x.clone(), logp)?;
+                Ok(x.as_ref().clone())
+            }
+
+            DynGenFnHandler::Gradients {
+                trace,
+                perturbation,
+                grads,
+                params,
+                ..
+            } => {
+                let value = match perturbation.search(addr) {
+                    Some(choice) => choice.ref_inner(),
+                    None => trace.data.try_search(addr)?.ref_inner()
+                };
+                let x = downcast_at::<V>(value, addr)?;
+                match dist.logpdf_grad(x.as_ref(), args.clone()) {
+                    Some((d_x, d_args)) => {
+                        if let Some(grads) = grads {
+                            grads.try_observe(addr, Arc::new(d_x))?;
+                        }
+                        params.push(any_coords(&args).zip(any_coords(&d_args)).unwrap_or_default());
+                    }
+                    None => {
+                        params.push((vec![], vec![]));
+                    }
+                }
+                Ok(x.as_ref().clone())
             }
         }
     }
 
-    /// Recursively sample a trace from another `gen_fn`.
-    /// 
-    /// Insert its `subtrace.data` trie as a descendant of the current `trace.data` trie.
-    /// Insert its `retv` as an inner value of the `trace.data` trie.
-    /// 
-    /// Return a clone of the `retv`.
-    pub fn trace_at<
+    fn try_trace_at<
         X: Clone + 'static,
         Y: Clone + Send + Sync + 'static
-    >(&mut self, gen_fn: &impl GenFn<X,DynTrie,Y>, args: X, addr: &str) -> Y {
+    >(&mut self, gen_fn: &impl GenFn<X,DynTrie,Y>, args: X, addr: &str) -> Result<Y, Error> {
         match self {
             DynGenFnHandler::Simulate {
-                prng: _,
+                prng,
                 trace,
+                ..
             } => {
-                let mut subtrace = gen_fn.simulate(args);
+                let mut subtrace = gen_fn.try_simulate(prng, args)?;
                 subtrace.data.replace_inner(Arc::new(subtrace.retv.clone().unwrap()));
-                trace.data.insert(addr, subtrace.data);
-                subtrace.retv.unwrap()
+                trace.data.try_insert(addr, subtrace.data)?;
+                Ok(subtrace.retv.unwrap())
             }
 
             DynGenFnHandler::Generate {
-                prng: _,
+                prng,
+                trace,
+                weight,
+                constraints,
+                ..
+            }
+            | DynGenFnHandler::Enumerate {
+                prng,
                 trace,
                 weight,
                 constraints,
+                ..
             } => {
                 let (mut sub, retv) = match constraints.remove(addr) {
                     Some(choices) => {
                         debug_assert!(!choices.is_leaf());
-                        let (subtrace, d_weight) = gen_fn.generate(args, choices);
+                        let (subtrace, d_weight) = gen_fn.try_generate(prng, args, choices)?;
                         *weight += d_weight;
                         (subtrace.data, subtrace.retv)
                     }
                     None => {
-                        let subtrace = gen_fn.simulate(args);
+                        let subtrace = gen_fn.try_simulate(prng, args)?;
                         (subtrace.data, subtrace.retv)
                     }
                 };
                 sub.replace_inner(Arc::new(retv.clone().unwrap()));
-                trace.data.insert(addr, sub);
-                retv.unwrap()
+                trace.data.try_insert(addr, sub)?;
+                Ok(retv.unwrap())
             },
 
             DynGenFnHandler::Update {
-                prng: _,
+                prng,
                 trace,
                 diff,
                 constraints,
                 weight,
                 discard,
-                visitor
+                visitor,
+                ..
             } => {
                 visitor.visit(addr);
 
@@ -336,16 +520,16 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                                 debug_assert!(!choices.is_leaf());
                                 let logjp = sub.weight();
                                 let subtrace = Trace { args: args.clone(), data: sub, retv: None, logjp };
-                                let (subtrace, subdiscard, d_weight) = gen_fn.update(subtrace, args, diff.clone(), choices);
+                                let (subtrace, subdiscard, d_weight) = gen_fn.try_update(prng, subtrace, args, diff.clone(), choices)?;
                                 if !subdiscard.is_empty() {
-                                    discard.insert(addr, subdiscard);
+                                    discard.try_insert(addr, subdiscard)?;
                                 }
                                 *diff = ArgDiff::Unknown;
                                 *weight += d_weight;
                                 (subtrace.data, subtrace.retv)
                             }
                             None => {
-                                let (subtrace, d_weight) = gen_fn.generate(args, choices);
+                                let (subtrace, d_weight) = gen_fn.try_generate(prng, args, choices)?;
                                 *diff = ArgDiff::Unknown;
                                 *weight += d_weight;
                                 (subtrace.data, subtrace.retv)
@@ -357,27 +541,27 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                             Some(sub) => {
                                 match diff {
                                     ArgDiff::NoChange => {
-                                        let retv = sub.ref_inner().unwrap().downcast_ref::<Y>().unwrap().clone();
-                                        trace.data.insert(addr, sub);
-                                        return retv;
+                                        let retv = downcast_at::<Y>(sub.ref_inner(), addr)?.as_ref().clone();
+                                        trace.data.try_insert(addr, sub)?;
+                                        return Ok(retv);
                                     }
                                     ArgDiff::Unknown => {
                                         let logjp = sub.weight();
                                         let subtrace = Trace { args: args.clone(), data: sub, retv: None, logjp };
-                                        let (subtrace, subdiscard, d_weight) = gen_fn.update(subtrace, args, ArgDiff::Unknown, DynTrie::new());
+                                        let (subtrace, subdiscard, d_weight) = gen_fn.try_update(prng, subtrace, args, ArgDiff::Unknown, DynTrie::new())?;
                                         if !(subdiscard.is_empty()) {
-                                            discard.insert(addr, subdiscard);
+                                            discard.try_insert(addr, subdiscard)?;
                                         }
                                         *weight += d_weight;
                                         (subtrace.data, subtrace.retv)
                                     }
                                     _ => {
-                                        panic!("update: ArgDiff::Extend not supported");
+                                        return Err(Error::UnsupportedDiff(diff.clone()));
                                     }
                                 }
                             }
                             None => {
-                                let subtrace = gen_fn.simulate(args);
+                                let subtrace = gen_fn.try_simulate(prng, args)?;
                                 *diff = ArgDiff::Unknown;
                                 (subtrace.data, subtrace.retv)
                             }
@@ -386,17 +570,18 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                 };
 
                 sub.replace_inner(Arc::new(retv.clone().unwrap()));
-                trace.data.insert(addr, sub);
-                retv.unwrap()
+                trace.data.try_insert(addr, sub)?;
+                Ok(retv.unwrap())
             }
 
             DynGenFnHandler::Regenerate {
-                prng: _prng,
+                prng,
                 trace,
                 diff,
                 mask,
                 weight,
-                visitor
+                visitor,
+                ..
             } => {
                 visitor.visit(addr);
 
@@ -408,7 +593,7 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                         match submask {
                             Some(submask) => {
                                 let subtrace = Trace { args: args.clone(), data: sub, retv: None, logjp };
-                                let (subtrace, d_weight) = gen_fn.regenerate(subtrace, args, diff.clone(), submask);
+                                let (subtrace, d_weight) = gen_fn.try_regenerate(prng, subtrace, args, diff.clone(), submask)?;
                                 *diff = ArgDiff::Unknown;
                                 *weight += d_weight;
                                 (subtrace.data, subtrace.retv)
@@ -416,35 +601,71 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                             None => {  // submask is absent
                                 match diff {
                                     ArgDiff::NoChange => {
-                                        let retv = sub.ref_inner().unwrap().downcast_ref::<Y>().unwrap().clone();
-                                        trace.data.insert(addr, sub);
-                                        return retv;
+                                        let retv = downcast_at::<Y>(sub.ref_inner(), addr)?.as_ref().clone();
+                                        trace.data.try_insert(addr, sub)?;
+                                        return Ok(retv);
                                     }
                                     ArgDiff::Unknown => {
                                         let prev_weight = sub.weight();
-                                        let (subtrace, new_weight) = gen_fn.generate(args, sub);
+                                        let (subtrace, new_weight) = gen_fn.try_generate(prng, args, sub)?;
                                         *weight += new_weight - prev_weight;
                                         (subtrace.data, subtrace.retv)
                                     }
                                     _ => {
-                                        panic!("regenerate: ArgDiff::Extend not supported");
+                                        return Err(Error::UnsupportedDiff(diff.clone()));
                                     }
                                 }
                             }
                         }
                     }
                     None => {
-                        let subtrace = gen_fn.simulate(args);
+                        let subtrace = gen_fn.try_simulate(prng, args)?;
                         *diff = ArgDiff::Unknown;
                         (subtrace.data, subtrace.retv)
                     }
                 };
 
                 sub.replace_inner(Arc::new(retv.clone().unwrap()));
-                trace.data.insert(addr, sub);
-                retv.unwrap()
+                trace.data.try_insert(addr, sub)?;
+                Ok(retv.unwrap())
             }
 
+            DynGenFnHandler::Gradients {
+                prng,
+                trace,
+                perturbation,
+                selection,
+                grads,
+                params,
+                ..
+            } => {
+                let mut data = trace.data.try_search(addr)?.clone();
+                let retv = downcast_at::<Y>(data.take_inner().as_ref(), addr)?;
+                let logjp = data.weight();
+                let subtrace = Trace::new(args.clone(), data, retv.as_ref().clone(), logjp);
+                match grads {
+                    Some(grads) => {
+                        if let Some(subselection) = selection.search(addr) {
+                            let subselection = if subselection.is_leaf() { subtrace.data.schema() } else { subselection.clone() };
+                            let subgrads = gen_fn.choice_gradients(prng, &subtrace, &subselection);
+                            if !subgrads.is_empty() {
+                                grads.try_insert(addr, subgrads)?;
+                            }
+                        }
+                        params.push((vec![logjp], vec![1.]));
+                        Ok(retv.as_ref().clone())
+                    }
+                    None => {
+                        // the gradients of the perturbed choices of the subtrace are accumulated by its own `choice_gradients`,
+                        // so only the change in its `logjp` due to a change in its `args` is recorded
+                        let constraints = perturbation.search(addr).cloned().unwrap_or_default();
+                        let perturbed = !constraints.is_empty();
+                        let (subtrace, _, weight) = gen_fn.try_update(prng, subtrace, args, ArgDiff::Unknown, constraints)?;
+                        params.push((vec![if perturbed { logjp } else { logjp + weight }], vec![1.]));
+                        Ok(subtrace.retv.unwrap())
+                    }
+                }
+            }
         }
     }
 
@@ -453,7 +674,7 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
     /// Panics on other variants.
     pub fn gc(self) -> Self {
         match self {
-            Self::Update { prng, trace, diff, constraints, weight, mut discard, visitor } => {
+            Self::Update { prng, trace, diff, constraints, weight, mut discard, visitor, error } => {
                 let schema = trace.data.schema();
                 let (data, complement, complement_weight) = trace.data.collect(&schema.complement(&visitor));
                 debug_assert!(visitor.all_visited(&data.schema()));  // all unvisited nodes garbage-collected
@@ -465,10 +686,11 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                     constraints,
                     weight: weight - complement_weight,
                     discard,
-                    visitor
+                    visitor,
+                    error
                 }
             }
-            Self::Regenerate { prng, trace, diff, mask, weight, visitor } => {
+            Self::Regenerate { prng, trace, diff, mask, weight, visitor, error } => {
                 let schema = trace.data.schema();
                 let (data, _, _) = trace.data.collect(&schema.complement(&visitor));
                 debug_assert!(visitor.all_visited(&data.schema()));  // all unvisited nodes garbage-collected
@@ -478,7 +700,8 @@ impl<A,T> DynGenFnHandler<'_,A,T> {
                     diff,
                     mask,
                     weight,
-                    visitor
+                    visitor,
+                    error
                 }
             }
             _ => { panic!("garbage-collect (gc): called outside of update or regenerate context") }
@@ -500,85 +723,275 @@ impl<Args,Ret> DynGenFn<Args,Ret> {
     }
 }
 
+impl<Args: Clone,Ret> DynGenFn<Args,Ret> {
+    /// Replay the choices of `trace` with `args`, replaying the choices in `perturbation` in place of their values.
+    ///
+    /// Return the coordinates of the parameters of each choice with the gradient of its `logpdf` with respect to them
+    /// (see `DynGenFnHandler::Gradients`), and if `accumulate` is `true`, the gradient of the `logpdf` of each choice
+    /// with respect to its value (and the `choice_gradients` of the subtraces selected by `selection`).
+    #[allow(clippy::type_complexity)]
+    fn replay_gradients(&self,
+        rng: &mut impl Rng,
+        trace: &DynTrace<Args,Ret>,
+        args: Args,
+        perturbation: &DynTrie,
+        selection: &AddrMap,
+        accumulate: bool
+    ) -> (Vec<(Vec<f64>, Vec<f64>)>, Option<DynTrie>) {
+        let mut g = DynGenFnHandler::Gradients {
+            prng: rng,
+            trace,
+            perturbation,
+            selection,
+            grads: if accumulate { Some(DynTrie::new()) } else { None },
+            params: vec![],
+            error: None
+        };
+        (self.func)(&mut g, args);
+        if let Err(err) = g.take_error() {
+            panic!("gradients: {err}");
+        }
+        let DynGenFnHandler::Gradients {prng: _, trace: _, perturbation: _, selection: _, grads, params, error: _} = g else { unreachable!() };
+        (params, grads)
+    }
+
+    /// Return the gradient of `trace.logjp` through the parameters of its choices with respect to the coordinate `i`
+    /// of the input replayed by `replay`, linearizing the parameters by replays perturbed by `h` in each direction.
+    fn param_gradient(
+        base: &[(Vec<f64>, Vec<f64>)],
+        x: &[f64],
+        i: usize,
+        mut replay: impl FnMut(&[f64]) -> Vec<(Vec<f64>, Vec<f64>)>
+    ) -> f64 {
+        let h = f64::EPSILON.cbrt() * x[i].abs().max(1.);
+        let mut x_h = x.to_vec();
+        x_h[i] = x[i] + h;
+        let plus = replay(&x_h);
+        x_h[i] = x[i] - h;
+        let minus = replay(&x_h);
+        base.iter().zip(plus.iter().zip(minus.iter()))
+            .map(|((_, grad), ((p_plus, _), (p_minus, _)))| {
+                grad.iter().zip(p_plus.iter().zip(p_minus.iter())).map(|(g, (p, m))| g * (p - m)).sum::<f64>()
+            })
+            .sum::<f64>() / (2.*h)
+    }
+
+    /// Return the gradient of `trace.logjp` with respect to the choice `x` at `addr`, given the gradient `grad` of
+    /// its own `logpdf` (or subtrace) and the parameters of the choices of the `base` replay of `trace`.
+    fn choice_gradient<V: Differentiable + Clone + Send + Sync + 'static>(&self,
+        rng: &mut impl Rng,
+        trace: &DynTrace<Args,Ret>,
+        base: &[(Vec<f64>, Vec<f64>)],
+        addr: &str,
+        x: &V,
+        grad: Option<&V>
+    ) -> V {
+        let coords = x.to_coords();
+        let mut grad = grad.map_or_else(|| vec![0.; coords.len()], |grad| grad.to_coords());
+        for (i, d_x) in grad.iter_mut().enumerate() {
+            *d_x += Self::param_gradient(base, &coords, i, |coords| {
+                let mut perturbation = DynTrie::new();
+                perturbation.observe(addr, Arc::new(x.with_coords(coords)));
+                self.replay_gradients(rng, trace, trace.args.clone(), &perturbation, &AddrMap::new(), false).0
+            });
+        }
+        x.with_coords(&grad)
+    }
+}
+
+/// Collect the addresses of the choices in `data` that are selected by `selection`.
+pub(crate) fn selected_choices(data: &DynTrie, selection: &AddrMap) -> Vec<String> {
+    let mut addrs = vec![];
+    for (addr, sub) in data.iter() {
+        if let Some(subselection) = selection.search(addr) {
+            if sub.is_leaf() {
+                addrs.push(addr.clone());
+            } else {
+                let subselection = if subselection.is_leaf() { &sub.schema() } else { subselection };
+                addrs.extend(selected_choices(sub, subselection)
+                    .into_iter()
+                    .map(|subaddr| format!("{addr} / {subaddr}")));
+            }
+        }
+    }
+    addrs
+}
+
+/// Sum the weights of the choices in `data` that are selected by `selection`.
+pub(crate) fn selected_weight(data: &DynTrie, selection: &AddrMap) -> f64 {
+    let mut weight = 0.;
+    for (addr, sub) in data.iter() {
+        if let Some(subselection) = selection.search(addr) {
+            weight += if sub.is_leaf() || subselection.is_leaf() { sub.weight() } else { selected_weight(sub, subselection) };
+        }
+    }
+    weight
+}
+
 impl<Args: Clone,Ret> GenFn<Args,DynTrie,Ret> for DynGenFn<Args,Ret> {
-    fn simulate(&self, args: Args) -> DynTrace<Args,Ret> {
+    fn simulate(&self, rng: &mut impl Rng, args: Args) -> DynTrace<Args,Ret> {
+        self.try_simulate(rng, args).unwrap_or_else(|err| panic!("simulate: {err}"))
+    }
+
+    fn generate(&self, rng: &mut impl Rng, args: Args, constraints: DynTrie) -> (DynTrace<Args,Ret>, f64) {
+        self.try_generate(rng, args, constraints).unwrap_or_else(|err| panic!("generate: {err}"))
+    }
+
+    fn update(&self,
+        rng: &mut impl Rng,
+        trace: DynTrace<Args,Ret>,
+        args: Args,
+        diff: ArgDiff,
+        constraints: DynTrie
+    ) -> (DynTrace<Args,Ret>, DynTrie, f64) {
+        self.try_update(rng, trace, args, diff, constraints).unwrap_or_else(|err| panic!("update: {err}"))
+    }
+
+    fn regenerate(&self,
+        rng: &mut impl Rng,
+        trace: DynTrace<Args,Ret>,
+        args: Args,
+        diff: ArgDiff,
+        mask: &AddrMap
+    ) -> (DynTrace<Args,Ret>, f64) {
+        self.try_regenerate(rng, trace, args, diff, mask).unwrap_or_else(|err| panic!("regenerate: {err}"))
+    }
+
+    /// Accumulates the gradients `Distribution::logpdf_grad` of the choices while replaying `trace`: the gradient of
+    /// each selected choice is the gradient of its own `logpdf`, plus the gradients of the `logpdf` of the choices whose
+    /// parameters depend on it. These parameters (and the `logjp` of subtraces) are linearized with respect to the choice
+    /// by replaying `trace` with the choice perturbed, so the gradient is exact if they are affine in the choice.
+    ///
+    /// Only choices of type `f64` or `DVector<f64>` are differentiated; other selected choices are ignored. Choices whose
+    /// distribution has no `logpdf_grad` (or unknown parameter types) don't contribute to the gradient.
+    ///
+    /// Costs `1 + 2d` replays of `trace`, where `d` is the total number of coordinates of the selected choices
+    /// (one replay for the `logpdf` gradients, and two perturbed replays per coordinate).
+    fn choice_gradients(&self, rng: &mut impl Rng, trace: &DynTrace<Args,Ret>, selection: &AddrMap) -> DynTrie {
+        let (base, grads) = self.replay_gradients(rng, trace, trace.args.clone(), &DynTrie::new(), selection, true);
+        let grads = grads.unwrap();
+        let mut choice_grads = DynTrie::new();
+        for addr in selected_choices(&trace.data, selection) {
+            let x = trace.data.search(&addr).unwrap().ref_inner().unwrap();
+            let grad = grads.search(&addr).and_then(|grad| grad.ref_inner());
+            if let Some(x) = x.downcast_ref::<f64>() {
+                let grad = grad.and_then(|grad| grad.downcast_ref::<f64>());
+                choice_grads.observe(&addr, Arc::new(self.choice_gradient(rng, trace, &base, &addr, x, grad)));
+            } else if let Some(x) = x.downcast_ref::<DVector<f64>>() {
+                let grad = grad.and_then(|grad| grad.downcast_ref::<DVector<f64>>());
+                choice_grads.observe(&addr, Arc::new(self.choice_gradient(rng, trace, &base, &addr, x, grad)));
+            }
+        }
+        choice_grads
+    }
+
+    /// Accumulates the gradients `Distribution::logpdf_grad` of the choices with respect to their parameters while
+    /// replaying `trace`. The parameters (and the `logjp` of subtraces) are linearized with respect to the `args`
+    /// by replaying `trace` with perturbed `args`, as in `choice_gradients`.
+    ///
+    /// Costs `1 + 2d` replays of `trace`, where `d` is the number of coordinates of the `args`.
+    fn argument_gradients(&self, rng: &mut impl Rng, trace: &DynTrace<Args,Ret>) -> Args
+    where
+        Args: Differentiable + Clone,
+        DynTrie: Clone + Default,
+        Ret: Clone
+    {
+        let (base, _) = self.replay_gradients(rng, trace, trace.args.clone(), &DynTrie::new(), &AddrMap::new(), false);
+        let coords = trace.args.to_coords();
+        let grad = (0..coords.len())
+            .map(|i| Self::param_gradient(&base, &coords, i, |coords| {
+                self.replay_gradients(rng, trace, trace.args.with_coords(coords), &DynTrie::new(), &AddrMap::new(), false).0
+            }))
+            .collect::<Vec<f64>>();
+        trace.args.with_coords(&grad)
+    }
+
+    fn try_simulate(&self, rng: &mut impl Rng, args: Args) -> Result<DynTrace<Args,Ret>, Error> {
         let mut g = DynGenFnHandler::Simulate {
-            prng: &mut ThreadRng::default(),
+            prng: rng,
             trace: Trace { args: args.clone(), data: Trie::new(), retv: None, logjp: 0. },
+            error: None
         };
         let retv = (self.func)(&mut g, args);
-        let DynGenFnHandler::Simulate {prng: _, mut trace} = g else { unreachable!() };
+        g.take_error()?;
+        let DynGenFnHandler::Simulate {prng: _, mut trace, error: _} = g else { unreachable!() };
         trace.set_retv(retv);
         trace.logjp = trace.data.weight();
-        trace
+        Ok(trace)
     }
 
-    fn generate(&self, args: Args, mut constraints: DynTrie) -> (DynTrace<Args,Ret>, f64) {
+    fn try_generate(&self, rng: &mut impl Rng, args: Args, mut constraints: DynTrie) -> Result<(DynTrace<Args,Ret>, f64), Error> {
         constraints.take_inner();  // in case constraints came from a proposal
         let mut g = DynGenFnHandler::Generate {
-            prng: &mut ThreadRng::default(),
+            prng: rng,
             trace: Trace { args: args.clone(), data: Trie::new(), retv: None, logjp: 0. },
             weight: 0.,
             constraints: constraints,
+            error: None
         };
         let retv = (self.func)(&mut g, args);
-        let DynGenFnHandler::Generate {prng: _, mut trace, weight, constraints} = g else { unreachable!() };
+        g.take_error()?;
+        let DynGenFnHandler::Generate {prng: _, mut trace, weight, constraints, error: _} = g else { unreachable!() };
         if !constraints.is_empty() {
-            println!("residual found:\n{:#?}", constraints);
-            panic!("generate error: not all constraints were consumed!");
+            return Err(Error::UnconsumedConstraints(constraints.schema()));
         }  // else all constraints bound to trace
         trace.logjp = trace.data.weight();
         trace.set_retv(retv);
-        (trace, weight)
+        Ok((trace, weight))
     }
 
-    fn update(&self,
+    fn try_update(&self,
+        rng: &mut impl Rng,
         trace: DynTrace<Args,Ret>,
         args: Args,
         diff: ArgDiff,
         mut constraints: DynTrie
-    ) -> (DynTrace<Args,Ret>, DynTrie, f64) {
+    ) -> Result<(DynTrace<Args,Ret>, DynTrie, f64), Error> {
         constraints.take_inner();  // in case constraints came from a proposal
         let mut g = DynGenFnHandler::Update {
-            prng: &mut ThreadRng::default(),
+            prng: rng,
             trace,
             diff,
             weight: 0.,
             constraints: constraints,
             discard: Trie::new(),
-            visitor: AddrMap::new()
+            visitor: AddrMap::new(),
+            error: None
         };
         let retv = (self.func)(&mut g, args);
+        g.take_error()?;
         let g = g.gc();  // subtract weight of complement and add complement to discard
-        let DynGenFnHandler::Update {prng: _, mut trace, diff: _diff, weight, constraints, discard, visitor: _visitor} = g else { unreachable!() };
+        let DynGenFnHandler::Update {prng: _, mut trace, diff: _diff, weight, constraints, discard, visitor: _visitor, error: _} = g else { unreachable!() };
         if !constraints.is_empty() {
-            println!("residual found:\n{:#?}", constraints);
-            panic!("update error: not all constraints were consumed!");
+            return Err(Error::UnconsumedConstraints(constraints.schema()));
         }  // else all constraints bound to trace
         trace.logjp = trace.data.weight();
         trace.set_retv(retv);
-        (trace, discard, weight)
+        Ok((trace, discard, weight))
     }
 
-    fn regenerate(&self,
+    fn try_regenerate(&self,
+        rng: &mut impl Rng,
         trace: DynTrace<Args,Ret>,
         args: Args,
         diff: ArgDiff,
         mask: &AddrMap
-    ) -> (DynTrace<Args,Ret>, f64) {
+    ) -> Result<(DynTrace<Args,Ret>, f64), Error> {
         let mut g = DynGenFnHandler::Regenerate {
-            prng: &mut ThreadRng::default(),
+            prng: rng,
             mask: if mask.is_leaf() { &trace.data.schema() } else { mask },
             trace,
             diff,
             weight: 0.,
-            visitor: AddrMap::new()
+            visitor: AddrMap::new(),
+            error: None
         };
         let retv = (self.func)(&mut g, args);
+        g.take_error()?;
         let g = g.gc();
-        let DynGenFnHandler::Regenerate {prng: _, mut trace, diff: _diff, mask: _mask, weight, visitor: _visitor} = g else { unreachable!() };
+        let DynGenFnHandler::Regenerate {prng: _, mut trace, diff: _diff, mask: _mask, weight, visitor: _visitor, error: _} = g else { unreachable!() };
         trace.logjp = trace.data.weight();
         trace.set_retv(retv);
-        (trace, weight)
+        Ok((trace, weight))
     }
-}
\ No newline at end of file
+}
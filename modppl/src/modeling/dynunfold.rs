@@ -1,9 +1,13 @@
-use crate::{DynGenFn, DynGenFnHandler, DynTrie, GenFn, ArgDiff, Trace, ParticleSystem};
-use rand::rngs::ThreadRng;
+use crate::{DynGenFn, DynGenFnHandler, DynTrie, DynTrace, GenFn, ArgDiff, Trace, ParticleSystem, AddrMap, Error};
+use rand::Rng;
 
 
 /// Combinator struct for kernels that use the `DynGenFnHandler` DSL (`sample_at` and `trace_at`).
 /// Supports memory-efficient extension via the `GfDiff::Extend` flag (eg. as passed during a `ParticleSystem::step`).
+///
+/// Past steps can be revisited (eg. by MCMC kernels passed to `ParticleSystem::rejuvenate`) with an
+/// `ArgDiff::NoChange` update taking one `DynTrie` of constraints per step, or with a `regenerate` whose
+/// `mask` selects the choices of step `t` under the address `t` (eg. `"3 / dr"`).
 pub struct DynUnfold<State> {
     /// A stochastic kernel that takes in a mutable reference to a `DynGenFnHandler<A,T>` and some `State`, effectfully mutates it, and produces a new `State`.
     pub kernel: DynGenFn<(i64,State),State>
@@ -16,86 +20,235 @@ impl<State> DynUnfold<State> {
     }
 }
 
+/// Return `Error::InvalidArguments` unless the unfold runs for at least one step.
+fn check_num_steps(final_t: i64) -> Result<(), Error> {
+    if final_t >= 1 { Ok(()) } else {
+        Err(Error::InvalidArguments(format!("an unfold requires at least 1 step, got {final_t}")))
+    }
+}
+
+/// Return `Error::InvalidArguments` unless there is one `DynTrie` of constraints per step.
+fn check_num_constraints(num_steps: i64, num_constraints: usize) -> Result<(), Error> {
+    if num_steps == num_constraints as i64 { Ok(()) } else {
+        Err(Error::InvalidArguments(format!("expected constraints for {num_steps} steps, got {num_constraints}")))
+    }
+}
+
 ///
-pub type DynParticles<State> = ParticleSystem<State,Vec<DynTrie>,Vec<State>,DynUnfold<State>>;
+pub type DynParticles<State,R> = ParticleSystem<State,Vec<DynTrie>,Vec<State>,DynUnfold<State>,R>;
+
+impl<State: Clone> DynUnfold<State> {
+    /// Split a trace of the unfold into the traces of its individual kernel steps.
+    fn step_traces(vec_trace: Trace<(i64,State),Vec<DynTrie>,Vec<State>>) -> Vec<DynTrace<(i64,State),State>> {
+        let Trace { args: (_, init_state), data, retv, logjp: _ } = vec_trace;
+        let retv = retv.unwrap();
+        let prev_states = std::iter::once(init_state).chain(retv.iter().cloned());
+        data.into_iter()
+            .zip(retv.iter().cloned())
+            .zip(prev_states)
+            .enumerate()
+            .map(|(t, ((data, state), prev_state))| {
+                let logjp = data.weight();
+                Trace::new((t as i64, prev_state), data, state, logjp)
+            })
+            .collect()
+    }
+
+    /// Revisit each step of `vec_trace` with `revisit_step`, which is passed the trace of the step, its
+    /// (possibly new) input state, and whether any earlier step was changed, and returns `None` if the step is unchanged.
+    #[allow(clippy::type_complexity)]
+    fn revisit<R: Rng>(&self,
+        rng: &mut R,
+        vec_trace: Trace<(i64,State),Vec<DynTrie>,Vec<State>>,
+        mut revisit_step: impl FnMut(&mut R, usize, DynTrace<(i64,State),State>, State, bool) -> Result<Option<(DynTrace<(i64,State),State>, DynTrie, f64)>, Error>
+    ) -> Result<(Trace<(i64,State),Vec<DynTrie>,Vec<State>>, Vec<DynTrie>, f64), Error> {
+        let args = vec_trace.args.clone();
+        let mut new_trace = Trace { args: args.clone(), data: vec![], retv: Some(vec![]), logjp: 0. };
+        let mut discards = vec![];
+        let mut total_weight = 0.;
+        let mut state = args.1;
+        let mut changed = false;
+        for (t, step_trace) in Self::step_traces(vec_trace).into_iter().enumerate() {
+            let (step_trace, discard) = match revisit_step(rng, t, step_trace.clone(), state.clone(), changed)? {
+                Some((step_trace, discard, weight)) => {
+                    changed = true;
+                    total_weight += weight;
+                    (step_trace, discard)
+                }
+                None => (step_trace, DynTrie::new())
+            };
+            state = step_trace.retv.clone().unwrap();
+            new_trace.retv.as_mut().unwrap().push(state.clone());
+            new_trace.logjp += step_trace.logjp;
+            new_trace.data.push(step_trace.data);
+            discards.push(discard);
+        }
+        Ok((new_trace, discards, total_weight))
+    }
+}
 
 impl<State: Clone> GenFn<(i64,State),Vec<DynTrie>,Vec<State>> for DynUnfold<State> {
-    fn simulate(&self, final_t_and_args: (i64, State)) -> Trace<(i64,State),Vec<DynTrie>,Vec<State>> {
+    fn simulate(&self, rng: &mut impl Rng, final_t_and_args: (i64, State)) -> Trace<(i64,State),Vec<DynTrie>,Vec<State>> {
+        self.try_simulate(rng, final_t_and_args).unwrap_or_else(|err| panic!("simulate: {err}"))
+    }
+
+    fn generate(&self, rng: &mut impl Rng, final_t_and_args: (i64, State), vec_constraints: Vec<DynTrie>) 
+        -> (Trace<(i64,State),Vec<DynTrie>,Vec<State>>, f64)
+    {
+        self.try_generate(rng, final_t_and_args, vec_constraints).unwrap_or_else(|err| panic!("generate: {err}"))
+    }
+
+    fn update(&self,
+        rng: &mut impl Rng,
+        vec_trace: Trace<(i64,State),Vec<DynTrie>,Vec<State>>,
+        final_t_and_args: (i64, State),
+        diff: ArgDiff,
+        vec_constraints: Vec<DynTrie>
+    ) -> (Trace<(i64,State),Vec<DynTrie>,Vec<State>>, Vec<DynTrie>, f64) {
+        self.try_update(rng, vec_trace, final_t_and_args, diff, vec_constraints).unwrap_or_else(|err| panic!("update: {err}"))
+    }
+
+    fn regenerate(&self,
+        rng: &mut impl Rng,
+        vec_trace: Trace<(i64,State),Vec<DynTrie>,Vec<State>>,
+        final_t_and_args: (i64, State),
+        diff: ArgDiff,
+        mask: &AddrMap
+    ) -> (Trace<(i64,State),Vec<DynTrie>,Vec<State>>, f64) {
+        self.try_regenerate(rng, vec_trace, final_t_and_args, diff, mask).unwrap_or_else(|err| panic!("regenerate: {err}"))
+    }
+
+    fn try_simulate(&self, rng: &mut impl Rng, final_t_and_args: (i64, State)) -> Result<Trace<(i64,State),Vec<DynTrie>,Vec<State>>, Error> {
         let (final_t, mut state) = final_t_and_args;
-        assert!(final_t >= 1);
+        check_num_steps(final_t)?;
         let mut vec_trace = Trace { args: (final_t, state.clone()), data: vec![], retv: Some(vec![]), logjp: 0. };
         for t in 0..final_t {
             let mut g = DynGenFnHandler::Simulate {
-                prng: &mut ThreadRng::default(),
-                trace: Trace { args: (t as i64, state.clone()), data: DynTrie::new(), retv: None, logjp: 0. },
+                prng: rng,
+                trace: Trace { args: (t, state.clone()), data: DynTrie::new(), retv: None, logjp: 0. },
+                error: None
             };
-            state = (self.kernel.func)(&mut g, (t as i64, state.clone()));
-            let DynGenFnHandler::Simulate {prng: _, trace} = g else { unreachable!() };
+            state = (self.kernel.func)(&mut g, (t, state.clone()));
+            g.take_error()?;
+            let DynGenFnHandler::Simulate {prng: _, trace, error: _} = g else { unreachable!() };
             vec_trace.retv.as_mut().unwrap().push(state.clone());
+            vec_trace.logjp += trace.data.weight();
             vec_trace.data.push(trace.data);
-            vec_trace.logjp += trace.logjp;
         }
-        vec_trace
+        Ok(vec_trace)
     }
 
-    fn generate(&self, final_t_and_args: (i64, State), vec_constraints: Vec<DynTrie>) 
-        -> (Trace<(i64,State),Vec<DynTrie>,Vec<State>>, f64)
+    fn try_generate(&self, rng: &mut impl Rng, final_t_and_args: (i64, State), vec_constraints: Vec<DynTrie>) 
+        -> Result<(Trace<(i64,State),Vec<DynTrie>,Vec<State>>, f64), Error>
     {
         let (final_t, mut state) = final_t_and_args;
-        assert!(final_t >= 1);
+        check_num_steps(final_t)?;
+        check_num_constraints(final_t, vec_constraints.len())?;
         let mut vec_trace = Trace { args: (final_t, state.clone()), data: vec![], retv: Some(vec![]), logjp: 0. };
         let mut gen_weight = 0.;
         for (t,constraints) in vec_constraints.into_iter().enumerate() {
             let mut g = DynGenFnHandler::Generate {
-                prng: &mut ThreadRng::default(),
+                prng: rng,
                 trace: Trace { args: (t as i64, state.clone()), data: DynTrie::new(), retv: None, logjp: 0. },
                 weight: 0.,
-                constraints
+                constraints,
+                error: None
             };
             state = (self.kernel.func)(&mut g, (t as i64, state.clone()));
-            let DynGenFnHandler::Generate {prng: _, trace, weight, constraints} = g else { unreachable!() };
-            assert!(constraints.is_empty());
+            g.take_error()?;
+            let DynGenFnHandler::Generate {prng: _, trace, weight, constraints, error: _} = g else { unreachable!() };
+            if !constraints.is_empty() {
+                return Err(Error::UnconsumedConstraints(constraints.schema()));
+            }
             vec_trace.retv.as_mut().unwrap().push(state.clone());
+            vec_trace.logjp += trace.data.weight();
             vec_trace.data.push(trace.data);
-            vec_trace.logjp += trace.logjp;
             gen_weight += weight;
         }
-        (vec_trace, gen_weight)
+        Ok((vec_trace, gen_weight))
     }
 
-    fn update(&self,
+    fn try_update(&self,
+        rng: &mut impl Rng,
         mut vec_trace: Trace<(i64,State),Vec<DynTrie>,Vec<State>>,
         final_t_and_args: (i64, State),
         diff: ArgDiff,
         vec_constraints: Vec<DynTrie>
-    ) -> (Trace<(i64,State),Vec<DynTrie>,Vec<State>>, Vec<DynTrie>, f64) {
+    ) -> Result<(Trace<(i64,State),Vec<DynTrie>,Vec<State>>, Vec<DynTrie>, f64), Error> {
         let (final_t, _) = final_t_and_args;
-        assert!(final_t >= 1);
+        check_num_steps(final_t)?;
         let prev_t = vec_trace.args.0;
-        assert!(final_t - prev_t == vec_constraints.len() as i64);
+        if let ArgDiff::NoChange = diff {
+            if final_t != prev_t {
+                return Err(Error::InvalidArguments(format!("ArgDiff::NoChange with {final_t} steps, but the trace has {prev_t} steps")));
+            }
+            check_num_constraints(prev_t, vec_constraints.len())?;
+            let mut vec_constraints = vec_constraints.into_iter();
+            return self.revisit(rng, vec_trace, |rng, _, step_trace, state, changed| {
+                let constraints = vec_constraints.next().unwrap_or_default();
+                if !changed && constraints.is_empty() {
+                    return Ok(None);
+                }
+                let args = (step_trace.args.0, state);
+                let diff = if changed { ArgDiff::Unknown } else { ArgDiff::NoChange };
+                self.kernel.try_update(rng, step_trace, args, diff, constraints).map(Some)
+            });
+        }
+        check_num_constraints(final_t - prev_t, vec_constraints.len())?;
         let mut state = vec_trace.retv.as_ref().unwrap().last().unwrap().clone();
         let mut update_weight = 0.;
         match diff {
             ArgDiff::Extend => {
                 for (t,constraints) in vec_constraints.into_iter().enumerate() {
                     let mut g = DynGenFnHandler::Generate {
-                        prng: &mut ThreadRng::default(),
+                        prng: rng,
                         trace: Trace { args: (prev_t + (t as i64), state.clone()), data: DynTrie::new(), retv: None, logjp: 0. },
                         weight: 0.,
-                        constraints
+                        constraints,
+                        error: None
                     };
                     state = (self.kernel.func)(&mut g, (prev_t + (t as i64), state.clone()));
-                    let DynGenFnHandler::Generate {prng: _, trace, weight, constraints} = g else { unreachable!() };
-                    assert!(constraints.is_empty());
+                    g.take_error()?;
+                    let DynGenFnHandler::Generate {prng: _, trace, weight, constraints, error: _} = g else { unreachable!() };
+                    if !constraints.is_empty() {
+                        return Err(Error::UnconsumedConstraints(constraints.schema()));
+                    }
                     vec_trace.args.0 += 1;
                     vec_trace.retv.as_mut().unwrap().push(state.clone());
+                    vec_trace.logjp += trace.data.weight();
                     vec_trace.data.push(trace.data);
-                    vec_trace.logjp += trace.logjp;
                     update_weight += weight;
                 }
             },
-            _ => { panic!("Can't handle GF change type: {:?}", diff) },
+            _ => { return Err(Error::UnsupportedDiff(diff)) },
         }
-        (vec_trace, (prev_t..final_t).map(|_| DynTrie::new()).collect::<_>(), update_weight)
+        Ok((vec_trace, (prev_t..final_t).map(|_| DynTrie::new()).collect::<_>(), update_weight))
     }
-}
\ No newline at end of file
+
+    fn try_regenerate(&self,
+        rng: &mut impl Rng,
+        vec_trace: Trace<(i64,State),Vec<DynTrie>,Vec<State>>,
+        final_t_and_args: (i64, State),
+        diff: ArgDiff,
+        mask: &AddrMap
+    ) -> Result<(Trace<(i64,State),Vec<DynTrie>,Vec<State>>, f64), Error> {
+        let ArgDiff::NoChange = diff else { return Err(Error::UnsupportedDiff(diff)) };
+        if final_t_and_args.0 != vec_trace.args.0 {
+            return Err(Error::InvalidArguments(format!("ArgDiff::NoChange with {} steps, but the trace has {} steps", final_t_and_args.0, vec_trace.args.0)));
+        }
+        let (vec_trace, _, weight) = self.revisit(rng, vec_trace, |rng, t, step_trace, state, changed| {
+            let step_mask = if mask.is_leaf() { Some(mask) } else { mask.search(&t.to_string()) };
+            let args = (step_trace.args.0, state);
+            match step_mask {
+                Some(step_mask) => {
+                    let diff = if changed { ArgDiff::Unknown } else { ArgDiff::NoChange };
+                    let (step_trace, weight) = self.kernel.try_regenerate(rng, step_trace, args, diff, step_mask)?;
+                    Ok(Some((step_trace, DynTrie::new(), weight)))
+                }
+                None if changed => self.kernel.try_update(rng, step_trace, args, ArgDiff::Unknown, DynTrie::new()).map(Some),
+                None => Ok(None)
+            }
+        })?;
+        Ok((vec_trace, weight))
+    }
+}
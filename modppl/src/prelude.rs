@@ -1,15 +1,30 @@
-pub use rand::rngs::ThreadRng;
+pub use rand::{Rng,SeedableRng};
+pub use rand::rngs::{ThreadRng,StdRng};
 pub use std::sync::{Arc,Weak};
 pub use std::any::Any;
 
 pub use crate::{modeling::dists::*,
-    Trace,GenFn, ArgDiff,
+    Trace,GenFn, ArgDiff, Differentiable, Merge, Error,
     AddrMap,
     Trie,
     DynTrie,DynTrace,DynGenFn,DynGenFnHandler,
     importance_sampling,importance_resampling,
+    importance_sampling_with_proposal,importance_resampling_with_proposal,
+    enumerate,ChoiceGrid,
     metropolis_hastings,mh,
     regenerative_metropolis_hastings, regen_mh,
-    ParticleSystem,DynUnfold,DynParticles
+    metropolis_adjusted_langevin, mala,
+    involutive_metropolis_hastings, try_involutive_metropolis_hastings, involutive_mh,
+    estimate_involution_log_abs_det,
+    enumerative_gibbs, gibbs,
+    elliptical_slice_sampling, try_elliptical_slice_sampling, elliptical_slice,
+    slice_sampling, slice_sample,
+    hamiltonian_monte_carlo, hmc,
+    NoUTurnSampler,
+    ParticleSystem,ResamplingScheme,DynUnfold,DynParticles,
+    estimate_log_marginal_likelihood, particle_marginal_metropolis_hastings, pmmh,
+    annealed_importance_sampling, tempered_smc
 };
+#[cfg(feature = "parallel")]
+pub use crate::{par_importance_sampling, par_importance_sampling_with_proposal, par_importance_resampling, par_importance_resampling_with_proposal};
 pub use modppl_macros::dyngen;
\ No newline at end of file
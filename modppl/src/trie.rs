@@ -1,5 +1,5 @@
 use std::collections::{HashMap, hash_map};
-use crate::{SplitAddr::{self,Prefix,Term}, AddrMap};
+use crate::{SplitAddr::{self,Prefix,Term}, AddrMap, Error, normalize_addr};
 
 
 /// Weighted Digital Trie
@@ -93,11 +93,23 @@ impl<V> Trie<V> {
                 self.mapping.get(addr)
             }
             Prefix(first, rest) => {
-                self.mapping[first].search(rest)
+                self.mapping.get(first)?.search(rest)
             }
         }
     }
 
+    /// Return a reference to a descendant at `addr` if present, otherwise `Error::MissingAddress`.
+    pub fn try_search(&self, addr: &str) -> Result<&Trie<V>, Error> {
+        self.search(addr).ok_or_else(|| Error::MissingAddress(normalize_addr(addr)))
+    }
+
+    fn check_unoccupied(&self, addr: &str) -> Result<(), Error> {
+        match self.search(addr) {
+            Some(_) => Err(Error::AddressCollision(normalize_addr(addr))),
+            None => Ok(())
+        }
+    }
+
     /// Observe an unweighted `value` at `addr`. Panic if `addr` is occupied.
     pub fn observe(&mut self, addr: &str, value: V) {
         match SplitAddr::from_addr(addr) {
@@ -111,12 +123,19 @@ impl<V> Trie<V> {
             Prefix(first, rest) => {
                 let submap = self.mapping
                     .entry(first.to_string())
-                    .or_insert(Trie::new());
+                    .or_default();
                 submap.observe(rest, value)
             }
         }
     }
 
+    /// Observe an unweighted `value` at `addr`. Return `Error::AddressCollision` if `addr` is occupied.
+    pub fn try_observe(&mut self, addr: &str, value: V) -> Result<(), Error> {
+        self.check_unoccupied(addr)?;
+        self.observe(addr, value);
+        Ok(())
+    }
+
     /// Observe a weighted `value` at `addr`, summing the weight by `weight`. Panic if `addr` is occupied.
     pub fn w_observe(&mut self, addr: &str, value: V, weight: f64) { 
         self.weight += weight;
@@ -131,12 +150,19 @@ impl<V> Trie<V> {
             Prefix(first, rest) => {
                 let submap = self.mapping
                     .entry(first.to_string())
-                    .or_insert(Trie::new());
+                    .or_default();
                 submap.w_observe(rest, value, weight)
             }
         }
     }
 
+    /// Observe a weighted `value` at `addr`, summing the weight by `weight`. Return `Error::AddressCollision` if `addr` is occupied.
+    pub fn try_w_observe(&mut self, addr: &str, value: V, weight: f64) -> Result<(), Error> {
+        self.check_unoccupied(addr)?;
+        self.w_observe(addr, value, weight);
+        Ok(())
+    }
+
     /// Insert a descendant `sub` at `addr`. Panic if `addr` is occupied.
     pub fn insert(&mut self, addr: &str, sub: Trie<V>) {
         self.weight += sub.weight;
@@ -151,12 +177,19 @@ impl<V> Trie<V> {
             Prefix(first, rest) => {
                 let submap = self.mapping
                     .entry(first.to_string())
-                    .or_insert(Trie::new());
+                    .or_default();
                 submap.insert(rest, sub)
             }
         }
     }
 
+    /// Insert a descendant `sub` at `addr`. Return `Error::AddressCollision` if `addr` is occupied.
+    pub fn try_insert(&mut self, addr: &str, sub: Trie<V>) -> Result<(), Error> {
+        self.check_unoccupied(addr)?;
+        self.insert(addr, sub);
+        Ok(())
+    }
+
     /// Return a descendant at `addr` if present (removing it), otherwise just return none.
     pub fn remove(&mut self, addr: &str) -> Option<Trie<V>> {
         if let Some(sub) = match SplitAddr::from_addr(addr) {
@@ -246,4 +279,9 @@ impl<V> Trie<V> {
         (self, collected, weight)
     }
 
-}
\ No newline at end of file
+}
+impl<V> Default for Trie<V> {
+    fn default() -> Self {
+        Trie::new()
+    }
+}
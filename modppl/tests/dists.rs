@@ -1,9 +1,12 @@
 use std::collections::HashMap;
-use nalgebra::{dvector,dmatrix};
+use nalgebra::{dvector,dmatrix,DVector,DMatrix};
 
-use rand::rngs::ThreadRng;
+use rand::{SeedableRng, rngs::{StdRng, mock::StepRng}};
 use approx;
-use modppl::{Distribution, bernoulli, uniform, uniform_discrete, categorical, normal, mvnormal, geometric, poisson, beta, gamma};
+use modppl::{Error, Distribution, ln_gamma, ln_beta, ln_factorial, bernoulli, uniform, uniform_discrete, categorical, categorical_logits, AliasTable, normal, mvnormal, geometric, poisson, beta, gamma,
+    exponential, laplace, cauchy, half_cauchy, student_t, lognormal, inv_gamma, half_normal,
+    binomial, neg_binomial, beta_binomial, hypergeometric, multinomial,
+    dirichlet, wishart, inv_wishart, lkj_cholesky};
 
 const LOGPDF_EPSILON: f64 = f32::EPSILON as f64;
 
@@ -22,7 +25,7 @@ fn standard_deviation(v: &[f64]) -> f64 {
 
 #[test]
 fn test_bernoulli() {
-    let mut rng = ThreadRng::default();
+    let mut rng = StdRng::seed_from_u64(0);
 
     let true_p = 0.11;
     assert_eq!(bernoulli.logpdf(&true, true_p), true_p.ln());
@@ -37,7 +40,7 @@ fn test_bernoulli() {
 
 #[test]
 fn test_uniform() {
-    let mut rng = ThreadRng::default();
+    let mut rng = StdRng::seed_from_u64(0);
 
     // continuous
     let params = (0.5, 3.14);
@@ -84,7 +87,7 @@ fn test_uniform() {
 
 #[test]
 fn test_categorical() {
-    let mut rng = ThreadRng::default();
+    let mut rng = StdRng::seed_from_u64(0);
     let labels = vec!["a", "b", "c", "d", "e", "f"];
     let probs = vec![0.1, 0.3, 0.2, 0.1, 0.05, 0.25];
     let num_samples = 50000;
@@ -103,9 +106,76 @@ fn test_categorical() {
     }
 }
 
+#[test]
+fn test_categorical_logits() {
+    let probs = [0.1, 0.2, 0.0, 0.7];
+    let logits = probs.iter().map(|p: &f64| p.ln() + 3.).collect::<Vec<f64>>();
+    for x in [0, 1, 3] {
+        approx::assert_abs_diff_eq!(categorical.logpdf(&x, probs.to_vec()), categorical_logits.logpdf(&x, logits.clone()), epsilon = 1e-12);
+    }
+    assert_eq!(f64::NEG_INFINITY, categorical_logits.logpdf(&2, logits.clone()));
+    assert_eq!(f64::NEG_INFINITY, categorical_logits.logpdf(&4, logits.clone()));
+    assert_eq!(f64::NEG_INFINITY, categorical_logits.logpdf(&-1, logits.clone()));
+
+    // logits far outside the range of exp
+    approx::assert_abs_diff_eq!(-(1_f64.exp() + 1.).ln(), categorical_logits.logpdf(&0, vec![-1000., -999.]), epsilon = 1e-12);
+    approx::assert_abs_diff_eq!(-(1_f64 + (-1_f64).exp()).ln(), categorical_logits.logpdf(&1, vec![1000., 1001.]), epsilon = 1e-12);
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let num_samples = 50000;
+    let mut counts = [0; 4];
+    for _ in 0..num_samples {
+        counts[categorical_logits.random(&mut rng, logits.clone()) as usize] += 1;
+    }
+    assert_eq!(counts[2], 0);
+    for (count, p) in counts.iter().zip(probs) {
+        approx::assert_abs_diff_eq!(*count as f64 / num_samples as f64, p, epsilon = 0.01);
+    }
+    assert!((0..100).all(|_| categorical_logits.random(&mut rng, vec![-1000., f64::NEG_INFINITY, -1001.]) != 1));
+    // the extremes of the underlying uniform draws land on values with positive probability
+    assert_eq!(1, categorical_logits.random(&mut StepRng::new(0, 0), vec![f64::NEG_INFINITY, 0., 0.]));
+    assert_eq!(1, categorical_logits.random(&mut StepRng::new(u64::MAX, 0), vec![0., 0., f64::NEG_INFINITY]));
+}
+
+#[test]
+fn test_alias_table() {
+    let weights = [3., 0., 1., 6., 0.5];
+    let table = AliasTable::new(&weights);
+    assert_eq!(table.len(), 5);
+    assert!(!table.is_empty());
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let num_samples = 100000;
+    let mut counts = [0; 5];
+    for _ in 0..num_samples {
+        counts[table.sample(&mut rng)] += 1;
+    }
+    assert_eq!(counts[1], 0);
+    for (count, w) in counts.iter().zip(weights) {
+        approx::assert_abs_diff_eq!(*count as f64 / num_samples as f64, w / 10.5, epsilon = 0.01);
+    }
+
+    // log weights far outside the range of exp
+    let table = AliasTable::from_log_weights(&[-2000., f64::NEG_INFINITY, -2000. + 3_f64.ln()]);
+    let num_threes = (0..num_samples).filter(|_| {
+        let i = table.sample(&mut rng);
+        assert_ne!(i, 1);
+        i == 2
+    }).count();
+    approx::assert_abs_diff_eq!(num_threes as f64 / num_samples as f64, 0.75, epsilon = 0.01);
+
+    assert_eq!(AliasTable::new(&[2.]).sample(&mut rng), 0);
+}
+
+#[test]
+#[should_panic(expected = "at least one weight must be positive")]
+fn test_alias_table_zero_weights() {
+    AliasTable::new(&[0., 0.]);
+}
+
 #[test]
 fn test_normal() {
-    let mut rng = ThreadRng::default();
+    let mut rng = StdRng::seed_from_u64(0);
 
     let true_mu = 1.64;
     let true_std = 0.025;
@@ -138,7 +208,7 @@ fn test_normal() {
 
 #[test]
 fn test_mvnormal() {
-    let mut rng = ThreadRng::default();
+    let mut rng = StdRng::seed_from_u64(0);
 
     let true_mu = dvector![-1.5, 3.2];
     let true_cov = dmatrix![1.,-3./5.;-3./5.,2.];
@@ -202,6 +272,8 @@ pub fn test_beta() {
     approx::assert_abs_diff_eq!(-0.364406011717066, beta.logpdf(&0.3, (0.5, 0.5)), epsilon = LOGPDF_EPSILON);
     approx::assert_abs_diff_eq!(-0.06055443631298263, beta.logpdf(&0.7, (1.5, 2.0)), epsilon = LOGPDF_EPSILON);
     approx::assert_abs_diff_eq!(-0.36440601171706609, beta.logpdf(&0.3, (0.5, 0.5)), epsilon = LOGPDF_EPSILON);
+    assert_eq!(f64::NEG_INFINITY, beta.logpdf(&-0.1, (1.5, 2.0)));
+    assert_eq!(f64::NEG_INFINITY, beta.logpdf(&1.1, (0.5, 0.5)));
 }
 
 #[test]
@@ -209,4 +281,481 @@ pub fn test_gamma() {
     approx::assert_abs_diff_eq!(-1.414334369005868, gamma.logpdf(&1.7, (1.23, 1.46)), epsilon = LOGPDF_EPSILON);
     approx::assert_abs_diff_eq!(-3.4049256003700052, gamma.logpdf(&8.4, (4.5, 1.0)), epsilon = LOGPDF_EPSILON);
     approx::assert_abs_diff_eq!(-528.8122715889206, gamma.logpdf(&0.03, (50.0, 70.0)), epsilon = LOGPDF_EPSILON);
-}
\ No newline at end of file
+    assert_eq!(f64::NEG_INFINITY, gamma.logpdf(&-0.5, (0.5, 1.0)));
+    assert_eq!(f64::NEG_INFINITY, gamma.logpdf(&-0.5, (3.0, 1.0)));
+}
+
+#[test]
+pub fn test_exponential() {
+    let mut rng = StdRng::seed_from_u64(0);
+    approx::assert_abs_diff_eq!(-0.8337092681258449, exponential.logpdf(&0.7, 2.5), epsilon = LOGPDF_EPSILON);
+    assert_eq!(f64::NEG_INFINITY, exponential.logpdf(&-0.1, 2.5));
+    let samples = (0..50000).map(|_| exponential.random(&mut rng, 2.5)).collect::<Vec<f64>>();
+    approx::assert_abs_diff_eq!(mean(&samples), 0.4, epsilon = 0.01);
+}
+
+#[test]
+pub fn test_laplace() {
+    let mut rng = StdRng::seed_from_u64(0);
+    approx::assert_abs_diff_eq!(-2.5950036292457356, laplace.logpdf(&1.3, (-0.4, 0.8)), epsilon = LOGPDF_EPSILON);
+    let samples = (0..50000).map(|_| laplace.random(&mut rng, (-0.4, 0.8))).collect::<Vec<f64>>();
+    approx::assert_abs_diff_eq!(mean(&samples), -0.4, epsilon = 0.02);
+    approx::assert_abs_diff_eq!(variance(&samples), 2. * 0.8 * 0.8, epsilon = 0.05);
+    // the extremes of the underlying uniform draws stay finite
+    assert!(laplace.random(&mut StepRng::new(0, 0), (-0.4, 0.8)).is_finite());
+    assert!(laplace.random(&mut StepRng::new(u64::MAX, 0), (-0.4, 0.8)).is_finite());
+}
+
+#[test]
+pub fn test_cauchy() {
+    let mut rng = StdRng::seed_from_u64(0);
+    approx::assert_abs_diff_eq!(-3.188544249886085, cauchy.logpdf(&3.1, (1.2, 0.5)), epsilon = LOGPDF_EPSILON);
+    approx::assert_abs_diff_eq!(-2.0048029377253266, half_cauchy.logpdf(&2.2, 1.5), epsilon = LOGPDF_EPSILON);
+    assert_eq!(f64::NEG_INFINITY, half_cauchy.logpdf(&-2.2, 1.5));
+
+    // the cauchy has no mean, so compare quartiles
+    let mut samples = (0..50000).map(|_| cauchy.random(&mut rng, (1.2, 0.5))).collect::<Vec<f64>>();
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    approx::assert_abs_diff_eq!(samples[12500], 0.7, epsilon = 0.03);
+    approx::assert_abs_diff_eq!(samples[37500], 1.7, epsilon = 0.03);
+    assert!((0..1000).all(|_| half_cauchy.random(&mut rng, 1.5) >= 0.));
+}
+
+#[test]
+pub fn test_student_t() {
+    let mut rng = StdRng::seed_from_u64(0);
+    approx::assert_abs_diff_eq!(-1.9771596209591692, student_t.logpdf(&1.7, (3.5, 0.2, 1.3)), epsilon = LOGPDF_EPSILON);
+    // approaches the normal for large nu
+    approx::assert_abs_diff_eq!(-1.4189390332045894, student_t.logpdf(&50., (1e6, 49., 1.)), epsilon = 1e-10);
+    approx::assert_abs_diff_eq!(-0.9189385357046727, student_t.logpdf(&0., (1e8, 0., 1.)), epsilon = 1e-10);
+    let samples = (0..50000).map(|_| student_t.random(&mut rng, (5., 0.2, 1.3))).collect::<Vec<f64>>();
+    approx::assert_abs_diff_eq!(mean(&samples), 0.2, epsilon = 0.03);
+    approx::assert_abs_diff_eq!(variance(&samples), 1.3 * 1.3 * 5. / 3., epsilon = 0.15);
+}
+
+#[test]
+pub fn test_lognormal() {
+    let mut rng = StdRng::seed_from_u64(0);
+    approx::assert_abs_diff_eq!(-1.4793827200288763, lognormal.logpdf(&2.4, (0.5, 0.6)), epsilon = LOGPDF_EPSILON);
+    assert_eq!(f64::NEG_INFINITY, lognormal.logpdf(&0., (0.5, 0.6)));
+    let samples = (0..50000).map(|_| lognormal.random(&mut rng, (0.5, 0.6))).collect::<Vec<f64>>();
+    approx::assert_abs_diff_eq!(mean(&samples), (0.5 + 0.6 * 0.6 / 2_f64).exp(), epsilon = 0.02);
+}
+
+#[test]
+pub fn test_inv_gamma() {
+    let mut rng = StdRng::seed_from_u64(0);
+    approx::assert_abs_diff_eq!(-0.2692694758835647, inv_gamma.logpdf(&0.8, (3.2, 1.9)), epsilon = LOGPDF_EPSILON);
+    approx::assert_relative_eq!(6.681755593047479, inv_gamma.logpdf(&0.01, (400., 4.)), max_relative = 1e-10);
+    let samples = (0..50000).map(|_| inv_gamma.random(&mut rng, (3.2, 1.9))).collect::<Vec<f64>>();
+    approx::assert_abs_diff_eq!(mean(&samples), 1.9 / 2.2, epsilon = 0.02);
+}
+
+#[test]
+pub fn test_half_normal() {
+    let mut rng = StdRng::seed_from_u64(0);
+    approx::assert_abs_diff_eq!(-0.8965580120113961, half_normal.logpdf(&0.9, 1.7), epsilon = LOGPDF_EPSILON);
+    assert_eq!(f64::NEG_INFINITY, half_normal.logpdf(&-0.9, 1.7));
+    let samples = (0..50000).map(|_| half_normal.random(&mut rng, 1.7)).collect::<Vec<f64>>();
+    approx::assert_abs_diff_eq!(mean(&samples), 1.7 * (2. / std::f64::consts::PI).sqrt(), epsilon = 0.02);
+}
+
+#[test]
+pub fn test_binomial() {
+    let mut rng = StdRng::seed_from_u64(0);
+    approx::assert_abs_diff_eq!(-1.377455043361167, binomial.logpdf(&3, (10, 0.35)), epsilon = LOGPDF_EPSILON);
+    approx::assert_relative_eq!(-5.895080526478088, binomial.logpdf(&30000, (100000, 0.3)), max_relative = 1e-10);
+    approx::assert_abs_diff_eq!(0., binomial.logpdf(&0, (10, 0.)), epsilon = 1e-14);
+    assert_eq!(f64::NEG_INFINITY, binomial.logpdf(&11, (10, 0.35)));
+    let samples = (0..50000).map(|_| binomial.random(&mut rng, (10, 0.35)) as f64).collect::<Vec<f64>>();
+    approx::assert_abs_diff_eq!(mean(&samples), 3.5, epsilon = 0.03);
+    approx::assert_abs_diff_eq!(variance(&samples), 10. * 0.35 * 0.65, epsilon = 0.05);
+}
+
+#[test]
+pub fn test_neg_binomial() {
+    let mut rng = StdRng::seed_from_u64(0);
+    approx::assert_abs_diff_eq!(-2.134203965713074, neg_binomial.logpdf(&4, (2.5, 0.4)), epsilon = LOGPDF_EPSILON);
+    approx::assert_relative_eq!(-11.281654702175026, neg_binomial.logpdf(&1000000, (1000., 0.001)), max_relative = 1e-10);
+    // the number of failures before the first success is geometric
+    approx::assert_abs_diff_eq!(geometric.logpdf(&7, 0.3), neg_binomial.logpdf(&7, (1., 0.3)), epsilon = 1e-12);
+    let samples = (0..50000).map(|_| neg_binomial.random(&mut rng, (2.5, 0.4)) as f64).collect::<Vec<f64>>();
+    approx::assert_abs_diff_eq!(mean(&samples), 2.5 * 0.6 / 0.4, epsilon = 0.05);
+    approx::assert_abs_diff_eq!(variance(&samples), 2.5 * 0.6 / (0.4 * 0.4), epsilon = 0.3);
+}
+
+#[test]
+pub fn test_beta_binomial() {
+    let mut rng = StdRng::seed_from_u64(0);
+    approx::assert_abs_diff_eq!(-1.8739033333102163, beta_binomial.logpdf(&3, (10, 2., 3.5)), epsilon = LOGPDF_EPSILON);
+    approx::assert_relative_eq!(-5.726970439908287, beta_binomial.logpdf(&5000, (10000, 1000., 1000.)), max_relative = 1e-10);
+    // a uniform success probability gives uniform counts
+    approx::assert_abs_diff_eq!(-(11_f64.ln()), beta_binomial.logpdf(&4, (10, 1., 1.)), epsilon = 1e-12);
+    let samples = (0..50000).map(|_| beta_binomial.random(&mut rng, (10, 2., 3.5)) as f64).collect::<Vec<f64>>();
+    approx::assert_abs_diff_eq!(mean(&samples), 10. * 2. / 5.5, epsilon = 0.03);
+}
+
+#[test]
+pub fn test_hypergeometric() {
+    let mut rng = StdRng::seed_from_u64(0);
+    approx::assert_abs_diff_eq!(-1.329410060042762, hypergeometric.logpdf(&4, (50, 12, 15)), epsilon = LOGPDF_EPSILON);
+    approx::assert_relative_eq!(-3.587780654257812, hypergeometric.logpdf(&300, (100000, 1000, 30000)), max_relative = 1e-10);
+    assert_eq!(f64::NEG_INFINITY, hypergeometric.logpdf(&13, (50, 12, 15)));
+    assert_eq!(f64::NEG_INFINITY, hypergeometric.logpdf(&1, (10, 8, 5)));
+    let samples = (0..50000).map(|_| hypergeometric.random(&mut rng, (50, 12, 15)) as f64).collect::<Vec<f64>>();
+    approx::assert_abs_diff_eq!(mean(&samples), 15. * 12. / 50., epsilon = 0.03);
+}
+
+#[test]
+pub fn test_multinomial() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let probs = vec![0.2, 0.3, 0.5];
+    approx::assert_abs_diff_eq!(-2.0024805005437076, multinomial.logpdf(&vec![1, 2, 3], (6, probs.clone())), epsilon = LOGPDF_EPSILON);
+    assert_eq!(f64::NEG_INFINITY, multinomial.logpdf(&vec![1, 2, 2], (6, probs.clone())));
+    assert_eq!(f64::NEG_INFINITY, multinomial.logpdf(&vec![1, 5], (6, probs.clone())));
+    assert_eq!(f64::NEG_INFINITY, multinomial.logpdf(&vec![1, 0, 5], (6, vec![0.5, 0.5, 0.])));
+
+    let num_samples = 20000;
+    let mut totals = [0.; 3];
+    for _ in 0..num_samples {
+        let counts = multinomial.random(&mut rng, (6, probs.clone()));
+        assert_eq!(counts.iter().sum::<i64>(), 6);
+        for (total, k) in totals.iter_mut().zip(counts) {
+            *total += k as f64 / num_samples as f64;
+        }
+    }
+    for (total, p) in totals.iter().zip(&probs) {
+        approx::assert_abs_diff_eq!(*total, 6. * p, epsilon = 0.05);
+    }
+    let counts = multinomial.random(&mut rng, (6, vec![0.1, 0.9, 0., 0.]));
+    assert_eq!(counts.iter().sum::<i64>(), 6);
+    assert_eq!(counts[2..], [0, 0]);
+}
+
+#[test]
+pub fn test_dirichlet() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let alpha = dvector![1.5, 2., 3.];
+    approx::assert_abs_diff_eq!(1.6952109952695646, dirichlet.logpdf(&dvector![0.2, 0.3, 0.5], alpha.clone()), epsilon = LOGPDF_EPSILON);
+    assert_eq!(f64::NEG_INFINITY, dirichlet.logpdf(&dvector![0.2, 0.3, 0.6], alpha.clone()));
+    assert_eq!(f64::NEG_INFINITY, dirichlet.logpdf(&dvector![0.5, 0.5], alpha.clone()));
+    // a flat dirichlet is uniform over the simplex, which has volume 1/2
+    approx::assert_abs_diff_eq!(2_f64.ln(), dirichlet.logpdf(&dvector![0.1, 0.6, 0.3], dvector![1., 1., 1.]), epsilon = 1e-12);
+
+    let num_samples = 20000;
+    let mut total = DVector::zeros(3);
+    for _ in 0..num_samples {
+        let x = dirichlet.random(&mut rng, alpha.clone());
+        approx::assert_abs_diff_eq!(x.sum(), 1., epsilon = 1e-12);
+        total += x / num_samples as f64;
+    }
+    approx::assert_abs_diff_eq!(total, alpha.clone() / alpha.sum(), epsilon = 0.01);
+}
+
+#[test]
+pub fn test_wishart() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let x = dmatrix![2., 0.3; 0.3, 1.];
+    let scale = dmatrix![1., 0.2; 0.2, 0.5];
+    approx::assert_abs_diff_eq!(-3.3910404883715393, wishart.logpdf(&x, (4., scale.clone())), epsilon = LOGPDF_EPSILON);
+    approx::assert_abs_diff_eq!(-9.34466525503763, inv_wishart.logpdf(&x, (5., scale.clone())), epsilon = LOGPDF_EPSILON);
+    approx::assert_relative_eq!(11.41290099960495, wishart.logpdf(&DMatrix::identity(3, 3), (400., DMatrix::identity(3, 3) / 400.)), max_relative = 1e-10);
+    approx::assert_relative_eq!(11.41290099960495, inv_wishart.logpdf(&DMatrix::identity(3, 3), (400., DMatrix::identity(3, 3) * 400.)), max_relative = 1e-10);
+    assert_eq!(f64::NEG_INFINITY, wishart.logpdf(&dmatrix![1., 2.; 2., 1.], (4., scale.clone())));
+    // a single dimension is a (scaled) chi-squared
+    approx::assert_abs_diff_eq!(gamma.logpdf(&1.7, (2.5, 1.2)), wishart.logpdf(&dmatrix![1.7], (5., dmatrix![0.6])), epsilon = 1e-12);
+
+    let num_samples = 20000;
+    let (mut wishart_mean, mut inv_wishart_mean) = (DMatrix::zeros(2, 2), DMatrix::zeros(2, 2));
+    for _ in 0..num_samples {
+        let w = wishart.random(&mut rng, (4., scale.clone()));
+        assert!(w.clone().cholesky().is_some());
+        wishart_mean += w / num_samples as f64;
+        inv_wishart_mean += inv_wishart.random(&mut rng, (6., scale.clone())) / num_samples as f64;
+    }
+    approx::assert_abs_diff_eq!(wishart_mean, scale.clone() * 4., epsilon = 0.1);
+    approx::assert_abs_diff_eq!(inv_wishart_mean, scale / 3., epsilon = 0.02);
+}
+
+#[test]
+pub fn test_lkj_cholesky() {
+    let mut rng = StdRng::seed_from_u64(0);
+    // with 3 dimensions and eta = 1, correlations are uniform over a volume of pi^2/2,
+    // and the jacobian from the off-diagonal of the cholesky factor is L[1,1]
+    let l = DMatrix::from_row_slice(3, 3, &[1., 0., 0., 0.6, 0.8, 0., -0.3, 0.4, 0.75_f64.sqrt()]);
+    approx::assert_abs_diff_eq!((2. / (std::f64::consts::PI * std::f64::consts::PI)).ln() + 0.8_f64.ln(), lkj_cholesky.logpdf(&l, (3, 1.)), epsilon = 1e-12);
+
+    // with 2 dimensions the correlation is a scaled beta(eta, eta)
+    let l = dmatrix![1., 0.; -0.4, 0.84_f64.sqrt()];
+    approx::assert_abs_diff_eq!(beta.logpdf(&0.3, (2.5, 2.5)) - 2_f64.ln(), lkj_cholesky.logpdf(&l, (2, 2.5)), epsilon = 1e-12);
+    assert_eq!(f64::NEG_INFINITY, lkj_cholesky.logpdf(&dmatrix![1., 0.; -0.4, 0.8], (2, 2.5)));
+    assert_eq!(f64::NEG_INFINITY, lkj_cholesky.logpdf(&dmatrix![1., 0.; -0.4, -(0.84_f64.sqrt())], (2, 2.5)));
+    approx::assert_abs_diff_eq!(0., lkj_cholesky.logpdf(&dmatrix![1.], (1, 2.)), epsilon = 1e-12);
+
+    // each correlation is marginally a scaled beta(eta - 1 + dim/2, eta - 1 + dim/2), with variance 1/(2 eta + dim - 1)
+    let (dim, eta) = (4, 0.5);
+    let num_samples = 20000;
+    let mut variances = DMatrix::zeros(dim, dim);
+    for _ in 0..num_samples {
+        let l = lkj_cholesky.random(&mut rng, (dim, eta));
+        assert!(lkj_cholesky.logpdf(&l, (dim, eta)).is_finite());
+        let corr = &l * l.transpose();
+        approx::assert_abs_diff_eq!(corr.diagonal(), DVector::from_element(dim, 1.), epsilon = 1e-12);
+        variances += corr.map(|r| r * r) / num_samples as f64;
+    }
+    for i in 0..dim {
+        for j in 0..i {
+            approx::assert_abs_diff_eq!(variances[(i, j)], 1. / (2. * eta + dim as f64 - 1.), epsilon = 0.015);
+        }
+    }
+}
+
+#[test]
+pub fn test_log_space_helpers() {
+    approx::assert_relative_eq!(0.5723649429247001, ln_gamma(0.5), max_relative = 1e-13);
+    approx::assert_relative_eq!(23.025850929882735, ln_gamma(1e-10), max_relative = 1e-13);
+    approx::assert_relative_eq!(6.907178885383854, ln_gamma(1e-3), max_relative = 1e-13);
+    approx::assert_relative_eq!(2.252712651734206, ln_gamma(0.1), max_relative = 1e-13);
+    approx::assert_relative_eq!(1.2009736023470743, ln_gamma(3.5), max_relative = 1e-13);
+    approx::assert_relative_eq!(701.437263808737, ln_gamma(170.), max_relative = 1e-13);
+    approx::assert_relative_eq!(709.1431630309282, ln_gamma(171.5), max_relative = 1e-13);
+    approx::assert_relative_eq!(-0.05624371649767405, ln_gamma(-2.5), max_relative = 1e-12);
+    approx::assert_relative_eq!(5905.220423209181, ln_gamma(1000.), max_relative = 1e-13);
+    approx::assert_relative_eq!(12815504.569147612, ln_gamma(1e6), max_relative = 1e-13);
+    approx::assert_abs_diff_eq!(0., ln_gamma(1.), epsilon = 1e-14);
+    approx::assert_abs_diff_eq!(0., ln_gamma(2.), epsilon = 1e-14);
+
+    approx::assert_relative_eq!(1.1447298858494002, ln_beta(0.5, 0.5), max_relative = 1e-13);
+    approx::assert_relative_eq!(-1388.4826016359023, ln_beta(1000., 1000.), max_relative = 1e-12);
+    approx::assert_relative_eq!(6.895665964913892, ln_beta(1e-3, 1e5), max_relative = 1e-11);
+
+    approx::assert_abs_diff_eq!(0., ln_factorial(0), epsilon = 1e-14);
+    approx::assert_relative_eq!(120_f64.ln(), ln_factorial(5), max_relative = 1e-13);
+    approx::assert_relative_eq!(5912.128178488163, ln_factorial(1000), max_relative = 1e-13);
+}
+
+#[test]
+pub fn test_extreme_parameters() {
+    // reference values at parameters where the densities overflow in linear space
+    approx::assert_relative_eq!(3.574534877131522, beta.logpdf(&0.5, (1000., 1000.)), max_relative = 1e-10);
+    approx::assert_relative_eq!(-3.280831810346357, gamma.logpdf(&150., (200., 0.75)), max_relative = 1e-10);
+    approx::assert_relative_eq!(-5.177551795579436, poisson.logpdf(&5000, 5000.), max_relative = 1e-10);
+    approx::assert_relative_eq!(-14.815511057964607, geometric.logpdf(&1000000, 1e-6), max_relative = 1e-10);
+    approx::assert_relative_eq!(-1386.9875083004506, geometric.logpdf(&2000, 0.5), max_relative = 1e-12);
+
+    // boundaries of the support
+    approx::assert_abs_diff_eq!(0., beta.logpdf(&0., (1., 1.)), epsilon = 1e-14);
+    approx::assert_abs_diff_eq!(-2_f64.ln(), gamma.logpdf(&0., (1., 2.)), epsilon = 1e-14);
+    assert_eq!(f64::NEG_INFINITY, poisson.logpdf(&-1, 2.));
+    assert_eq!(f64::NEG_INFINITY, geometric.logpdf(&-1, 0.5));
+
+    // the determinant of a high-dimensional covariance underflows
+    let k = 400;
+    let x = nalgebra::DVector::from_element(k, 0.1);
+    let cov = nalgebra::DMatrix::<f64>::identity(k, k) * 0.01;
+    let expected = -(k as f64) * ((2. * std::f64::consts::PI).ln() + 0.01_f64.ln() + 1.) / 2.;
+    approx::assert_relative_eq!(expected, mvnormal.logpdf(&x, (nalgebra::DVector::zeros(k), cov)), max_relative = 1e-12);
+}
+
+#[test]
+pub fn test_check_params() {
+    assert!(bernoulli.check_params(&0.3).is_ok());
+    assert!(matches!(bernoulli.check_params(&1.2), Err(Error::InvalidParameters(_))));
+    assert!(normal.check_params(&(0., 1.)).is_ok());
+    assert!(normal.check_params(&(0., -1.)).is_err());
+    assert!(normal.check_params(&(0., f64::NAN)).is_err());
+    assert!(beta.check_params(&(0., 1.)).is_err());
+    assert!(gamma.check_params(&(1., 0.)).is_err());
+    assert!(poisson.check_params(&0.).is_err());
+    assert!(geometric.check_params(&1.).is_err());
+    assert!(uniform.check_params(&(1., 0.)).is_err());
+    assert!(uniform_discrete.check_params(&(0, 3)).is_ok());
+    assert!(categorical.check_params(&vec![0.2, 0.8]).is_ok());
+    assert!(categorical.check_params(&vec![0.2, 0.7]).is_err());
+    assert!(categorical.check_params(&vec![-0.2, 1.2]).is_err());
+    assert!(categorical_logits.check_params(&vec![-3., f64::NEG_INFINITY, 20.]).is_ok());
+    assert!(categorical_logits.check_params(&vec![f64::NEG_INFINITY, f64::NEG_INFINITY]).is_err());
+    assert!(categorical_logits.check_params(&vec![0., f64::INFINITY]).is_err());
+    assert!(categorical_logits.check_params(&vec![0., f64::NAN]).is_err());
+    assert!(mvnormal.check_params(&(dvector![0., 0.], dmatrix![1., 0.; 0., 1.])).is_ok());
+    assert!(mvnormal.check_params(&(dvector![0., 0., 0.], dmatrix![1., 0.; 0., 1.])).is_err());
+    assert!(exponential.check_params(&0.).is_err());
+    assert!(laplace.check_params(&(0., -1.)).is_err());
+    assert!(cauchy.check_params(&(0., 0.)).is_err());
+    assert!(half_cauchy.check_params(&1.).is_ok());
+    assert!(student_t.check_params(&(0., 0., 1.)).is_err());
+    assert!(student_t.check_params(&(1., 0., 0.)).is_err());
+    assert!(lognormal.check_params(&(0., 0.)).is_err());
+    assert!(inv_gamma.check_params(&(1., -1.)).is_err());
+    assert!(half_normal.check_params(&f64::NAN).is_err());
+    assert!(binomial.check_params(&(-1, 0.5)).is_err());
+    assert!(binomial.check_params(&(3, 1.5)).is_err());
+    assert!(neg_binomial.check_params(&(2., 1.)).is_err());
+    assert!(beta_binomial.check_params(&(3, 1., 0.)).is_err());
+    assert!(hypergeometric.check_params(&(10, 4, 10)).is_ok());
+    assert!(hypergeometric.check_params(&(10, 11, 3)).is_err());
+    assert!(hypergeometric.check_params(&(10, 4, 11)).is_err());
+    assert!(multinomial.check_params(&(3, vec![0.2, 0.8])).is_ok());
+    assert!(multinomial.check_params(&(-3, vec![0.2, 0.8])).is_err());
+    assert!(multinomial.check_params(&(3, vec![0.2, 0.7])).is_err());
+    assert!(dirichlet.check_params(&dvector![1., 0.5]).is_ok());
+    assert!(dirichlet.check_params(&dvector![1., 0.]).is_err());
+    assert!(wishart.check_params(&(2.5, dmatrix![1., 0.; 0., 1.])).is_ok());
+    assert!(wishart.check_params(&(0.5, dmatrix![1., 0.; 0., 1.])).is_err());
+    assert!(wishart.check_params(&(3., dmatrix![1., 2.; 2., 1.])).is_err());
+    assert!(inv_wishart.check_params(&(3., DMatrix::zeros(2, 3))).is_err());
+    assert!(lkj_cholesky.check_params(&(3, 1.)).is_ok());
+    assert!(lkj_cholesky.check_params(&(0, 1.)).is_err());
+    assert!(lkj_cholesky.check_params(&(3, 0.)).is_err());
+}
+
+fn assert_grad_matches_central_difference(f: impl Fn(&[f64]) -> f64, x: &[f64], grad: &[f64]) {
+    let h = 1e-6;
+    for i in 0..x.len() {
+        let mut x_plus = x.to_vec();
+        x_plus[i] += h;
+        let mut x_minus = x.to_vec();
+        x_minus[i] -= h;
+        approx::assert_abs_diff_eq!(grad[i], (f(&x_plus) - f(&x_minus))/(2.*h), epsilon = 1e-5);
+    }
+}
+
+// perturb symmetric pairs of entries, whose derivative is the sum of the gradient at both entries
+fn assert_symmetric_grad_matches_central_difference(f: impl Fn(&DMatrix<f64>) -> f64, x: &DMatrix<f64>, grad: &DMatrix<f64>) {
+    let h = 1e-6;
+    for i in 0..x.nrows() {
+        for j in 0..=i {
+            let mut dx = DMatrix::zeros(x.nrows(), x.ncols());
+            dx[(i, j)] = h;
+            dx[(j, i)] = h;
+            let expected = if i == j { grad[(i, i)] } else { grad[(i, j)] + grad[(j, i)] };
+            approx::assert_abs_diff_eq!(expected, (f(&(x + &dx)) - f(&(x - &dx)))/(2.*h), epsilon = 1e-5);
+        }
+    }
+}
+
+fn assert_wishart_grad_matches_central_difference(dist: &impl Distribution<DMatrix<f64>,(f64,DMatrix<f64>)>, x: &DMatrix<f64>, df: f64, scale: &DMatrix<f64>) {
+    let (d_x, (d_df, d_scale)) = dist.logpdf_grad(x, (df, scale.clone())).unwrap();
+    assert_grad_matches_central_difference(|v| dist.logpdf(x, (v[0], scale.clone())), &[df], &[d_df]);
+    assert_symmetric_grad_matches_central_difference(|m| dist.logpdf(m, (df, scale.clone())), x, &d_x);
+    assert_symmetric_grad_matches_central_difference(|m| dist.logpdf(x, (df, m.clone())), scale, &d_scale);
+}
+
+#[test]
+pub fn test_logpdf_grad() {
+    let (d_x, (d_mu, d_std)) = normal.logpdf_grad(&0.4, (-0.3, 1.7)).unwrap();
+    assert_grad_matches_central_difference(|v| normal.logpdf(&v[0], (v[1], v[2])), &[0.4, -0.3, 1.7], &[d_x, d_mu, d_std]);
+
+    let (d_x, (d_a, d_b)) = beta.logpdf_grad(&0.3, (1.5, 2.5)).unwrap();
+    assert_grad_matches_central_difference(|v| beta.logpdf(&v[0], (v[1], v[2])), &[0.3, 1.5, 2.5], &[d_x, d_a, d_b]);
+
+    let (d_x, (d_a, d_b)) = gamma.logpdf_grad(&1.7, (1.23, 1.46)).unwrap();
+    assert_grad_matches_central_difference(|v| gamma.logpdf(&v[0], (v[1], v[2])), &[1.7, 1.23, 1.46], &[d_x, d_a, d_b]);
+
+    let (d_x, d_rate) = exponential.logpdf_grad(&0.7, 2.5).unwrap();
+    assert_grad_matches_central_difference(|v| exponential.logpdf(&v[0], v[1]), &[0.7, 2.5], &[d_x, d_rate]);
+    assert!(exponential.logpdf_grad(&-0.1, 2.5).is_none());
+
+    let (d_x, (d_loc, d_scale)) = laplace.logpdf_grad(&1.3, (-0.4, 0.8)).unwrap();
+    assert_grad_matches_central_difference(|v| laplace.logpdf(&v[0], (v[1], v[2])), &[1.3, -0.4, 0.8], &[d_x, d_loc, d_scale]);
+
+    let (d_x, (d_loc, d_scale)) = cauchy.logpdf_grad(&3.1, (1.2, 0.5)).unwrap();
+    assert_grad_matches_central_difference(|v| cauchy.logpdf(&v[0], (v[1], v[2])), &[3.1, 1.2, 0.5], &[d_x, d_loc, d_scale]);
+
+    let (d_x, d_scale) = half_cauchy.logpdf_grad(&2.2, 1.5).unwrap();
+    assert_grad_matches_central_difference(|v| half_cauchy.logpdf(&v[0], v[1]), &[2.2, 1.5], &[d_x, d_scale]);
+
+    let (d_x, (d_nu, d_loc, d_scale)) = student_t.logpdf_grad(&1.7, (3.5, 0.2, 1.3)).unwrap();
+    assert_grad_matches_central_difference(|v| student_t.logpdf(&v[0], (v[1], v[2], v[3])), &[1.7, 3.5, 0.2, 1.3], &[d_x, d_nu, d_loc, d_scale]);
+
+    let (d_x, (d_mu, d_std)) = lognormal.logpdf_grad(&2.4, (0.5, 0.6)).unwrap();
+    assert_grad_matches_central_difference(|v| lognormal.logpdf(&v[0], (v[1], v[2])), &[2.4, 0.5, 0.6], &[d_x, d_mu, d_std]);
+
+    let (d_x, (d_a, d_b)) = inv_gamma.logpdf_grad(&0.8, (3.2, 1.9)).unwrap();
+    assert_grad_matches_central_difference(|v| inv_gamma.logpdf(&v[0], (v[1], v[2])), &[0.8, 3.2, 1.9], &[d_x, d_a, d_b]);
+
+    let (d_x, d_std) = half_normal.logpdf_grad(&0.9, 1.7).unwrap();
+    assert_grad_matches_central_difference(|v| half_normal.logpdf(&v[0], v[1]), &[0.9, 1.7], &[d_x, d_std]);
+
+    let x = dvector![0.2, 0.3, 0.5];
+    let (d_x, d_alpha) = dirichlet.logpdf_grad(&x, dvector![1.5, 2., 3.]).unwrap();
+    approx::assert_abs_diff_eq!(d_x, dvector![0.5 / 0.2, 1. / 0.3, 2. / 0.5], epsilon = 1e-12);
+    assert_grad_matches_central_difference(|v| dirichlet.logpdf(&x, DVector::from_column_slice(v)), &[1.5, 2., 3.], d_alpha.as_slice());
+
+    let x = dmatrix![2., 0.3; 0.3, 1.];
+    let scale = dmatrix![1., 0.2; 0.2, 0.5];
+    assert_wishart_grad_matches_central_difference(&wishart, &x, 5., &scale);
+    assert_wishart_grad_matches_central_difference(&inv_wishart, &x, 5., &scale);
+
+    let (d_x, (d_a, d_b)) = uniform.logpdf_grad(&0.2, (-1., 3.)).unwrap();
+    assert_grad_matches_central_difference(|v| uniform.logpdf(&v[0], (v[1], v[2])), &[0.2, -1., 3.], &[d_x, d_a, d_b]);
+
+    let x = dvector![0.5, -1.];
+    let mu = dvector![0.1, 0.2];
+    let cov = dmatrix![1., 0.3; 0.3, 0.5];
+    let (d_x, (d_mu, d_cov)) = mvnormal.logpdf_grad(&x, (mu.clone(), cov.clone())).unwrap();
+    let mvnormal_logpdf = |v: &[f64]| mvnormal.logpdf(
+        &dvector![v[0], v[1]],
+        (dvector![v[2], v[3]], dmatrix![v[4], v[5]; v[5], v[6]])
+    );
+    let d_cov_sym = [d_cov[(0,0)], d_cov[(0,1)] + d_cov[(1,0)], d_cov[(1,1)]];
+    assert_grad_matches_central_difference(mvnormal_logpdf,
+        &[x[0], x[1], mu[0], mu[1], cov[(0,0)], cov[(0,1)], cov[(1,1)]],
+        &[d_x[0], d_x[1], d_mu[0], d_mu[1], d_cov_sym[0], d_cov_sym[1], d_cov_sym[2]]
+    );
+
+    assert!(bernoulli.logpdf_grad(&true, 0.5).is_none());
+}
+
+#[test]
+pub fn test_logpdf_grad_outside_support() {
+    assert!(uniform.logpdf_grad(&3.5, (-1., 3.)).is_none());
+    assert!(uniform.logpdf_grad(&-1.5, (-1., 3.)).is_none());
+    assert!(beta.logpdf_grad(&1.2, (1.5, 2.5)).is_none());
+    assert!(beta.logpdf_grad(&-0.2, (1.5, 2.5)).is_none());
+    assert!(gamma.logpdf_grad(&-1.7, (1.23, 1.46)).is_none());
+    assert!(inv_gamma.logpdf_grad(&-0.8, (3.2, 1.9)).is_none());
+    assert!(lognormal.logpdf_grad(&-2.4, (0.5, 0.6)).is_none());
+    assert!(half_normal.logpdf_grad(&-0.9, 1.7).is_none());
+    assert!(half_cauchy.logpdf_grad(&-2.2, 1.5).is_none());
+    assert!(dirichlet.logpdf_grad(&dvector![1.2, -0.2], dvector![1.5, 2.]).is_none());
+
+    // singular covariance
+    let cov = dmatrix![1., 1.; 1., 1.];
+    assert!(mvnormal.logpdf_grad(&dvector![0.5, -1.], (dvector![0., 0.], cov)).is_none());
+}
+
+#[test]
+pub fn test_support() {
+    assert_eq!(bernoulli.support(&0.3), Some(vec![false, true]));
+    assert_eq!(bernoulli.support(&1.), Some(vec![true]));
+    assert_eq!(categorical.support(&vec![0.2, 0., 0.8]), Some(vec![0, 2]));
+    assert_eq!(categorical_logits.support(&vec![-0.2, f64::NEG_INFINITY, 4.8]), Some(vec![0, 2]));
+    assert_eq!(uniform_discrete.support(&(-1, 2)), Some(vec![-1, 0, 1, 2]));
+
+    // supports sum to one
+    let probs = vec![0.1, 0.6, 0.3];
+    let total = categorical.support(&probs).unwrap().iter().map(|x| categorical.logpdf(x, probs.clone()).exp()).sum::<f64>();
+    approx::assert_abs_diff_eq!(total, 1., epsilon = 1e-12);
+
+    assert_eq!(binomial.support(&(3, 0.5)), Some(vec![0, 1, 2, 3]));
+    assert_eq!(binomial.support(&(3, 1.)), Some(vec![3]));
+    assert_eq!(beta_binomial.support(&(2, 1., 1.)), Some(vec![0, 1, 2]));
+    assert_eq!(hypergeometric.support(&(10, 8, 5)), Some(vec![3, 4, 5]));
+    assert_eq!(multinomial.support(&(2, vec![0.5, 0., 0.5])), Some(vec![vec![0, 0, 2], vec![1, 0, 1], vec![2, 0, 0]]));
+
+    // discrete supports sum to one
+    let total = |logpdfs: Vec<f64>| logpdfs.iter().map(|l| l.exp()).sum::<f64>();
+    let params = (12, 0.3);
+    approx::assert_abs_diff_eq!(total(binomial.support(&params).unwrap().iter().map(|k| binomial.logpdf(k, params)).collect()), 1., epsilon = 1e-12);
+    let params = (12, 0.7, 2.1);
+    approx::assert_abs_diff_eq!(total(beta_binomial.support(&params).unwrap().iter().map(|k| beta_binomial.logpdf(k, params)).collect()), 1., epsilon = 1e-12);
+    let params = (30, 11, 14);
+    approx::assert_abs_diff_eq!(total(hypergeometric.support(&params).unwrap().iter().map(|k| hypergeometric.logpdf(k, params)).collect()), 1., epsilon = 1e-12);
+    let params = (5, vec![0.1, 0.2, 0.3, 0.4]);
+    approx::assert_abs_diff_eq!(total(multinomial.support(&params).unwrap().iter().map(|k| multinomial.logpdf(k, params.clone())).collect()), 1., epsilon = 1e-12);
+
+    assert!(normal.support(&(0., 1.)).is_none());
+    assert!(neg_binomial.support(&(2., 0.5)).is_none());
+    assert!(poisson.support(&3.).is_none());
+}
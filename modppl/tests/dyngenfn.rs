@@ -1,3 +1,7 @@
+#![allow(non_snake_case)]
+#![allow(non_upper_case_globals)]
+
+use nalgebra::{DVector,DMatrix};
 use modppl::prelude::*;
 
 mod pointed_model;
@@ -6,7 +10,7 @@ mod dyngenfns;
 
 pub fn _DynGenFn_prototype(state: &mut DynGenFnHandler<f64,f64>,noise: f64) -> f64 {
     let mut sum = 0.;
-    for i in (1..3000).into_iter() {
+    for i in 1..3000 {
         let x = state.sample_at(&normal, (1., noise), &format!("{}", i));
         sum += x;
     }
@@ -16,12 +20,13 @@ const DynGenFn_prototype: DynGenFn<f64,f64> = DynGenFn { func: _DynGenFn_prototy
 
 #[test]
 pub fn test_DynGenFn_prototype() {
-    for _ in (0..100).into_iter() {
-        let _trace = DynGenFn_prototype.simulate(1.);
+    let mut rng = ThreadRng::default();
+    for _ in 0..100 {
+        let _trace = DynGenFn_prototype.simulate(&mut rng, 1.);
         let mut constraints = DynTrie::new();
         constraints.observe("1", Arc::new(100.));
         constraints.observe("5", Arc::new(200.));
-        let (trace, weight) = DynGenFn_prototype.generate(0.1, constraints);
+        let (trace, weight) = DynGenFn_prototype.generate(&mut rng, 0.1, constraints);
         approx::assert_abs_diff_eq!(trace.retv.unwrap(), 3298., epsilon = 50.);
         dbg!(trace.logjp);
         dbg!(weight);
@@ -54,80 +59,163 @@ const DynGenFn_sample_at_update_weight_regression2: DynGenFn<(),()> = DynGenFn {
 
 #[test]
 pub fn test_sample_at_update_prev_and_constrained() {
+    let mut rng = ThreadRng::default();
     // sample_at
     let mut constraints = DynTrie::new();
     constraints.observe("b", Arc::new(true));
     constraints.observe("x", Arc::new(0.0));
-    let tr = DynGenFn_sample_at_update_weight_regression.generate((), constraints).0;
+    let tr = DynGenFn_sample_at_update_weight_regression.generate(&mut rng, (), constraints).0;
     let mut constraints = DynTrie::new();
     constraints.observe("x", Arc::new(1.0));
-    let w = DynGenFn_sample_at_update_weight_regression.update(tr, (), modppl::ArgDiff::Unknown, constraints).2;
+    let w = DynGenFn_sample_at_update_weight_regression.update(&mut rng, tr, (), modppl::ArgDiff::Unknown, constraints).2;
     assert_eq!(w, -0.5);
 }
 
 #[test]
 pub fn test_sample_at_update_no_prev_and_constrained() {
+    let mut rng = ThreadRng::default();
     // sample_at
     let mut constraints = DynTrie::new();
     constraints.observe("b", Arc::new(false));
-    let tr = DynGenFn_sample_at_update_weight_regression.generate((), constraints).0;
+    let tr = DynGenFn_sample_at_update_weight_regression.generate(&mut rng, (), constraints).0;
     let mut constraints = DynTrie::new();
     constraints.observe("b", Arc::new(true));
     constraints.observe("x", Arc::new(1.0));
-    let w = DynGenFn_sample_at_update_weight_regression.update(tr, (), modppl::ArgDiff::Unknown, constraints).2;
+    let w = DynGenFn_sample_at_update_weight_regression.update(&mut rng, tr, (), modppl::ArgDiff::Unknown, constraints).2;
     approx::assert_abs_diff_eq!(w, -2.517551, epsilon = 1e-6);
 }
 
 #[test]
 pub fn test_update_sample_at_prev_and_unconstrained() {
+    let mut rng = ThreadRng::default();
     // sample_at
     let mut constraints = DynTrie::new();
     constraints.observe("m", Arc::new(1.0));
     constraints.observe("x", Arc::new(1.0));
     constraints.observe("y", Arc::new(-0.3));
-    let tr = DynGenFn_sample_at_update_weight_regression2.generate((), constraints).0;
+    let tr = DynGenFn_sample_at_update_weight_regression2.generate(&mut rng, (), constraints).0;
     let mut constraints = DynTrie::new();
     constraints.observe("m", Arc::new(0.5));
-    let w = DynGenFn_sample_at_update_weight_regression2.update(tr, (), modppl::ArgDiff::Unknown, constraints).2;
+    let w = DynGenFn_sample_at_update_weight_regression2.update(&mut rng, tr, (), modppl::ArgDiff::Unknown, constraints).2;
     approx::assert_abs_diff_eq!(w, 0.4000000, epsilon = 1e-6);
 }
 
 #[test]
 pub fn test_update_no_prev_and_unconstrained() {
+    let mut rng = ThreadRng::default();
     // sample_at
     let mut constraints = DynTrie::new();
     constraints.observe("b", Arc::new(false));
-    let tr = DynGenFn_sample_at_update_weight_regression.generate((), constraints).0;
+    let tr = DynGenFn_sample_at_update_weight_regression.generate(&mut rng, (), constraints).0;
     let mut constraints = DynTrie::new();
     constraints.observe("b", Arc::new(true));
-    let w = DynGenFn_sample_at_update_weight_regression.update(tr, (), modppl::ArgDiff::Unknown, constraints).2;
+    let w = DynGenFn_sample_at_update_weight_regression.update(&mut rng, tr, (), modppl::ArgDiff::Unknown, constraints).2;
     approx::assert_abs_diff_eq!(w, -1.098612, epsilon = 1e-6);
 
     // trace_at
     let mut constraints = DynTrie::new();
     constraints.observe("b", Arc::new(false));
-    let tr = DynGenFn_trace_at_update_weight_regression.generate((), constraints).0;
+    let tr = DynGenFn_trace_at_update_weight_regression.generate(&mut rng, (), constraints).0;
     let mut constraints = DynTrie::new();
     constraints.observe("b", Arc::new(true));
-    let w = DynGenFn_trace_at_update_weight_regression.update(tr, (), modppl::ArgDiff::Unknown, constraints).2;
+    let w = DynGenFn_trace_at_update_weight_regression.update(&mut rng, tr, (), modppl::ArgDiff::Unknown, constraints).2;
     approx::assert_abs_diff_eq!(w, -1.098612, epsilon = 1e-6);
 }
 
 #[test]
 #[should_panic]
 pub fn test_generate_residual_constraints_panic() {
+    let mut rng = ThreadRng::default();
     let mut constraints = DynTrie::new();
     constraints.observe("abc", Arc::new(0.));
-    DynGenFn_prototype.generate(0.1, constraints);
+    DynGenFn_prototype.generate(&mut rng, 0.1, constraints);
 }
 
 #[test]
 #[should_panic]
 pub fn test_update_residual_constraints_panic() {
+    let mut rng = ThreadRng::default();
     let mut constraints = DynTrie::new();
     constraints.observe("abc", Arc::new(0.));
-    let trace = DynGenFn_prototype.simulate(0.1);
-    DynGenFn_prototype.update(trace, 0.1, ArgDiff::NoChange, constraints);
+    let trace = DynGenFn_prototype.simulate(&mut rng, 0.1);
+    DynGenFn_prototype.update(&mut rng, trace, 0.1, ArgDiff::NoChange, constraints);
+}
+
+#[test]
+pub fn test_try_generate_residual_constraints() {
+    let mut rng = ThreadRng::default();
+    let mut constraints = DynTrie::new();
+    constraints.observe("abc", Arc::new(0.));
+    constraints.observe("1", Arc::new(0.));
+    let Err(Error::UnconsumedConstraints(residual)) = DynGenFn_prototype.try_generate(&mut rng, 0.1, constraints) else {
+        panic!("expected residual constraints");
+    };
+    assert!(residual.search("abc").is_some());
+    assert!(residual.search("1").is_none());
+
+    let mut constraints = DynTrie::new();
+    constraints.observe("abc", Arc::new(0.));
+    let trace = DynGenFn_prototype.simulate(&mut rng, 0.1);
+    assert!(matches!(
+        DynGenFn_prototype.try_update(&mut rng, trace, 0.1, ArgDiff::NoChange, constraints),
+        Err(Error::UnconsumedConstraints(_))
+    ));
+}
+
+#[test]
+pub fn test_try_generate_type_mismatch() {
+    let mut rng = ThreadRng::default();
+    let mut constraints = DynTrie::new();
+    constraints.observe("1", Arc::new(true));
+    let result = DynGenFn_prototype.try_generate(&mut rng, 0.1, constraints);
+    assert_eq!(result.err(), Some(Error::TypeMismatch { addr: String::from("1"), expected: "f64" }));
+}
+
+#[test]
+pub fn test_try_simulate_invalid_parameters() {
+    let mut rng = ThreadRng::default();
+    assert!(matches!(DynGenFn_prototype.try_simulate(&mut rng, -1.), Err(Error::InvalidParameters(_))));
+    assert!(DynGenFn_prototype.try_simulate(&mut rng, 1.).is_ok());
+}
+
+#[test]
+pub fn test_try_generate_returns_first_error() {
+    let mut rng = ThreadRng::default();
+    let mut constraints = DynTrie::new();
+    constraints.observe("ys / 1", Arc::new(true));
+    constraints.observe("ys / 2", Arc::new(0_i64));
+    let result = dyngenfns::line_model.try_generate(&mut rng, vec![0., 1., 2.], constraints);
+    assert_eq!(result.err(), Some(Error::TypeMismatch { addr: String::from("1"), expected: "f64" }));
+}
+
+dyngen!(
+fn scaled_choice(std: f64) -> f64 {
+    normal(0., std) %= "x"
+});
+
+dyngen!(
+fn nested_scaled_choice(std: f64) -> f64 {
+    let x = scaled_choice(std) /= "sub";
+    normal(x, 1.) %= "y"
+});
+
+#[test]
+pub fn test_try_simulate_nested_error() {
+    let mut rng = StdRng::seed_from_u64(0);
+    assert!(matches!(nested_scaled_choice.try_simulate(&mut rng, -1.), Err(Error::InvalidParameters(_))));
+    let mut constraints = DynTrie::new();
+    constraints.observe("y", Arc::new(0.5));
+    assert!(matches!(nested_scaled_choice.try_generate(&mut rng, -1., constraints), Err(Error::InvalidParameters(_))));
+    assert!(nested_scaled_choice.try_simulate(&mut rng, 1.).is_ok());
+}
+
+#[test]
+pub fn test_try_read() {
+    let mut rng = ThreadRng::default();
+    let trace = DynGenFn_prototype.simulate(&mut rng, 0.1);
+    assert!(trace.data.try_read::<f64>("1").is_ok());
+    assert_eq!(trace.data.try_read::<f64>("0"), Err(Error::MissingAddress(String::from("0"))));
+    assert_eq!(trace.data.try_read::<bool>("1"), Err(Error::TypeMismatch { addr: String::from("1"), expected: "bool" }));
 }
 
 dyngen!(
@@ -153,11 +241,12 @@ fn proposal(tr: Weak<DynTrace<(),f64>>, drift: f64, addr: String) {
 
 #[test]
 pub fn test_parse() {
+    let mut rng = ThreadRng::default();
     let mut constraints = DynTrie::new();
     constraints.observe("y", Arc::new(0.3));
-    let mut tr = model.simulate(());
+    let mut tr = model.simulate(&mut rng, ());
     for _ in 0..1000 {
-        let (new_tr, accepted) = mh(&model, tr, &proposal, (0.5,String::from("var/prob_is_small")));
+        let (new_tr, accepted) = mh(&mut rng, &model, tr, &proposal, (0.5,String::from("var/prob_is_small")));
         dbg!(accepted);
         tr = new_tr;
     }
@@ -165,20 +254,86 @@ pub fn test_parse() {
 
 #[test]
 pub fn test_simulate() {
+    let mut rng = ThreadRng::default();
     dyngen!(
     fn foo(p: f64) -> bool {
         bernoulli(p) %= "x"
     });
 
     let p = 0.4;
-    let trace = foo.simulate(p);
+    let trace = foo.simulate(&mut rng, p);
     assert_eq!(trace.data.read::<bool>("x"), trace.retv.unwrap());
     assert_eq!(trace.args, p);
     assert_eq!(trace.logjp, if trace.data.read::<bool>("x") { p.ln() } else { (1.-p).ln() });
 }
 
+#[test]
+pub fn test_continuous_dists() {
+    let mut rng = ThreadRng::default();
+    dyngen!(
+    fn robust_line(xs: Vec<f64>) {
+        let slope = laplace(0., 1.) %= "slope";
+        let intercept = cauchy(0., 2.5) %= "intercept";
+        let noise = half_cauchy(1.) %= "noise";
+        let nu = exponential(0.1) %= "nu";
+        let scale = inv_gamma(2., 1.) %= "scale";
+        let log_std = half_normal(1.) %= "log_std";
+        lognormal(0., log_std) %= "positive";
+        for (i, x) in xs.iter().enumerate() {
+            student_t(nu, slope*x + intercept, noise*scale) %= &format!("ys/{i}");
+        }
+    });
+
+    let xs = vec![-1., 0., 1.];
+    let trace = robust_line.simulate(&mut rng, xs.clone());
+    let read = |addr: &str| trace.data.read::<f64>(addr);
+    let (slope, intercept, noise, nu, scale) = (read("slope"), read("intercept"), read("noise"), read("nu"), read("scale"));
+    let mut expected_logjp =
+        laplace.logpdf(&slope, (0., 1.)) +
+        cauchy.logpdf(&intercept, (0., 2.5)) +
+        half_cauchy.logpdf(&noise, 1.) +
+        exponential.logpdf(&nu, 0.1) +
+        inv_gamma.logpdf(&scale, (2., 1.)) +
+        half_normal.logpdf(&read("log_std"), 1.) +
+        lognormal.logpdf(&read("positive"), (0., read("log_std")));
+    for (i, x) in xs.iter().enumerate() {
+        expected_logjp += student_t.logpdf(&read(&format!("ys/{i}")), (nu, slope*x + intercept, noise*scale));
+    }
+    approx::assert_abs_diff_eq!(trace.logjp, expected_logjp, epsilon = 1e-10);
+}
+
+#[test]
+pub fn test_multivariate_dists() {
+    let mut rng = ThreadRng::default();
+    dyngen!(
+    fn mixture(k: usize) {
+        let probs = dirichlet(DVector::from_element(k, 1.)) %= "probs";
+        let covs = (0..k).map(|j| inv_wishart(4., DMatrix::identity(2, 2)) %= &format!("covs/{j}")).collect::<Vec<_>>();
+        let scale = wishart(3., DMatrix::identity(2, 2)) %= "scale";
+        let corr_chol = lkj_cholesky(2, 2.) %= "corr_chol";
+        let z = categorical(probs.as_slice().to_vec()) %= "z";
+        mvnormal(DVector::zeros(2), &covs[z as usize] + &scale + &corr_chol * corr_chol.transpose()) %= "x";
+    });
+
+    let trace = mixture.simulate(&mut rng, 3);
+    let probs = trace.data.read::<DVector<f64>>("probs");
+    let covs = (0..3).map(|j| trace.data.read::<DMatrix<f64>>(&format!("covs/{j}"))).collect::<Vec<_>>();
+    let scale = trace.data.read::<DMatrix<f64>>("scale");
+    let corr_chol = trace.data.read::<DMatrix<f64>>("corr_chol");
+    let z = trace.data.read::<i64>("z");
+    let expected_logjp =
+        dirichlet.logpdf(&probs, DVector::from_element(3, 1.)) +
+        covs.iter().map(|cov| inv_wishart.logpdf(cov, (4., DMatrix::identity(2, 2)))).sum::<f64>() +
+        wishart.logpdf(&scale, (3., DMatrix::identity(2, 2))) +
+        lkj_cholesky.logpdf(&corr_chol, (2, 2.)) +
+        categorical.logpdf(&z, probs.as_slice().to_vec()) +
+        mvnormal.logpdf(&trace.data.read::<DVector<f64>>("x"), (DVector::zeros(2), &covs[z as usize] + &scale + &corr_chol * corr_chol.transpose()));
+    approx::assert_abs_diff_eq!(trace.logjp, expected_logjp, epsilon = 1e-10);
+}
+
 #[test]
 pub fn test_update() {
+    let mut rng = ThreadRng::default();
     dyngen!(
     fn bar() -> f64 {
         normal(0., 1.) %= "a"
@@ -203,7 +358,7 @@ pub fn test_update() {
     // get a trace which follows the first branch
     let mut constraints = DynTrie::new();
     constraints.observe("branch", Arc::new(true));
-    let (trace, _) = foo.generate((), constraints);
+    let (trace, _) = foo.generate(&mut rng, (), constraints);
     let x = trace.data.read::<f64>("x");
     let a = trace.data.read::<f64>("u/a");
 
@@ -214,10 +369,10 @@ pub fn test_update() {
     constraints.observe("branch", Arc::new(false));
     constraints.observe("y", Arc::new(y));
     constraints.observe("v/b", Arc::new(b));
-    let (new_trace, discard, weight) = foo.update(trace, (), ArgDiff::NoChange, constraints);
+    let (new_trace, discard, weight) = foo.update(&mut rng, trace, (), ArgDiff::NoChange, constraints);
 
     // test discard
-    assert_eq!(discard.read::<bool>("branch"), true);
+    assert!(discard.read::<bool>("branch"));
     assert_eq!(discard.read::<f64>("x"), x);
     assert_eq!(discard.read::<f64>("u/a"), a);
     assert_eq!(discard.iter().fold(0, |l, (_, tr)| l + tr.is_leaf() as usize), 2);
@@ -225,7 +380,7 @@ pub fn test_update() {
 
     // test new trace
     let new_assignment = new_trace.data;
-    assert_eq!(new_assignment.read::<bool>("branch"), false);
+    assert!(!new_assignment.read::<bool>("branch"));
     assert_eq!(new_assignment.read::<f64>("y"), y);
     assert_eq!(new_assignment.read::<f64>("v/b"), b);
     assert_eq!(new_assignment.iter().fold(0, |l, (_, tr)| l + tr.is_leaf() as usize), 2);
@@ -260,12 +415,12 @@ pub fn test_update() {
     for i in 0..5 {
         constraints.observe(&format!("data/{i}"), Arc::new(0.));
     }
-    let (trace, _) = loopy.generate((), constraints);
+    let (trace, _) = loopy.generate(&mut rng, (), constraints);
 
     // update "a"
     let mut constraints = DynTrie::new();
     constraints.observe("a", Arc::new(1.));
-    let (new_trace, discard, weight) = loopy.update(trace, (), ArgDiff::NoChange, constraints);
+    let (new_trace, discard, weight) = loopy.update(&mut rng, trace, (), ArgDiff::NoChange, constraints);
 
     // test discard, logjp, weight
     assert_eq!(discard.read::<f64>("a"), 0.);
@@ -285,10 +440,10 @@ pub fn test_update() {
 
     let mut constraints = DynTrie::new();
     constraints.observe("k", Arc::new(3_i64));
-    let trace = hierarchical_update.generate((), constraints).0;
+    let trace = hierarchical_update.generate(&mut rng, (), constraints).0;
     let mut constraints = DynTrie::new();
     constraints.observe("k", Arc::new(1_i64));
-    let (_, discard, weight) = hierarchical_update.update(trace, (), ArgDiff::Unknown, constraints);
+    let (_, discard, weight) = hierarchical_update.update(&mut rng, trace, (), ArgDiff::Unknown, constraints);
     assert!(discard.search("value/1").is_some());
     assert!(discard.search("value/2").is_some());
     assert_eq!(
@@ -302,6 +457,7 @@ pub fn test_update() {
 
 #[test]
 pub fn test_regenerate() {
+    let mut rng = ThreadRng::default();
     dyngen!(
     fn bar(mu: f64) -> f64 {
         normal(mu, 1.) %= "a"
@@ -327,22 +483,19 @@ pub fn test_regenerate() {
     let mut mu = 0.123;
     let mut constraints = DynTrie::new();
     constraints.observe("branch", Arc::new(true));
-    let (mut trace, _) = foo.generate(mu, constraints);
-    let x = trace.data.read::<f64>("x");
-    let a = trace.data.read::<f64>("u/a");
+    let (mut trace, _) = foo.generate(&mut rng, mu, constraints);
 
     let mut mask = AddrMap::new();
     mask.visit("branch");
 
     // change the argument so that the weights can be nonzero
-    let mut rng = ThreadRng::default();
-    for i in 0..10 {
+    for _ in 0..10 {
         let prev_branch = trace.data.read::<bool>("branch");
 
         // test logjp
         let prev_mu = mu;
         mu = u01(&mut rng);
-        let (new_trace, weight) = foo.regenerate(trace, mu, ArgDiff::Unknown, &mask);
+        let (new_trace, weight) = foo.regenerate(&mut rng, trace, mu, ArgDiff::Unknown, &mask);
         trace = new_trace;
 
         // test logjp
@@ -386,3 +539,28 @@ pub fn test_regenerate() {
         approx::assert_abs_diff_eq!(expected_weight, weight, epsilon = 1e-3);
     }
 }
+
+#[test]
+pub fn test_seeded_reproducibility() {
+    dyngen!(
+    fn foo(mu: f64) -> f64 {
+        if bernoulli(0.4) %= "branch" {
+            normal(mu, 1.) %= "x"
+        } else {
+            normal(mu, 1.) %= "y"
+        }
+    });
+
+    let mut mask = AddrMap::new();
+    mask.visit("branch");
+
+    // the same seed should yield bit-identical traces and weights
+    let run = |seed: u64| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let trace = foo.simulate(&mut rng, 0.);
+        let (trace, weight) = foo.regenerate(&mut rng, trace, 1., ArgDiff::Unknown, &mask);
+        (trace.retv.unwrap(), trace.logjp, weight)
+    };
+    assert_eq!(run(42), run(42));
+    assert_ne!(run(42), run(43));
+}
@@ -68,4 +68,67 @@ pub fn hierarchical_drift_proposal(tr: Weak<DynTrace<Vec<f64>,Vec<f64>>>, drift_
     if !tr.data.read::<bool>("is_linear") {
         normal(tr.data.read::<f64>("coeffs/c"), drift_std) %= "coeffs/c";
     }
-});
\ No newline at end of file
+});
+
+// the mean of the observations is either a single parameter, or the sum of two
+dyngen!(
+pub fn split_mean_model(n: usize) {
+    let mean = if bernoulli(0.5) %= "split" {
+        (normal(0., 1.) %= "mu1") + (normal(0., 1.) %= "mu2")
+    } else {
+        normal(0., 1.) %= "mu"
+    };
+    for i in 0..n {
+        normal(mean, 1.) %= &format!("ys / {}", i);
+    }
+});
+
+// an offset for splitting the mean, or nothing when merging
+dyngen!(
+pub fn split_merge_proposal(tr: Weak<DynTrace<usize,()>>) {
+    let tr = tr.upgrade().unwrap();
+    if !tr.data.read::<bool>("split") {
+        normal(0., 1.) %= "u";
+    }
+});
+
+// (mu, u) <-> (mu1, mu2) = (mu + u, mu - u), with |det J| = 2 when splitting
+pub fn split_merge_involution(data: &DynTrie, aux: &DynTrie) -> (DynTrie, DynTrie, f64) {
+    let mut constraints = DynTrie::new();
+    let mut bwd_aux = DynTrie::new();
+    if data.read::<bool>("split") {
+        let (mu1, mu2) = (data.read::<f64>("mu1"), data.read::<f64>("mu2"));
+        constraints.observe("split", Arc::new(false));
+        constraints.observe("mu", Arc::new((mu1 + mu2) / 2.));
+        bwd_aux.observe("u", Arc::new((mu1 - mu2) / 2.));
+        (constraints, bwd_aux, -2_f64.ln())
+    } else {
+        let (mu, u) = (data.read::<f64>("mu"), aux.read::<f64>("u"));
+        constraints.observe("split", Arc::new(true));
+        constraints.observe("mu1", Arc::new(mu + u));
+        constraints.observe("mu2", Arc::new(mu - u));
+        (constraints, bwd_aux, 2_f64.ln())
+    }
+}
+
+// a positive scale under a gamma prior
+dyngen!(
+pub fn gamma_prior_model() {
+    gamma(3., 1.) %= "scale";
+});
+
+// a log-scale step for multiplying the scale
+dyngen!(
+pub fn log_step_proposal(_tr: Weak<DynTrace<(),()>>, step_std: f64) {
+    normal(0., step_std) %= "u";
+});
+
+// (scale, u) <-> (scale * exp(u), -u), with |det J| = exp(u)
+pub fn scale_involution(data: &DynTrie, aux: &DynTrie) -> (DynTrie, DynTrie, f64) {
+    let (scale, u) = (data.read::<f64>("scale"), aux.read::<f64>("u"));
+    let mut constraints = DynTrie::new();
+    let mut bwd_aux = DynTrie::new();
+    constraints.observe("scale", Arc::new(scale * u.exp()));
+    bwd_aux.observe("u", Arc::new(-u));
+    (constraints, bwd_aux, u)
+}
@@ -6,6 +6,10 @@ mod simple;
 mod hierarchical;
 mod unfold;
 
+// each test crate only uses some of the fixtures
+#[allow(unused_imports)]
 pub use simple::*;
+#[allow(unused_imports)]
 pub use hierarchical::*;
+#[allow(unused_imports)]
 pub use unfold::*;
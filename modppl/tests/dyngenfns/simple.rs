@@ -22,6 +22,56 @@ pub fn line_model(xs: Vec<f64>) -> Vec<f64> {
     obs_model(slope, intercept, xs) /= "ys"
 });
 
+// independent proposal for the line parameters
+dyngen!(
+pub fn line_proposal(slope_mean: f64, intercept_mean: f64, scale: f64) {
+    normal(slope_mean, scale) %= "slope";
+    normal(intercept_mean, scale) %= "intercept";
+});
+
+
+// conjugate normal prior on the mean of unit-variance observations
+dyngen!(
+pub fn conjugate_model(num_obs: usize) {
+    let mu = normal(0., 1.) %= "mu";
+    for i in 0..num_obs {
+        normal(mu, 1.) %= &format!("(y, {})", i);
+    }
+});
+
+// conjugate normal prior on the mean of observations at "ys / i"
+dyngen!(
+pub fn normal_mean_model(n: usize) {
+    let mu = normal(1., 2.) %= "mu";
+    for i in 0..n {
+        normal(mu, 0.5) %= &format!("ys / {}", i);
+    }
+});
+
+pub fn observe_ys(ys: &[f64]) -> DynTrie {
+    let mut observations = DynTrie::new();
+    for (i, y) in ys.iter().enumerate() {
+        observations.observe(&format!("ys / {}", i), Arc::new(*y));
+    }
+    observations
+}
+
+// discrete choices with noisy observations
+dyngen!(
+pub fn noisy_coin() {
+    let heads = bernoulli(0.3) %= "heads";
+    let die = uniform_discrete(1, 6) %= "die";
+    bernoulli(if heads { 0.9 } else { 0.2 }) %= "report";
+    normal(die as f64, 0.5) %= "noisy_die";
+});
+
+pub fn mean_and_var(samples: &[f64]) -> (f64, f64) {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let var = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+    (mean, var)
+}
+
 
 // pointed model (DynGenFn version)
 dyngen!(
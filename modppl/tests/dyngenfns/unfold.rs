@@ -30,4 +30,55 @@ fn spiral_kernel(t: i64, prev_pol: Point) -> Point {
     return pol;
 });
 
-pub const spiral_model: DynUnfold<Point> = DynUnfold { kernel: spiral_kernel };
\ No newline at end of file
+pub const spiral_model: DynUnfold<Point> = DynUnfold { kernel: spiral_kernel };
+
+dyngen!(
+fn random_walk_kernel(t: i64, prev_x: f64) -> f64 {
+    let mean = if t == 0 { 0. } else { prev_x };
+    let x = normal(mean, 1.) %= "x";
+    normal(x, 0.5) %= "y";
+    x
+});
+
+pub const random_walk_model: DynUnfold<f64> = DynUnfold { kernel: random_walk_kernel };
+
+pub fn random_walk_observations(ys: &[f64]) -> Vec<DynTrie> {
+    ys.iter().map(|y| {
+        let mut constraints = DynTrie::new();
+        constraints.observe("y", Arc::new(*y));
+        constraints
+    }).collect()
+}
+
+/// Exact log marginal likelihood of `ys` under `random_walk_model` by Kalman filtering.
+pub fn random_walk_log_ml(ys: &[f64]) -> f64 {
+    let (mut mean, mut var) = (0., 1.);
+    let mut log_ml = 0.;
+    for y in ys {
+        log_ml += normal.logpdf(y, (mean, (var + 0.25_f64).sqrt()));
+        let gain = var / (var + 0.25);
+        mean += gain * (y - mean);
+        var *= 1. - gain;
+        var += 1.;
+    }
+    log_ml
+}
+
+// a random walk whose observations also depend on the previous state
+dyngen!(
+fn lagged_walk_kernel(t: i64, prev_x: f64) -> f64 {
+    let mean = if t == 0 { 0. } else { prev_x };
+    let x = normal(mean, 1.) %= "x";
+    normal(x + 2.*mean, 0.3) %= "y";
+    x
+});
+
+pub const lagged_walk_model: DynUnfold<f64> = DynUnfold { kernel: lagged_walk_kernel };
+
+dyngen!(
+fn random_walk_proposal(trace: Weak<Trace<(i64,f64),Vec<DynTrie>,Vec<f64>>>, y: f64) {
+    // locally optimal proposal p(x | prev_x, y)
+    let trace = trace.upgrade().unwrap();
+    let prev_x = *trace.retv.as_ref().unwrap().last().unwrap();
+    normal((prev_x + 4.*y) / 5., 0.2_f64.sqrt()) %= "x";
+});
@@ -0,0 +1,179 @@
+#![allow(non_upper_case_globals)]
+
+use nalgebra::{dvector,dmatrix,DVector,DMatrix};
+use modppl::prelude::*;
+
+pub mod hmm;
+mod pointed_model;
+mod dyngenfns;
+use dyngenfns::noisy_coin;
+
+
+// dynamic version of the hmm in `hmm::HMM`, with states at "z / t" and observations at "x / t"
+dyngen!(
+fn hmm_model(prior: DVector<f64>, emission_dists: DMatrix<f64>, transition_dists: DMatrix<f64>, num_steps: usize) {
+    let mut probs = prior.as_slice().to_vec();
+    for t in 0..num_steps {
+        let z = categorical(probs) %= &format!("z / {}", t);
+        categorical(emission_dists.column(z as usize).as_slice().to_vec()) %= &format!("x / {}", t);
+        probs = transition_dists.column(z as usize).as_slice().to_vec();
+    }
+});
+
+// the support of "roll" depends on the value of "sides"
+dyngen!(
+fn dice_bag() {
+    let sides = uniform_discrete(0, 1) %= "sides";
+    let roll = uniform_discrete(1, if sides == 0 { 4 } else { 6 }) %= "roll";
+    normal(roll as f64, 1.) %= "noisy_roll";
+});
+
+dyngen!(
+fn noisy_mean() {
+    let mu = normal(0., 1.) %= "mu";
+    normal(mu, 0.5) %= "y";
+});
+
+#[test]
+fn test_choice_grid() {
+    let grid = ChoiceGrid::new()
+        .with("a", [false, true])
+        .with("b / c", 0..3_i64);
+    assert_eq!(grid.len(), 6);
+    assert!(!grid.is_empty());
+    assert!(grid.selection().search("b / c").is_some());
+
+    let mut observations = DynTrie::new();
+    observations.observe("x", Arc::new(1.));
+    let assignments = grid.assignments(&observations);
+    assert_eq!(assignments.len(), 6);
+    for (k, assignment) in assignments.iter().enumerate() {
+        assert_eq!(assignment.read::<bool>("a"), k >= 3);
+        assert_eq!(assignment.read::<i64>("b / c"), k as i64 % 3);
+        assert_eq!(assignment.read::<f64>("x"), 1.);
+    }
+
+    assert!(ChoiceGrid::new().with::<i64>("a", []).is_empty());
+    assert_eq!(ChoiceGrid::new().assignments(&observations).len(), 1);
+}
+
+#[test]
+fn test_enumerate_posterior() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut observations = DynTrie::new();
+    observations.observe("report", Arc::new(true));
+    observations.observe("noisy_die", Arc::new(2.2));
+    let mut selection = AddrMap::new();
+    selection.visit("heads");
+    selection.visit("die");
+
+    let (traces, log_normalized_weights, log_ml) = enumerate(&mut rng, &noisy_coin, (), observations, &selection, &ChoiceGrid::new());
+    assert_eq!(traces.len(), 12);
+
+    // the coin and die are independent given the observations
+    let die_likelihoods = (1..=6).map(|d| normal.logpdf(&2.2, (d as f64, 0.5)).exp() / 6.).collect::<Vec<f64>>();
+    let die_evidence = die_likelihoods.iter().sum::<f64>();
+    let coin_evidence = 0.3 * 0.9 + 0.7 * 0.2;
+    approx::assert_abs_diff_eq!(log_ml, (coin_evidence * die_evidence).ln(), epsilon = 1e-10);
+
+    let mut p_heads = 0.;
+    for (trace, log_weight) in traces.iter().zip(log_normalized_weights.iter()) {
+        if trace.data.read::<bool>("heads") {
+            p_heads += log_weight.exp();
+        }
+        let die = trace.data.read::<i64>("die") as usize;
+        let coin = if trace.data.read::<bool>("heads") { 0.3 * 0.9 } else { 0.7 * 0.2 };
+        approx::assert_abs_diff_eq!(log_weight.exp(), coin / coin_evidence * die_likelihoods[die - 1] / die_evidence, epsilon = 1e-10);
+    }
+    approx::assert_abs_diff_eq!(p_heads, 0.27 / 0.41, epsilon = 1e-10);
+}
+
+#[test]
+fn test_enumerate_hmm() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let prior = dvector![0.2, 0.3, 0.5];
+    let emission_dists = dmatrix![0.1, 0.9; 0.7, 0.3; 0.5, 0.5].transpose();
+    let transition_dists = dmatrix![0.5, 0.3, 0.2; 0.2, 0.6, 0.2; 0.1, 0.1, 0.8].transpose();
+    let data = vec![1, 0, 0, 1, 1];
+
+    let mut observations = DynTrie::new();
+    let mut selection = AddrMap::new();
+    for (t, x) in data.iter().enumerate() {
+        observations.observe(&format!("x / {}", t), Arc::new(*x as i64));
+        selection.visit(&format!("z / {}", t));
+    }
+
+    let args = (prior.clone(), emission_dists.clone(), transition_dists.clone(), data.len());
+    let (traces, log_normalized_weights, log_ml) = enumerate(&mut rng, &hmm_model, args, observations, &selection, &ChoiceGrid::new());
+    assert_eq!(traces.len(), 243);
+    approx::assert_abs_diff_eq!(modppl::logsumexp(&log_normalized_weights), 0., epsilon = 1e-10);
+
+    let expected = hmm::hmm_forward_alg(prior, emission_dists, transition_dists, &data).ln();
+    approx::assert_abs_diff_eq!(log_ml, expected, epsilon = 1e-10);
+}
+
+#[test]
+#[should_panic(expected = "must constrain every choice")]
+fn test_enumerate_incomplete_grid() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut observations = DynTrie::new();
+    observations.observe("report", Arc::new(true));
+    observations.observe("noisy_die", Arc::new(2.2));
+    let mut selection = AddrMap::new();
+    selection.visit("heads");
+    enumerate(&mut rng, &noisy_coin, (), observations, &selection, &ChoiceGrid::new());
+}
+
+#[test]
+fn test_enumerate_dependent_support() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut observations = DynTrie::new();
+    observations.observe("noisy_roll", Arc::new(5.5));
+    let mut selection = AddrMap::new();
+    selection.visit("sides");
+    selection.visit("roll");
+
+    let (traces, log_normalized_weights, log_ml) = enumerate(&mut rng, &dice_bag, (), observations, &selection, &ChoiceGrid::new());
+    assert_eq!(traces.len(), 4 + 6);
+    for (k, trace) in traces.iter().enumerate() {
+        // depth-first, in the order of the supports
+        assert_eq!(trace.data.read::<i64>("sides"), (k >= 4) as i64);
+        assert_eq!(trace.data.read::<i64>("roll"), if k < 4 { k as i64 + 1 } else { k as i64 - 3 });
+    }
+
+    let evidence = (1..=4).map(|r| normal.logpdf(&5.5, (r as f64, 1.)).exp() / 8.).sum::<f64>()
+        + (1..=6).map(|r| normal.logpdf(&5.5, (r as f64, 1.)).exp() / 12.).sum::<f64>();
+    approx::assert_abs_diff_eq!(log_ml, evidence.ln(), epsilon = 1e-10);
+    approx::assert_abs_diff_eq!(modppl::logsumexp(&log_normalized_weights), 0., epsilon = 1e-10);
+}
+
+#[test]
+fn test_enumerate_grid_override() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut observations = DynTrie::new();
+    observations.observe("y", Arc::new(0.3));
+    let values = [-1., 0., 1.];
+    let grid = ChoiceGrid::new().with("mu", values);
+
+    let (traces, log_normalized_weights, log_ml) = enumerate(&mut rng, &noisy_mean, (), observations, &AddrMap::new(), &grid);
+    assert_eq!(traces.len(), 3);
+    let joints = values.iter()
+        .map(|mu| normal.logpdf(mu, (0., 1.)) + normal.logpdf(&0.3, (*mu, 0.5)))
+        .collect::<Vec<f64>>();
+    approx::assert_abs_diff_eq!(log_ml, modppl::logsumexp(&joints), epsilon = 1e-10);
+    for ((trace, log_weight), (mu, joint)) in traces.iter().zip(log_normalized_weights.iter()).zip(values.iter().zip(joints.iter())) {
+        assert_eq!(trace.data.read::<f64>("mu"), *mu);
+        approx::assert_abs_diff_eq!(*log_weight, joint - log_ml, epsilon = 1e-10);
+    }
+}
+
+#[test]
+#[should_panic(expected = "has no finite support")]
+fn test_enumerate_continuous_choice() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut observations = DynTrie::new();
+    observations.observe("y", Arc::new(0.3));
+    let mut selection = AddrMap::new();
+    selection.visit("mu");
+    enumerate(&mut rng, &noisy_mean, (), observations, &selection, &ChoiceGrid::new());
+}
@@ -0,0 +1,102 @@
+#![allow(non_upper_case_globals)]
+
+use modppl::prelude::*;
+
+mod pointed_model;
+mod dyngenfns;
+use dyngenfns::noisy_coin;
+
+
+// the mean of the observation is only sampled if `has_offset`
+dyngen!(
+fn offset_model() {
+    let offset = if bernoulli(0.5) %= "has_offset" {
+        normal(0., 1.) %= "offset"
+    } else {
+        0.
+    };
+    normal(offset, 1.) %= "y";
+});
+
+#[test]
+fn test_gibbs_exact_conditional() {
+    const NUM_ITERS: usize = 20000;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut observations = DynTrie::new();
+    observations.observe("report", Arc::new(true));
+    observations.observe("noisy_die", Arc::new(2.2));
+
+    let mut selection = AddrMap::new();
+    selection.visit("heads");
+    selection.visit("die");
+    let (traces, log_normalized_weights, _) = enumerate(&mut rng, &noisy_coin, (), observations.clone(), &selection, &ChoiceGrid::new());
+    let mut expected = [0.; 7];
+    for (trace, log_weight) in traces.iter().zip(log_normalized_weights.iter()) {
+        expected[trace.data.read::<i64>("die") as usize] += log_weight.exp();
+    }
+
+    let (mut trace, _) = noisy_coin.generate(&mut rng, (), observations);
+    let mut counts = [0; 7];
+    let mut num_heads = 0;
+    for _ in 0..NUM_ITERS {
+        let (accepted_heads, accepted_die);
+        (trace, accepted_heads) = gibbs(&mut rng, &noisy_coin, trace, "heads", &bernoulli, &0.3);
+        (trace, accepted_die) = gibbs(&mut rng, &noisy_coin, trace, "die", &uniform_discrete, &(1, 6));
+        assert!(accepted_heads && accepted_die);
+        counts[trace.data.read::<i64>("die") as usize] += 1;
+        num_heads += trace.data.read::<bool>("heads") as usize;
+    }
+    for die in 1..=6 {
+        approx::assert_abs_diff_eq!(counts[die] as f64 / NUM_ITERS as f64, expected[die], epsilon = 0.015);
+    }
+    approx::assert_abs_diff_eq!(num_heads as f64 / NUM_ITERS as f64, 0.27 / 0.41, epsilon = 0.015);
+}
+
+#[test]
+fn test_gibbs_changing_structure() {
+    const NUM_ITERS: usize = 20000;
+    let y = 2.;
+
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut observations = DynTrie::new();
+    observations.observe("y", Arc::new(y));
+    let (mut trace, _) = offset_model.generate(&mut rng, (), observations);
+
+    // y ~ N(0, 2) with an offset and N(0, 1) without
+    let with_offset = normal.logpdf(&y, (0., 2_f64.sqrt())).exp();
+    let without_offset = normal.logpdf(&y, (0., 1.)).exp();
+    let expected = with_offset / (with_offset + without_offset);
+
+    let mut offset_selection = AddrMap::new();
+    offset_selection.visit("offset");
+    let mut num_offset = 0;
+    let mut num_accepted = 0;
+    for _ in 0..NUM_ITERS {
+        let accepted;
+        (trace, accepted) = gibbs(&mut rng, &offset_model, trace, "has_offset", &bernoulli, &0.5);
+        num_accepted += accepted as usize;
+        if trace.data.read::<bool>("has_offset") {
+            num_offset += 1;
+            (trace, _) = regen_mh(&mut rng, &offset_model, trace, &offset_selection);
+        }
+    }
+    assert!(num_accepted < NUM_ITERS);
+    approx::assert_abs_diff_eq!(num_offset as f64 / NUM_ITERS as f64, expected, epsilon = 0.02);
+}
+
+#[test]
+#[should_panic(expected = "finite support")]
+fn test_gibbs_infinite_support() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let trace = offset_model.simulate(&mut rng, ());
+    gibbs(&mut rng, &offset_model, trace, "y", &normal, &(0., 1.));
+}
+
+#[test]
+#[should_panic(expected = "but the trace recorded")]
+fn test_gibbs_mismatched_params() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let trace = noisy_coin.simulate(&mut rng, ());
+    gibbs(&mut rng, &noisy_coin, trace, "heads", &bernoulli, &0.5);
+}
@@ -0,0 +1,170 @@
+#![allow(non_upper_case_globals)]
+
+use nalgebra::{DVector, dvector, dmatrix};
+use modppl::prelude::*;
+
+mod pointed_model;
+mod dyngenfns;
+use dyngenfns::hierarchical_model;
+
+const GRAD_EPSILON: f64 = 1e-5;
+
+
+dyngen!(
+fn location_model(prior_std: f64) -> f64 {
+    let mu = normal(0., prior_std) %= "mu";
+    normal(mu, 0.5) %= "y"
+});
+
+dyngen!(
+fn nested_location_model() -> DVector<f64> {
+    let mu = location_model(1.) /= "sub";
+    let is_heads = bernoulli(0.5) %= "coin";
+    let offset = if is_heads { 1. } else { -1. };
+    mvnormal(dvector![mu, offset], dmatrix![1., 0.5; 0.5, 2.]) %= "x"
+});
+
+// a model whose parameters depend nonlinearly on earlier choices and the args
+dyngen!(
+fn link_model(scale: f64) -> f64 {
+    let log_rate = normal(0., scale) %= "log_rate";
+    let rate = gamma(2., log_rate.exp()) %= "rate";
+    let p = beta(1. + rate, 2.) %= "p";
+    let w = mvnormal(dvector![p, rate], dmatrix![1., 0.2; 0.2, 0.5]) %= "w";
+    uniform_continuous(w[0] - 3., w[0] + 3.) %= "u";
+    normal(w[1] * p, scale.sqrt()) %= "y"
+});
+
+/// Estimate the gradient of `f` at `x` by central differences.
+fn central_difference(x: &[f64], mut f: impl FnMut(&[f64]) -> f64) -> Vec<f64> {
+    let mut x_h = x.to_vec();
+    let mut grad = Vec::with_capacity(x.len());
+    for i in 0..x.len() {
+        let h = f64::EPSILON.cbrt() * x[i].abs().max(1.);
+        x_h[i] = x[i] + h;
+        let f_plus = f(&x_h);
+        x_h[i] = x[i] - h;
+        let f_minus = f(&x_h);
+        x_h[i] = x[i];
+        grad.push((f_plus - f_minus) / (2.*h));
+    }
+    grad
+}
+
+/// Oracle for `choice_gradients`, by central differences of the weights of `update`s with a perturbed choice.
+fn choice_gradient_oracle<V: Differentiable + Clone + Send + Sync + 'static>(rng: &mut impl Rng, trace: &DynTrace<f64,f64>, addr: &str) -> Vec<f64> {
+    let x = trace.data.read::<V>(addr);
+    central_difference(&x.to_coords(), |coords| {
+        let mut constraints = DynTrie::new();
+        constraints.observe(addr, Arc::new(x.with_coords(coords)));
+        link_model.update(rng, trace.clone(), trace.args, ArgDiff::NoChange, constraints).2
+    })
+}
+
+fn constrained_location_trace(rng: &mut impl Rng, mu: f64, y: f64) -> DynTrace<f64,f64> {
+    let mut constraints = DynTrie::new();
+    constraints.observe("mu", Arc::new(mu));
+    constraints.observe("y", Arc::new(y));
+    location_model.generate(rng, 2., constraints).0
+}
+
+#[test]
+pub fn test_choice_gradients() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let (mu, y) = (0.3, 1.2);
+    let trace = constrained_location_trace(&mut rng, mu, y);
+
+    let mut selection = AddrMap::new();
+    selection.visit("mu");
+    let grads = location_model.choice_gradients(&mut rng, &trace, &selection);
+    approx::assert_abs_diff_eq!(grads.read::<f64>("mu"), -mu/4. + (y - mu)/0.25, epsilon = GRAD_EPSILON);
+    assert!(grads.search("y").is_none());
+
+    selection.visit("y");
+    let grads = location_model.choice_gradients(&mut rng, &trace, &selection);
+    approx::assert_abs_diff_eq!(grads.read::<f64>("y"), -(y - mu)/0.25, epsilon = GRAD_EPSILON);
+}
+
+#[test]
+pub fn test_choice_gradients_nested() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let trace = nested_location_model.simulate(&mut rng, ());
+    let mu = trace.data.read::<f64>("sub / mu");
+    let y = trace.data.read::<f64>("sub / y");
+    let offset = if trace.data.read::<bool>("coin") { 1. } else { -1. };
+    let x = trace.data.read::<DVector<f64>>("x");
+
+    let mut selection = AddrMap::new();
+    selection.visit("sub");
+    selection.visit("coin");
+    selection.visit("x");
+    let grads = nested_location_model.choice_gradients(&mut rng, &trace, &selection);
+    assert!(grads.search("coin").is_none());
+
+    let cov_inv = dmatrix![1., 0.5; 0.5, 2.].try_inverse().unwrap();
+    let d_x = -&cov_inv * (&x - dvector![y, offset]);
+    approx::assert_abs_diff_eq!(grads.read::<DVector<f64>>("x"), d_x, epsilon = GRAD_EPSILON);
+    approx::assert_abs_diff_eq!(grads.read::<f64>("sub / mu"), -mu + (y - mu)/0.25, epsilon = GRAD_EPSILON);
+    approx::assert_abs_diff_eq!(grads.read::<f64>("sub / y"), -(y - mu)/0.25 - d_x[0], epsilon = GRAD_EPSILON);
+}
+
+#[test]
+pub fn test_choice_gradients_hierarchical() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let xs = vec![-1., 0., 0.5, 2.];
+    let ys = [-0.8, 0.3, 0.6, 2.1];
+    let mut constraints = DynTrie::new();
+    constraints.observe("is_linear", Arc::new(true));
+    for (i, y) in ys.iter().enumerate() {
+        constraints.observe(&format!("(y, {})", i), Arc::new(*y));
+    }
+    let (trace, _) = hierarchical_model.generate(&mut rng, xs.clone(), constraints);
+    let a = trace.data.read::<f64>("coeffs / a");
+    let b = trace.data.read::<f64>("coeffs / b");
+
+    let mut selection = AddrMap::new();
+    selection.visit("coeffs");
+    let grads = hierarchical_model.choice_gradients(&mut rng, &trace, &selection);
+    let residuals = xs.iter().zip(ys).map(|(x, y)| (y - a - b*x)/0.01).collect::<Vec<f64>>();
+    let d_a = -a + residuals.iter().sum::<f64>();
+    let d_b = -b + residuals.iter().zip(&xs).map(|(r, x)| r*x).sum::<f64>();
+    approx::assert_relative_eq!(grads.read::<f64>("coeffs / a"), d_a, max_relative = GRAD_EPSILON);
+    approx::assert_relative_eq!(grads.read::<f64>("coeffs / b"), d_b, max_relative = GRAD_EPSILON);
+
+    let d_xs = hierarchical_model.argument_gradients(&mut rng, &trace);
+    for i in 0..xs.len() {
+        approx::assert_relative_eq!(d_xs[i], residuals[i]*b, max_relative = GRAD_EPSILON);
+    }
+}
+
+#[test]
+pub fn test_argument_gradients() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let mu = 0.7;
+    let trace = constrained_location_trace(&mut rng, mu, 0.);
+    let d_prior_std = location_model.argument_gradients(&mut rng, &trace);
+    approx::assert_abs_diff_eq!(d_prior_std, (mu*mu/4. - 1.)/2., epsilon = GRAD_EPSILON);
+}
+
+#[test]
+pub fn test_gradients_finite_difference_oracle() {
+    let mut rng = StdRng::seed_from_u64(4);
+    let trace = link_model.simulate(&mut rng, 0.8);
+
+    let mut selection = AddrMap::new();
+    for addr in ["log_rate", "rate", "p", "w", "u", "y"] {
+        selection.visit(addr);
+    }
+    let grads = link_model.choice_gradients(&mut rng, &trace, &selection);
+    for addr in ["log_rate", "rate", "p", "u", "y"] {
+        let expected = choice_gradient_oracle::<f64>(&mut rng, &trace, addr)[0];
+        approx::assert_relative_eq!(grads.read::<f64>(addr), expected, max_relative = GRAD_EPSILON, epsilon = GRAD_EPSILON);
+    }
+    let expected = DVector::from_vec(choice_gradient_oracle::<DVector<f64>>(&mut rng, &trace, "w"));
+    approx::assert_relative_eq!(grads.read::<DVector<f64>>("w"), expected, max_relative = GRAD_EPSILON, epsilon = GRAD_EPSILON);
+
+    let expected = central_difference(&[trace.args], |coords| {
+        link_model.update(&mut rng, trace.clone(), coords[0], ArgDiff::Unknown, DynTrie::new()).2
+    })[0];
+    approx::assert_relative_eq!(link_model.argument_gradients(&mut rng, &trace), expected, max_relative = GRAD_EPSILON);
+}
@@ -0,0 +1,64 @@
+#![allow(non_upper_case_globals)]
+
+use modppl::prelude::*;
+
+mod pointed_model;
+mod dyngenfns;
+use dyngenfns::{hierarchical_model, conjugate_model, mean_and_var};
+
+
+#[test]
+fn test_hmc_conjugate_posterior() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let ys = [1.5, 0.7, 2.1, 1.2];
+    let mut constraints = DynTrie::new();
+    for (i, y) in ys.iter().enumerate() {
+        constraints.observe(&format!("(y, {})", i), Arc::new(*y));
+    }
+    let (mut trace, _) = conjugate_model.generate(&mut rng, ys.len(), constraints);
+
+    let mut selection = AddrMap::new();
+    selection.visit("mu");
+
+    const NUM_ITERS: usize = 2000;
+    let mut samples = vec![];
+    let mut num_accepted = 0;
+    for _ in 0..NUM_ITERS {
+        let (new_trace, accepted) = hmc(&mut rng, &conjugate_model, trace, &selection, 0.25, 5);
+        trace = new_trace;
+        num_accepted += accepted as usize;
+        samples.push(trace.data.read::<f64>("mu"));
+    }
+
+    let n = ys.len() as f64;
+    let post_mean = ys.iter().sum::<f64>() / (n + 1.);
+    let post_var = 1. / (n + 1.);
+    let (mean, var) = mean_and_var(&samples);
+    approx::assert_abs_diff_eq!(mean, post_mean, epsilon = 0.05);
+    approx::assert_abs_diff_eq!(var, post_var, epsilon = 0.05);
+    assert!(num_accepted as f64 / NUM_ITERS as f64 > 0.9);
+}
+
+#[test]
+fn test_hmc_hierarchical() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let xs = vec![-2., -1., 0., 1., 2.];
+    let ys = [-3.1, -1.0, 0.9, 3.0, 5.1];
+    let mut constraints = DynTrie::new();
+    constraints.observe("is_linear", Arc::new(true));
+    constraints.observe("coeffs / a", Arc::new(0.));
+    constraints.observe("coeffs / b", Arc::new(0.));
+    for (i, y) in ys.iter().enumerate() {
+        constraints.observe(&format!("(y, {})", i), Arc::new(*y));
+    }
+    let (mut trace, _) = hierarchical_model.generate(&mut rng, xs, constraints);
+
+    let mut selection = AddrMap::new();
+    selection.visit("coeffs");
+    for _ in 0..100 {
+        (trace, _) = hmc(&mut rng, &hierarchical_model, trace, &selection, 0.0125, 6);
+    }
+    approx::assert_abs_diff_eq!(trace.data.read::<f64>("coeffs / a"), 0.98, epsilon = 0.1);
+    approx::assert_abs_diff_eq!(trace.data.read::<f64>("coeffs / b"), 2.03, epsilon = 0.1);
+    assert!(trace.data.read::<bool>("is_linear"));
+}
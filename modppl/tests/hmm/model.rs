@@ -1,5 +1,5 @@
 use nalgebra::{DVector,DMatrix};
-use rand::rngs::ThreadRng;
+use rand::Rng;
 
 use super::{HMMTrace,ParamStore,extend};
 use modppl::{GenFn,ArgDiff,Distribution,categorical};
@@ -30,9 +30,8 @@ impl HMM {
         HMM { params }
     }
 
-    pub fn kernel(&self, trace: &mut HMMTrace, state_probs: Vec<f64>, new_observation: usize) -> f64 {
-        let mut rng = ThreadRng::default();
-        let new_state = categorical.random(&mut rng, state_probs.clone()) as usize;
+    pub fn kernel(&self, rng: &mut impl Rng, trace: &mut HMMTrace, state_probs: Vec<f64>, new_observation: usize) -> f64 {
+        let new_state = categorical.random(rng, state_probs.clone()) as usize;
         let obs_probs = self.params.emission_matrix.column(new_state).transpose().data.as_vec().to_vec();
         extend(trace, new_state, new_observation);
         let weight = categorical.logpdf(&(new_observation as i64), obs_probs);
@@ -43,11 +42,11 @@ impl HMM {
 
 impl GenFn<(i64,ParamStore),(Vec<Option<usize>>,Vec<Option<usize>>),Vec<usize>> for HMM {
 
-    fn simulate(&self, _: (i64, ParamStore)) -> HMMTrace {
+    fn simulate(&self, _: &mut impl Rng, _: (i64, ParamStore)) -> HMMTrace {
         panic!("not implemented");
     }
 
-    fn generate(&self, args: (i64, ParamStore), constraints: (Vec<Option<usize>>,Vec<Option<usize>>)) -> (HMMTrace, f64) {
+    fn generate(&self, rng: &mut impl Rng, args: (i64, ParamStore), constraints: (Vec<Option<usize>>,Vec<Option<usize>>)) -> (HMMTrace, f64) {
         let (t, _) = args;
         if t != 1 {
             panic!("only expect generate to be called to initialize the state (T = 1)");
@@ -55,11 +54,11 @@ impl GenFn<(i64,ParamStore),(Vec<Option<usize>>,Vec<Option<usize>>),Vec<usize>>
         let new_observation = constraints.1[0].unwrap();
         let mut trace = HMMTrace::new(args, constraints, vec![new_observation], 0.);
         let state_probs = self.params.prior.data.as_vec().to_vec();
-        let weight = self.kernel(&mut trace, state_probs, new_observation);
+        let weight = self.kernel(rng, &mut trace, state_probs, new_observation);
         (trace, weight)
     }
 
-    fn update(&self, mut trace: HMMTrace, _: (i64, ParamStore), diff: modppl::ArgDiff, constraints: (Vec<Option<usize>>,Vec<Option<usize>>))
+    fn update(&self, rng: &mut impl Rng, mut trace: HMMTrace, _: (i64, ParamStore), diff: modppl::ArgDiff, constraints: (Vec<Option<usize>>,Vec<Option<usize>>))
         -> (HMMTrace, (Vec<Option<usize>>, Vec<Option<usize>>), f64)
     {
         match diff {
@@ -71,7 +70,7 @@ impl GenFn<(i64,ParamStore),(Vec<Option<usize>>,Vec<Option<usize>>),Vec<usize>>
                     .data
                     .as_vec()
                     .to_vec();
-                let weight = self.kernel(&mut trace, state_probs, new_observation);
+                let weight = self.kernel(rng, &mut trace, state_probs, new_observation);
                 (trace, (vec![], vec![]), weight)
             },
             _ => { panic!("Can't handle GF change type: {:?}", diff) },
@@ -1,17 +1,19 @@
+#![allow(clippy::needless_range_loop)]
+
 use std::any::Any;
 use std::sync::Arc;
 use std::fs::{write,create_dir_all};
-use rand::rngs::ThreadRng;
-use nalgebra::{dvector,dmatrix};
+use rand::{SeedableRng, rngs::{ThreadRng, StdRng}};
+use nalgebra::{dvector,dmatrix,DMatrix,DVector};
 
-use modppl::{Trace,Trie,Distribution,importance_sampling,normal,categorical};
+use modppl::{Trace,Trie,Distribution,importance_sampling,importance_resampling_with_proposal,normal,mvnormal,categorical};
 
 mod pointed_model;
 use pointed_model::types_2d::Bounds;
 use pointed_model::{PointedTrace,PointedModel};
 
 mod dyngenfns;
-use dyngenfns::{line_model, hierarchical_model};
+use dyngenfns::{line_model, line_proposal, hierarchical_model};
 
 
 #[test]
@@ -28,7 +30,7 @@ fn test_importance_handcoded() -> std::io::Result<()> {
     let constraints = (None, Some(obs));
 
     let (traces, log_normalized_weights, log_ml_estimate) = 
-        modppl::importance_sampling(&model, bounds, constraints, NUM_SAMPLES);
+        modppl::importance_sampling(&mut rng, &model, bounds, constraints, NUM_SAMPLES);
 
     dbg!(log_ml_estimate);
 
@@ -67,7 +69,7 @@ pub fn test_importance_dyngenfn() {
                 Box::leak(format!("ys / {}", i).into_boxed_str()),
                 Arc::new(0.5*x - 1. + normal.random(&mut rng, (0., 0.1))) as Arc<dyn Any + Send + Sync>);
             });
-    let (traces, log_normalized_weights, lml_estimate) = importance_sampling(&line_model, xs, observations, NUM_SAMPLES);
+    let (traces, log_normalized_weights, lml_estimate) = importance_sampling(&mut rng, &line_model, xs, observations, NUM_SAMPLES);
 
     let probs = log_normalized_weights.iter()
         .map(|w| w.exp())
@@ -106,7 +108,7 @@ pub fn test_importance_hierarchical() -> std::io::Result<()> {
     ys.into_iter().enumerate().for_each(|(i, y)| { observations.observe(&format!("(y, {})", i), Arc::new(y) as Arc<dyn Any + Send + Sync>); });
 
     let (traces, log_normalized_weights, lml_estimate) =
-        importance_sampling(&hierarchical_model, xs, observations, NUM_SAMPLES);
+        importance_sampling(&mut rng, &hierarchical_model, xs, observations, NUM_SAMPLES);
     // dbg!(&traces[0].data);
     // return Ok(());
 
@@ -137,4 +139,59 @@ pub fn test_importance_hierarchical() -> std::io::Result<()> {
 
     dbg!(lml_estimate);
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn test_importance_seeded() {
+    const NUM_SAMPLES: u32 = 100;
+
+    let xs = vec![-1., 0., 1.];
+    let mut observations = Trie::new();
+    xs.iter()
+        .enumerate()
+        .for_each(|(i, x)| {
+            observations.observe(&format!("ys / {}", i), Arc::new(0.5*x - 1.) as Arc<dyn Any + Send + Sync>);
+        });
+
+    // the same seed should yield bit-identical weights and estimates
+    let run = |seed: u64| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (_, log_normalized_weights, lml_estimate) =
+            importance_sampling(&mut rng, &line_model, xs.clone(), observations.clone(), NUM_SAMPLES);
+        (log_normalized_weights, lml_estimate)
+    };
+    assert_eq!(run(7), run(7));
+}
+
+#[test]
+pub fn test_importance_custom_proposal() {
+    const NUM_SAMPLES: u32 = 2000;
+
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let xs = vec![-1., 0., 1., 2.];
+    let ys = xs.iter().map(|x| 0.5*x - 1.).collect::<Vec<f64>>();
+    let mut observations = Trie::new();
+    ys.iter()
+        .enumerate()
+        .for_each(|(i, y)| {
+            observations.observe(&format!("ys / {}", i), Arc::new(*y) as Arc<dyn Any + Send + Sync>);
+        });
+
+    // exact evidence: ys ~ N(0, X diag(1,4) X^T + 0.01 I)
+    let n = xs.len();
+    let design = DMatrix::from_fn(n, 2, |i, j| if j == 0 { xs[i] } else { 1. });
+    let cov = &design * DMatrix::from_diagonal(&dvector![1., 4.]) * design.transpose() + DMatrix::identity(n, n) * 0.01;
+    let expected = mvnormal.logpdf(&DVector::from_vec(ys), (DVector::zeros(n), cov));
+
+    // the prior proposal is hopeless with this little noise, but a proposal near the posterior is not
+    let (traces, resampled_indices, lml_estimate) = importance_resampling_with_proposal(
+        &mut rng, &line_model, xs, observations, &line_proposal, (0.5, -1., 0.2), NUM_SAMPLES, 100
+    );
+    approx::assert_abs_diff_eq!(lml_estimate, expected, epsilon = 0.25);
+    assert_eq!(resampled_indices.len(), 100);
+    for idx in resampled_indices {
+        approx::assert_abs_diff_eq!(traces[idx].data.read::<f64>("slope"), 0.5, epsilon = 0.2);
+        approx::assert_abs_diff_eq!(traces[idx].data.read::<f64>("intercept"), -1., epsilon = 0.2);
+    }
+}
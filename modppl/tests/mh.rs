@@ -1,8 +1,7 @@
 use std::any::Any;
 use std::sync::Arc;
 use std::fs::{write, create_dir_all};
-use rand::rngs::ThreadRng;
-use nalgebra::{DVector, dvector, dmatrix};
+use nalgebra::{DMatrix, DVector, dvector, dmatrix};
 
 use modppl::prelude::*;
 
@@ -11,10 +10,13 @@ use pointed_model::types_2d::Bounds;
 use pointed_model::{PointedModel, DriftProposal};
 
 mod dyngenfns;
-use dyngenfns::{pointed_2d_model, pointed_2d_drift_proposal};
+use dyngenfns::{pointed_2d_model, pointed_2d_drift_proposal, line_model};
 use dyngenfns::{hierarchical_model, read_coeffs,
     hierarchical_drift_proposal,
-    add_or_remove_param_proposal
+    add_or_remove_param_proposal,
+    split_mean_model, split_merge_proposal, split_merge_involution,
+    gamma_prior_model, log_step_proposal, scale_involution,
+    mean_and_var
 };
 
 
@@ -23,6 +25,7 @@ fn test_metropolis_hastings_handcoded() -> std::io::Result<()> {
     create_dir_all("../data")?;
 
     const NUM_ITERS: u32 = 25000;
+    let mut rng = ThreadRng::default();
 
     let model = PointedModel { obs_cov: dmatrix![1., -3./5.; -3./5., 2.] };
     let proposal = DriftProposal { drift_cov: dmatrix![0.25, 0.; 0., 0.25] };
@@ -31,10 +34,10 @@ fn test_metropolis_hastings_handcoded() -> std::io::Result<()> {
 
     let constraints = (None, Some(obs));
 
-    let (mut trace, _) = model.generate(bounds, constraints);
+    let (mut trace, _) = model.generate(&mut rng, bounds, constraints);
     for iter in 0..NUM_ITERS {
         dbg!(iter);
-        let (new_trace, accepted) = modppl::mh(&model, trace, &proposal, ());
+        let (new_trace, accepted) = modppl::mh(&mut rng, &model, trace, &proposal, ());
         dbg!(accepted);
         trace = new_trace;
         let data = trace.data.0.clone().unwrap();
@@ -51,6 +54,7 @@ pub fn test_metropolis_hastings_dyngenfn() -> std::io::Result<()> {
     create_dir_all("../data")?;
 
     const NUM_ITERS: u32 = 25000;
+    let mut rng = ThreadRng::default();
 
     let bounds = Bounds { xmin: -5., xmax: 5., ymin: -5., ymax: 5. };
     let obs = dvector![0., 0.];
@@ -58,10 +62,10 @@ pub fn test_metropolis_hastings_dyngenfn() -> std::io::Result<()> {
     let mut observations = Trie::new();
     observations.observe("obs", Arc::new(obs) as Arc<dyn Any + Send + Sync>);
 
-    let mut trace = pointed_2d_model.generate((bounds, dmatrix![1., -3./5.; -3./5., 2.]), observations).0;
+    let mut trace = pointed_2d_model.generate(&mut rng, (bounds, dmatrix![1., -3./5.; -3./5., 2.]), observations).0;
     for iter in 0..NUM_ITERS {
         dbg!(iter);
-        let (new_trace, accepted) = mh(&pointed_2d_model, trace, &pointed_2d_drift_proposal, dmatrix![0.25, 0.; 0., 0.25]);
+        let (new_trace, accepted) = mh(&mut rng, &pointed_2d_model, trace, &pointed_2d_drift_proposal, dmatrix![0.25, 0.; 0., 0.25]);
         dbg!(accepted);
         trace = new_trace;
         let data = trace.data.read::<DVector<f64>>("latent");
@@ -88,23 +92,174 @@ pub fn test_metropolis_hastings_hierarchical() -> std::io::Result<()> {
     write("../data/hierarchical_data.json", format!("[{:?}, {:?}]", xs, ys))?;
     ys.into_iter().enumerate().for_each(|(i, y)| { observations.observe(&format!("(y, {})", i), Arc::new(y)); });
 
-    let mut trace = hierarchical_model.generate(xs, observations).0;
+    let mut trace = hierarchical_model.generate(&mut rng, xs, observations).0;
     let mut all_coeffs = vec![];
     for _ in 0..100 {
-        let (new_trace, _) = mh(&hierarchical_model, trace, &add_or_remove_param_proposal, ());
+        let (new_trace, _) = mh(&mut rng, &hierarchical_model, trace, &add_or_remove_param_proposal, ());
         trace = new_trace;
         all_coeffs.push(read_coeffs(&trace));
         for _ in 0..3 {
-            let (new_trace, _) = mh(&hierarchical_model, trace, &hierarchical_drift_proposal, 0.1);
+            let (new_trace, _) = mh(&mut rng, &hierarchical_model, trace, &hierarchical_drift_proposal, 0.1);
             trace = new_trace;
             all_coeffs.push(read_coeffs(&trace));
         }
         for _ in 0..10 {
-            let (new_trace, _) = mh(&hierarchical_model, trace, &hierarchical_drift_proposal, 0.01);
+            let (new_trace, _) = mh(&mut rng, &hierarchical_model, trace, &hierarchical_drift_proposal, 0.01);
             trace = new_trace;
             all_coeffs.push(read_coeffs(&trace));
         }
         write("../data/hierarchical_model.json", format!("{:?}", all_coeffs))?;
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn test_metropolis_hastings_seeded() {
+    let bounds = Bounds { xmin: -5., xmax: 5., ymin: -5., ymax: 5. };
+    let mut observations = DynTrie::new();
+    observations.observe("obs", Arc::new(dvector![0., 0.]));
+
+    // the same seed should yield identical accept/reject decisions and states
+    let run = |seed: u64| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut trace = pointed_2d_model.generate(&mut rng, (bounds, dmatrix![1., 0.; 0., 1.]), observations.clone()).0;
+        let mut decisions = vec![];
+        for _ in 0..100 {
+            let (new_trace, accepted) = mh(&mut rng, &pointed_2d_model, trace, &pointed_2d_drift_proposal, dmatrix![0.25, 0.; 0., 0.25]);
+            trace = new_trace;
+            decisions.push(accepted);
+        }
+        (decisions, trace.data.read::<DVector<f64>>("latent"), trace.logjp)
+    };
+    assert_eq!(run(3), run(3));
+}
+
+#[test]
+pub fn test_mala_line_model() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let xs = vec![-2., -1., 0., 1., 2., 3.];
+    let ys = xs.iter().map(|x| 0.7*x - 1.2 + normal.random(&mut rng, (0., 0.1))).collect::<Vec<f64>>();
+    let mut observations = DynTrie::new();
+    ys.iter().enumerate().for_each(|(i, y)| { observations.observe(&format!("ys / {}", i), Arc::new(*y)); });
+    let mut trace = line_model.generate(&mut rng, xs.clone(), observations).0;
+
+    // conjugate posterior over (slope, intercept)
+    let design = DMatrix::from_fn(xs.len(), 2, |i, j| if j == 0 { xs[i] } else { 1. });
+    let precision = dmatrix![1., 0.; 0., 0.25] + design.transpose() * &design / 0.01;
+    let post_mean = precision.clone().try_inverse().unwrap() * design.transpose() * DVector::from_vec(ys) / 0.01;
+
+    let mut selection = AddrMap::new();
+    selection.visit("slope");
+    selection.visit("intercept");
+    let mut num_accepted = 0;
+    let mut samples = vec![];
+    for iter in 0..2000 {
+        let accepted;
+        (trace, accepted) = mala(&mut rng, &line_model, trace, &selection, 0.02);
+        num_accepted += accepted as usize;
+        if iter >= 500 {
+            samples.push(dvector![trace.data.read::<f64>("slope"), trace.data.read::<f64>("intercept")]);
+        }
+    }
+    let mean = samples.iter().sum::<DVector<f64>>() / samples.len() as f64;
+    approx::assert_abs_diff_eq!(mean, post_mean, epsilon = 0.02);
+    assert!(num_accepted > 1000);
+}
+
+#[test]
+pub fn test_involutive_mh_split_merge() {
+    const NUM_ITERS: usize = 6000;
+    let ys = [1.9, 2.4, 1.1, 2.8];
+    let n = ys.len();
+
+    // the mean has prior variance 2 when split and 1 otherwise
+    let y = DVector::from_column_slice(&ys);
+    let evidence = |prior_var: f64| mvnormal.logpdf(&y, (DVector::zeros(n), DMatrix::identity(n, n) + DMatrix::from_element(n, n, prior_var))).exp();
+    let expected = evidence(2.) / (evidence(1.) + evidence(2.));
+
+    let mut observations = Trie::new();
+    for (i, y) in ys.iter().enumerate() {
+        observations.observe(&format!("ys / {}", i), Arc::new(*y) as Arc<dyn Any + Send + Sync>);
+    }
+    let mut rng = StdRng::seed_from_u64(0);
+    let (mut trace, _) = split_mean_model.generate(&mut rng, n, observations);
+    let mut num_split = 0;
+    let mut num_accepted = 0;
+    for _ in 0..NUM_ITERS {
+        let accepted;
+        (trace, accepted) = involutive_mh(&mut rng, &split_mean_model, trace, &split_merge_proposal, (), split_merge_involution);
+        num_accepted += accepted as usize;
+        let addrs = if trace.data.read::<bool>("split") { vec!["mu1", "mu2"] } else { vec!["mu"] };
+        for addr in addrs {
+            (trace, _) = slice_sampling(&mut rng, &split_mean_model, trace, addr, 1., 10);
+        }
+        num_split += trace.data.read::<bool>("split") as usize;
+    }
+    assert!(num_accepted > NUM_ITERS / 10);
+    approx::assert_abs_diff_eq!(num_split as f64 / NUM_ITERS as f64, expected, epsilon = 0.03);
+}
+
+#[test]
+pub fn test_involutive_mh_nonlinear() {
+    const NUM_ITERS: usize = 20000;
+    let mut rng = StdRng::seed_from_u64(0);
+    let (mut trace, _) = gamma_prior_model.generate(&mut rng, (), Trie::new());
+    let mut samples = vec![];
+    let mut num_accepted = 0;
+    for _ in 0..NUM_ITERS {
+        let accepted;
+        (trace, accepted) = involutive_mh(&mut rng, &gamma_prior_model, trace, &log_step_proposal, 0.5, scale_involution);
+        num_accepted += accepted as usize;
+        samples.push(trace.data.read::<f64>("scale"));
+    }
+    assert!(num_accepted > NUM_ITERS / 2);
+
+    // gamma(3, 1) has mean 3 and variance 3
+    let (mean, var) = mean_and_var(&samples);
+    approx::assert_abs_diff_eq!(mean, 3., epsilon = 0.15);
+    approx::assert_abs_diff_eq!(var, 3., epsilon = 0.5);
+}
+
+#[test]
+pub fn test_estimate_involution_log_abs_det() {
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let (trace, _) = gamma_prior_model.generate(&mut rng, (), Trie::new());
+    let mut fwd_choices = DynTrie::new();
+    fwd_choices.observe("u", Arc::new(0.7));
+    let (_, _, log_abs_det) = scale_involution(&trace.data, &fwd_choices);
+    let estimate = estimate_involution_log_abs_det(&mut rng, &gamma_prior_model, &trace, &fwd_choices, scale_involution).unwrap();
+    approx::assert_abs_diff_eq!(estimate, log_abs_det, epsilon = 1e-6);
+
+    // splitting, then merging back with the reverse auxiliary choices
+    let mut observations = Trie::new();
+    observations.observe("split", Arc::new(false) as Arc<dyn Any + Send + Sync>);
+    let (trace, _) = split_mean_model.generate(&mut rng, 0, observations);
+    let mut fwd_choices = DynTrie::new();
+    fwd_choices.observe("u", Arc::new(-0.4));
+    let (constraints, bwd_choices, log_abs_det) = split_merge_involution(&trace.data, &fwd_choices);
+    let estimate = estimate_involution_log_abs_det(&mut rng, &split_mean_model, &trace, &fwd_choices, split_merge_involution).unwrap();
+    approx::assert_abs_diff_eq!(estimate, log_abs_det, epsilon = 1e-6);
+
+    let (split_trace, _, _) = split_mean_model.update(&mut rng, trace, 0, ArgDiff::NoChange, constraints);
+    let (_, _, log_abs_det) = split_merge_involution(&split_trace.data, &bwd_choices);
+    let estimate = estimate_involution_log_abs_det(&mut rng, &split_mean_model, &split_trace, &bwd_choices, split_merge_involution).unwrap();
+    approx::assert_abs_diff_eq!(estimate, log_abs_det, epsilon = 1e-6);
+}
+
+#[test]
+pub fn test_try_involutive_mh_unconstrained_choice() {
+    let mut observations = Trie::new();
+    observations.observe("split", Arc::new(false) as Arc<dyn Any + Send + Sync>);
+    let mut rng = StdRng::seed_from_u64(0);
+    let (trace, _) = split_mean_model.generate(&mut rng, 0, observations);
+
+    // splits without constraining the new means
+    let involution = |_: &DynTrie, _: &DynTrie| {
+        let mut constraints = DynTrie::new();
+        constraints.observe("split", Arc::new(true));
+        (constraints, DynTrie::new(), 0.)
+    };
+    let result = try_involutive_metropolis_hastings(&mut rng, &split_mean_model, trace, &split_merge_proposal, (), involution);
+    assert!(matches!(result, Err(Error::InvalidArguments(_))));
+}
@@ -0,0 +1,78 @@
+#![allow(non_upper_case_globals)]
+
+use modppl::prelude::*;
+
+mod pointed_model;
+mod dyngenfns;
+use dyngenfns::{conjugate_model, mean_and_var};
+
+
+dyngen!(
+fn anisotropic_model() {
+    normal(0., 1.) %= "x";
+    normal(0., 10.) %= "y";
+});
+
+#[test]
+fn test_nuts_conjugate_posterior() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let ys = [1.5, 0.7, 2.1, 1.2];
+    let mut constraints = DynTrie::new();
+    for (i, y) in ys.iter().enumerate() {
+        constraints.observe(&format!("(y, {})", i), Arc::new(*y));
+    }
+    let (mut trace, _) = conjugate_model.generate(&mut rng, ys.len(), constraints);
+
+    let mut selection = AddrMap::new();
+    selection.visit("mu");
+
+    let mut sampler = NoUTurnSampler::new(1.);
+    trace = sampler.warmup(&mut rng, &conjugate_model, trace, &selection, 200);
+    assert!(sampler.step_size.is_finite() && sampler.step_size > 0.);
+
+    let mut samples = vec![];
+    let mut accept_stats = vec![];
+    for _ in 0..1000 {
+        let accept_stat;
+        (trace, accept_stat) = sampler.step(&mut rng, &conjugate_model, trace, &selection);
+        samples.push(trace.data.read::<f64>("mu"));
+        accept_stats.push(accept_stat);
+    }
+
+    let n = ys.len() as f64;
+    let (mean, var) = mean_and_var(&samples);
+    approx::assert_abs_diff_eq!(mean, ys.iter().sum::<f64>() / (n + 1.), epsilon = 0.05);
+    approx::assert_abs_diff_eq!(var, 1. / (n + 1.), epsilon = 0.05);
+    approx::assert_abs_diff_eq!(mean_and_var(&accept_stats).0, sampler.target_accept, epsilon = 0.1);
+}
+
+#[test]
+fn test_nuts_mass_adaptation() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut trace = anisotropic_model.simulate(&mut rng, ());
+
+    let mut selection = AddrMap::new();
+    selection.visit("x");
+    selection.visit("y");
+
+    let mut sampler = NoUTurnSampler::new(0.1);
+    sampler.adapt_mass = true;
+    trace = sampler.warmup(&mut rng, &anisotropic_model, trace, &selection, 400);
+    let inv_mass = sampler.inv_mass.clone().unwrap();
+    assert_eq!(inv_mass.len(), 2);
+    assert!(inv_mass[1] / inv_mass[0] > 20.);
+
+    let mut xs = vec![];
+    let mut ys = vec![];
+    for _ in 0..1000 {
+        (trace, _) = sampler.step(&mut rng, &anisotropic_model, trace, &selection);
+        xs.push(trace.data.read::<f64>("x"));
+        ys.push(trace.data.read::<f64>("y"));
+    }
+    let (x_mean, x_var) = mean_and_var(&xs);
+    let (y_mean, y_var) = mean_and_var(&ys);
+    approx::assert_abs_diff_eq!(x_mean, 0., epsilon = 0.15);
+    approx::assert_abs_diff_eq!(y_mean, 0., epsilon = 1.5);
+    approx::assert_relative_eq!(x_var, 1., max_relative = 0.2);
+    approx::assert_relative_eq!(y_var, 100., max_relative = 0.2);
+}
@@ -0,0 +1,135 @@
+#![cfg(feature = "parallel")]
+#![allow(non_upper_case_globals)]
+
+use nalgebra::{dvector,dmatrix};
+use modppl::prelude::*;
+
+mod hmm;
+mod pointed_model;
+mod dyngenfns;
+use dyngenfns::{conjugate_model, random_walk_model, random_walk_proposal, random_walk_observations, random_walk_log_ml};
+
+
+dyngen!(
+fn mu_proposal(mean: f64, std: f64) {
+    normal(mean, std) %= "mu";
+});
+
+#[test]
+fn test_par_importance_sampling() {
+    let y = 0.8;
+    let mut constraints = DynTrie::new();
+    constraints.observe("(y, 0)", Arc::new(y));
+    let expected = normal.logpdf(&y, (0., 2_f64.sqrt()));
+
+    let run = |seed: u64| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (traces, log_weights, log_ml) = par_importance_sampling(&mut rng, &conjugate_model, 1, constraints.clone(), 5000);
+        let mus = traces.iter().map(|tr| tr.data.read::<f64>("mu")).collect::<Vec<f64>>();
+        (mus, log_weights, log_ml)
+    };
+    let (mus, log_weights, log_ml) = run(0);
+    assert_eq!(mus.len(), 5000);
+    assert_eq!((mus.clone(), log_weights.clone(), log_ml), run(0));
+    approx::assert_abs_diff_eq!(log_ml, expected, epsilon = 0.05);
+
+    let posterior_mean = mus.iter().zip(&log_weights).map(|(mu, w)| mu * w.exp()).sum::<f64>();
+    approx::assert_abs_diff_eq!(posterior_mean, y / 2., epsilon = 0.05);
+}
+
+#[test]
+fn test_par_particle_filter() {
+    const NUM_PARTICLES: usize = 10000;
+
+    let prior = dvector![0.2, 0.3, 0.5];
+    let emission_matrix = dmatrix![
+        0.1, 0.2, 0.7;
+        0.2, 0.7, 0.1;
+        0.7, 0.2, 0.1
+    ].transpose();
+    let transition_matrix = dmatrix![
+        0.4, 0.4, 0.2;
+        0.2, 0.3, 0.5;
+        0.9, 0.05, 0.05
+    ].transpose();
+    let data = vec![0, 0, 1, 2];
+    let expected = hmm::hmm_forward_alg(prior.clone(), emission_matrix.clone(), transition_matrix.clone(), &data).ln();
+
+    let run = |seed: u64| {
+        let params = hmm::HMMParams::new(prior.clone(), emission_matrix.clone(), transition_matrix.clone());
+        let mut filter = ParticleSystem::new(hmm::HMM::new(params), NUM_PARTICLES, StdRng::seed_from_u64(seed))
+            .with_ess_threshold(0.5);
+        filter.par_init_step(hmm::ParamStore { }, (vec![None], vec![Some(data[0])]));
+        for obs in &data[1..] {
+            filter = filter.par_step((vec![None], vec![Some(*obs)]));
+        }
+        let states = filter.traces.iter().map(|tr| tr.data.0.clone()).collect::<Vec<_>>();
+        (states, filter.log_marginal_likelihood_estimate())
+    };
+
+    let (states, log_ml) = run(3);
+    assert_eq!((states, log_ml), run(3));
+    approx::assert_abs_diff_eq!(log_ml, expected, epsilon = 0.05);
+}
+
+#[test]
+fn test_par_importance_sampling_with_proposal() {
+    let y = 0.8;
+    let mut constraints = DynTrie::new();
+    constraints.observe("(y, 0)", Arc::new(y));
+    let expected = normal.logpdf(&y, (0., 2_f64.sqrt()));
+
+    // a proposal close to the posterior N(0.4, 0.5)
+    let run = |seed: u64| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (traces, log_weights, log_ml) = par_importance_sampling_with_proposal(
+            &mut rng, &conjugate_model, 1, constraints.clone(), &mu_proposal, (0.4, 0.75), 2000
+        );
+        let mus = traces.iter().map(|tr| tr.data.read::<f64>("mu")).collect::<Vec<f64>>();
+        (mus, log_weights, log_ml)
+    };
+    let (mus, log_weights, log_ml) = run(0);
+    assert_eq!((mus, log_weights, log_ml), run(0));
+    approx::assert_abs_diff_eq!(log_ml, expected, epsilon = 0.02);
+
+    let mut rng = StdRng::seed_from_u64(1);
+    let (traces, indices, log_ml) = par_importance_resampling_with_proposal(
+        &mut rng, &conjugate_model, 1, constraints.clone(), &mu_proposal, (0.4, 0.75), 2000, 100
+    );
+    assert_eq!(indices.len(), 100);
+    assert!(indices.iter().all(|idx| *idx < traces.len()));
+    approx::assert_abs_diff_eq!(log_ml, expected, epsilon = 0.02);
+
+    let (traces, indices, log_ml) = par_importance_resampling(&mut rng, &conjugate_model, 1, constraints, 5000, 100);
+    assert_eq!(indices.len(), 100);
+    assert!(indices.iter().all(|idx| *idx < traces.len()));
+    approx::assert_abs_diff_eq!(log_ml, expected, epsilon = 0.05);
+}
+
+#[test]
+fn test_par_smc_step_with_proposal_and_rejuvenate() {
+    const NUM_PARTICLES: usize = 2000;
+    let ys = [0.3, 1.1, 0.8, 2.0, 1.4, 0.9];
+    let expected = random_walk_log_ml(&ys);
+
+    let run = |seed: u64| {
+        let mut filter = ParticleSystem::new(random_walk_model, NUM_PARTICLES, StdRng::seed_from_u64(seed));
+        let mut data_it = random_walk_observations(&ys).into_iter().zip(ys);
+        filter.init_step(0., vec![data_it.next().unwrap().0]);
+        for (constraints, y) in data_it {
+            filter.resample();
+            let t = filter.traces[0].args.0;
+            let mut mask = AddrMap::new();
+            mask.visit(&format!("{} / x", t - 1));
+            let acceptance_rate = filter.par_rejuvenate(|rng, model, trace| regen_mh(rng, model, trace, &mask));
+            assert!(acceptance_rate > 0.);
+            filter = filter.par_step_with_proposal(vec![constraints], &random_walk_proposal, y);
+        }
+        let xs = filter.traces.iter().map(|tr| tr.retv.clone().unwrap()).collect::<Vec<_>>();
+        (xs, filter.log_marginal_likelihood_estimate())
+    };
+
+    let (xs, log_ml) = run(5);
+    assert_eq!((xs, log_ml), run(5));
+    approx::assert_abs_diff_eq!(log_ml, expected, epsilon = 0.05);
+}
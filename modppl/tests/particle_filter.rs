@@ -1,7 +1,7 @@
-use rand::rngs::ThreadRng;
+use rand::{SeedableRng, rngs::{ThreadRng, StdRng}};
 use nalgebra::{dvector,dmatrix};
 
-use modppl::ParticleSystem;
+use modppl::{ParticleSystem, ResamplingScheme, Distribution, categorical};
 
 mod hmm;
 
@@ -76,4 +76,228 @@ fn test_particle_filter() -> std::io::Result<()> {
     approx::assert_abs_diff_eq!(lml_estimate, expected, epsilon = 0.03);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_particle_filter_seeded() {
+    const NUM_PARTICLES: usize = 100;
+
+    let prior = dvector![0.2, 0.3, 0.5];
+    let emission_matrix = dmatrix![
+        0.1, 0.2, 0.7;
+        0.2, 0.7, 0.1;
+        0.7, 0.2, 0.1
+    ].transpose();
+    let transition_matrix = dmatrix![
+        0.4, 0.4, 0.2;
+        0.2, 0.3, 0.5;
+        0.9, 0.05, 0.05
+    ].transpose();
+
+    // the same seed should yield identical particles and estimates
+    let run = |seed: u64| {
+        let params = hmm::HMMParams::new(prior.clone(), emission_matrix.clone(), transition_matrix.clone());
+        let mut filter = ParticleSystem::new(hmm::HMM::new(params), NUM_PARTICLES, StdRng::seed_from_u64(seed));
+        filter.init_step(hmm::ParamStore { }, (vec![None], vec![Some(0)]));
+        for obs in [0, 1, 2] {
+            filter = filter.step((vec![None], vec![Some(obs)]));
+            filter.resample();
+        }
+        let states = filter.traces.iter().map(|tr| tr.data.0.clone()).collect::<Vec<_>>();
+        (states, filter.log_marginal_likelihood_estimate())
+    };
+    assert_eq!(run(11), run(11));
+}
+
+const SCHEMES: [ResamplingScheme; 4] = [
+    ResamplingScheme::Multinomial,
+    ResamplingScheme::Systematic,
+    ResamplingScheme::Stratified,
+    ResamplingScheme::Residual
+];
+
+#[test]
+fn test_resampling_schemes() {
+    let mut rng = StdRng::seed_from_u64(0);
+    const N: usize = 20;
+    const NUM_TRIALS: usize = 2000;
+    let weights = [0.05, 0.5, 0., 0.3, 0.15];
+
+    for scheme in SCHEMES {
+        let mut mean_counts = vec![0.; weights.len()];
+        for _ in 0..NUM_TRIALS {
+            let parents = scheme.resample(&mut rng, &weights, N);
+            assert_eq!(parents.len(), N);
+            let mut counts = vec![0; weights.len()];
+            for i in parents {
+                counts[i] += 1;
+            }
+            assert_eq!(counts[2], 0);
+            for (i, w) in weights.iter().enumerate() {
+                let expected = N as f64 * w;
+                match scheme {
+                    ResamplingScheme::Systematic => {
+                        assert!(counts[i] as f64 >= expected.floor() && counts[i] as f64 <= expected.ceil());
+                    }
+                    ResamplingScheme::Residual => {
+                        assert!(counts[i] as f64 >= expected.floor());
+                    }
+                    _ => { }
+                }
+                mean_counts[i] += counts[i] as f64 / NUM_TRIALS as f64;
+            }
+        }
+        for (i, w) in weights.iter().enumerate() {
+            approx::assert_abs_diff_eq!(mean_counts[i], N as f64 * w, epsilon = 0.2);
+        }
+    }
+}
+
+#[test]
+fn test_particle_filter_resampling_schemes() {
+    const NUM_PARTICLES: usize = 2000;
+
+    let prior = dvector![0.2, 0.3, 0.5];
+    let emission_matrix = dmatrix![
+        0.1, 0.2, 0.7;
+        0.2, 0.7, 0.1;
+        0.7, 0.2, 0.1
+    ].transpose();
+    let transition_matrix = dmatrix![
+        0.4, 0.4, 0.2;
+        0.2, 0.3, 0.5;
+        0.9, 0.05, 0.05
+    ].transpose();
+    let data = vec![0, 0, 1, 2];
+    let expected = hmm::hmm_forward_alg(prior.clone(), emission_matrix.clone(), transition_matrix.clone(), &data).ln();
+
+    for (seed, scheme) in SCHEMES.into_iter().enumerate() {
+        let params = hmm::HMMParams::new(prior.clone(), emission_matrix.clone(), transition_matrix.clone());
+        let mut filter = ParticleSystem::new(hmm::HMM::new(params), NUM_PARTICLES, StdRng::seed_from_u64(seed as u64))
+            .with_resampling(scheme);
+        filter.init_step(hmm::ParamStore { }, (vec![None], vec![Some(data[0])]));
+        for obs in &data[1..] {
+            filter = filter.step((vec![None], vec![Some(*obs)]));
+            filter.resample();
+        }
+        approx::assert_abs_diff_eq!(filter.log_marginal_likelihood_estimate(), expected, epsilon = 0.05);
+    }
+}
+
+#[test]
+fn test_particle_filter_adaptive_resampling() {
+    const NUM_PARTICLES: usize = 10000;
+
+    let prior = dvector![0.2, 0.3, 0.5];
+    let emission_matrix = dmatrix![
+        0.1, 0.2, 0.7;
+        0.2, 0.7, 0.1;
+        0.7, 0.2, 0.1
+    ].transpose();
+    let transition_matrix = dmatrix![
+        0.4, 0.4, 0.2;
+        0.2, 0.3, 0.5;
+        0.9, 0.05, 0.05
+    ].transpose();
+    let data = vec![0, 0, 1, 2, 2, 0];
+    let expected = hmm::hmm_forward_alg(prior.clone(), emission_matrix.clone(), transition_matrix.clone(), &data).ln();
+
+    // a threshold of 0 never resamples (plain sequential importance sampling), while 1 almost always does
+    for (seed, threshold) in [0., 0.5, 1.].into_iter().enumerate() {
+        let params = hmm::HMMParams::new(prior.clone(), emission_matrix.clone(), transition_matrix.clone());
+        let mut filter = ParticleSystem::new(hmm::HMM::new(params), NUM_PARTICLES, StdRng::seed_from_u64(seed as u64))
+            .with_ess_threshold(threshold);
+        filter.init_step(hmm::ParamStore { }, (vec![None], vec![Some(data[0])]));
+        for obs in &data[1..] {
+            filter = filter.step((vec![None], vec![Some(*obs)]));
+        }
+        approx::assert_abs_diff_eq!(filter.log_marginal_likelihood_estimate(), expected, epsilon = 0.1);
+    }
+}
+
+#[test]
+fn test_maybe_resample() {
+    let prior = dvector![0.2, 0.3, 0.5];
+    let emission_matrix = dmatrix![
+        0.1, 0.2, 0.7;
+        0.2, 0.7, 0.1;
+        0.7, 0.2, 0.1
+    ].transpose();
+    let transition_matrix = dmatrix![
+        0.4, 0.4, 0.2;
+        0.2, 0.3, 0.5;
+        0.9, 0.05, 0.05
+    ].transpose();
+    let params = hmm::HMMParams::new(prior, emission_matrix, transition_matrix);
+    let mut filter = ParticleSystem::new(hmm::HMM::new(params), 100, StdRng::seed_from_u64(0));
+    filter.init_step(hmm::ParamStore { }, (vec![None], vec![Some(0)]));
+
+    let ess = filter.effective_sample_size();
+    assert!(ess > 1. && ess < 100.);
+    let log_ml = filter.log_marginal_likelihood_estimate();
+    assert!(!filter.maybe_resample(0.));
+    approx::assert_abs_diff_eq!(filter.effective_sample_size(), ess);
+
+    assert!(filter.maybe_resample(1.));
+    approx::assert_abs_diff_eq!(filter.effective_sample_size(), 100., epsilon = 1e-9);
+    approx::assert_abs_diff_eq!(filter.log_marginal_likelihood_estimate(), log_ml, epsilon = 1e-9);
+}
+
+#[test]
+fn test_particle_filter_rejuvenate() {
+    const NUM_PARTICLES: usize = 5000;
+
+    let prior = dvector![0.2, 0.3, 0.5];
+    let emission_matrix = dmatrix![
+        0.1, 0.2, 0.7;
+        0.2, 0.7, 0.1;
+        0.7, 0.2, 0.1
+    ].transpose();
+    let transition_matrix = dmatrix![
+        0.4, 0.4, 0.2;
+        0.2, 0.3, 0.5;
+        0.9, 0.05, 0.05
+    ].transpose();
+    let data = vec![0, 0, 1, 2];
+    let expected = hmm::hmm_forward_alg(prior.clone(), emission_matrix.clone(), transition_matrix.clone(), &data).ln();
+
+    // filtering distribution of the final state
+    let mut filtered = prior.clone();
+    for (t, obs) in data.iter().enumerate() {
+        if t > 0 {
+            filtered = &transition_matrix * filtered;
+        }
+        filtered = filtered.component_mul(&emission_matrix.row(*obs).transpose());
+        filtered /= filtered.sum();
+    }
+
+    // Gibbs move on the final state given the previous state and the final observation
+    let gibbs = |rng: &mut StdRng, _: &hmm::HMM, mut trace: hmm::HMMTrace| {
+        let t = trace.data.0.len() - 1;
+        let prev_state = trace.data.0[t-1].unwrap();
+        let obs = trace.data.1[t].unwrap();
+        let probs = (0..3)
+            .map(|z| transition_matrix[(z, prev_state)] * emission_matrix[(obs, z)])
+            .collect::<Vec<f64>>();
+        let new_state = categorical.random(rng, probs.iter().map(|p| p / probs.iter().sum::<f64>()).collect()) as usize;
+        let old_state = trace.data.0[t].replace(new_state).unwrap();
+        trace.logjp += emission_matrix[(obs, new_state)].ln() - emission_matrix[(obs, old_state)].ln();
+        (trace, new_state != old_state)
+    };
+
+    let params = hmm::HMMParams::new(prior.clone(), emission_matrix.clone(), transition_matrix.clone());
+    let mut filter = ParticleSystem::new(hmm::HMM::new(params), NUM_PARTICLES, StdRng::seed_from_u64(0));
+    filter.init_step(hmm::ParamStore { }, (vec![None], vec![Some(data[0])]));
+    for obs in &data[1..] {
+        filter = filter.step((vec![None], vec![Some(*obs)]));
+        filter.resample();
+        let acceptance_rate = filter.rejuvenate(gibbs);
+        assert!(acceptance_rate > 0. && acceptance_rate < 1.);
+    }
+    approx::assert_abs_diff_eq!(filter.log_marginal_likelihood_estimate(), expected, epsilon = 0.05);
+
+    for (z, p) in filtered.iter().enumerate() {
+        let freq = filter.traces.iter().filter(|tr| tr.data.0.last().unwrap().unwrap() == z).count() as f64 / NUM_PARTICLES as f64;
+        approx::assert_abs_diff_eq!(freq, p, epsilon = 0.02);
+    }
+}
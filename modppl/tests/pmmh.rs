@@ -0,0 +1,168 @@
+#![allow(non_upper_case_globals)]
+
+use nalgebra::{dvector,dmatrix,DMatrix};
+use modppl::prelude::*;
+
+mod hmm;
+
+
+dyngen!(
+fn stickiness_prior() -> f64 {
+    let z = normal(0., 1.5) %= "z";
+    1. / (1. + (-z).exp())
+});
+
+dyngen!(
+fn stickiness_drift(tr: Weak<DynTrace<(),f64>>) {
+    let z = tr.upgrade().unwrap().data.read::<f64>("z");
+    normal(z, 0.7) %= "z";
+});
+
+fn sticky_transitions(rho: f64) -> DMatrix<f64> {
+    dmatrix![rho, 1. - rho; 1. - rho, rho].transpose()
+}
+
+#[test]
+fn test_pmmh_hmm_stickiness() {
+    const NUM_PARTICLES: usize = 50;
+    const NUM_ITERS: usize = 1500;
+    const BURN_IN: usize = 200;
+
+    let prior = dvector![0.5, 0.5];
+    let emission_matrix = dmatrix![0.9, 0.1; 0.2, 0.8].transpose();
+    let data = vec![0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 0, 0, 1, 1, 1];
+    let observations = data.iter().map(|obs| (vec![None], vec![Some(*obs)])).collect::<Vec<_>>();
+
+    // exact posterior mean of the logit stickiness on a grid
+    let (mut total, mut weighted) = (0., 0.);
+    for i in 0..1600 {
+        let z = -8. + 0.01 * i as f64;
+        let rho = 1. / (1. + f64::exp(-z));
+        let likelihood = hmm::hmm_forward_alg(prior.clone(), emission_matrix.clone(), sticky_transitions(rho), &data);
+        let density = normal.logpdf(&z, (0., 1.5)).exp() * likelihood;
+        total += density;
+        weighted += z * density;
+    }
+    let expected = weighted / total;
+
+    let make_model = |rho: &f64| {
+        let model = hmm::HMM::new(hmm::HMMParams::new(prior.clone(), emission_matrix.clone(), sticky_transitions(*rho)));
+        (model, hmm::ParamStore { })
+    };
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut trace = stickiness_prior.simulate(&mut rng, ());
+    let (model, args) = make_model(trace.retv.as_ref().unwrap());
+    let mut log_ml = estimate_log_marginal_likelihood(&mut rng, model, args, &observations, NUM_PARTICLES);
+    let mut mean = 0.;
+    let mut num_accepted = 0;
+    for iter in 0..NUM_ITERS {
+        let accepted;
+        (trace, log_ml, accepted) = pmmh(&mut rng, &stickiness_prior, trace, log_ml, &stickiness_drift, (), make_model, &observations, NUM_PARTICLES);
+        if accepted {
+            num_accepted += 1;
+        }
+        if iter >= BURN_IN {
+            mean += trace.data.read::<f64>("z") / (NUM_ITERS - BURN_IN) as f64;
+        }
+    }
+    assert!(num_accepted > NUM_ITERS / 10);
+    approx::assert_abs_diff_eq!(mean, expected, epsilon = 0.2);
+}
+
+#[test]
+fn test_estimate_log_marginal_likelihood() {
+    let prior = dvector![0.5, 0.5];
+    let emission_matrix = dmatrix![0.9, 0.1; 0.2, 0.8].transpose();
+    let transition_matrix = sticky_transitions(0.8);
+    let data = vec![0, 1, 1, 0, 1];
+    let observations = data.iter().map(|obs| (vec![None], vec![Some(*obs)])).collect::<Vec<_>>();
+    let expected = hmm::hmm_forward_alg(prior.clone(), emission_matrix.clone(), transition_matrix.clone(), &data).ln();
+
+    let mut rng = StdRng::seed_from_u64(1);
+    let model = hmm::HMM::new(hmm::HMMParams::new(prior, emission_matrix, transition_matrix));
+    let log_ml = estimate_log_marginal_likelihood(&mut rng, model, hmm::ParamStore { }, &observations, 10000);
+    approx::assert_abs_diff_eq!(log_ml, expected, epsilon = 0.03);
+}
+
+#[test]
+#[should_panic(expected = "at least one observation")]
+fn test_estimate_log_marginal_likelihood_no_observations() {
+    let model = hmm::HMM::new(hmm::HMMParams::new(dvector![0.5, 0.5], dmatrix![0.9, 0.1; 0.2, 0.8], sticky_transitions(0.8)));
+    let observations = Vec::<usize>::new().iter().map(|obs| (vec![None], vec![Some(*obs)])).collect::<Vec<_>>();
+    estimate_log_marginal_likelihood(&mut StdRng::seed_from_u64(1), model, hmm::ParamStore { }, &observations, 10);
+}
+
+dyngen!(
+fn noisy_walk_kernel(t: i64, state: (f64, f64)) -> (f64, f64) {
+    // the observation noise is a static parameter carried along in the state
+    let (prev_x, noise) = state;
+    let mean = if t == 0 { 0. } else { prev_x };
+    let x = normal(mean, 1.) %= "x";
+    normal(x, noise) %= "y";
+    (x, noise)
+});
+
+const noisy_walk_model: DynUnfold<(f64, f64)> = DynUnfold { kernel: noisy_walk_kernel };
+
+dyngen!(
+fn log_noise_prior() -> f64 {
+    let log_noise = normal(-0.5, 0.5) %= "log_noise";
+    log_noise.exp()
+});
+
+dyngen!(
+fn log_noise_drift(tr: Weak<DynTrace<(),f64>>) {
+    let log_noise = tr.upgrade().unwrap().data.read::<f64>("log_noise");
+    normal(log_noise, 0.4) %= "log_noise";
+});
+
+/// Exact log marginal likelihood of `ys` under `noisy_walk_model` by Kalman filtering.
+fn noisy_walk_log_ml(ys: &[f64], noise: f64) -> f64 {
+    let (mut mean, mut var) = (0., 1.);
+    let mut log_ml = 0.;
+    for y in ys {
+        log_ml += normal.logpdf(y, (mean, (var + noise*noise).sqrt()));
+        let gain = var / (var + noise*noise);
+        mean += gain * (y - mean);
+        var = (1. - gain) * var + 1.;
+    }
+    log_ml
+}
+
+#[test]
+fn test_pmmh_dynunfold_noise() {
+    const NUM_PARTICLES: usize = 30;
+    const NUM_ITERS: usize = 800;
+    const BURN_IN: usize = 100;
+    let ys = [0.1, 0.9, 0.4, 1.6, 1.2, 2.3, 1.7, 1.9];
+    let observations = ys.iter().map(|y| {
+        let mut constraints = DynTrie::new();
+        constraints.observe("y", Arc::new(*y));
+        vec![constraints]
+    }).collect::<Vec<_>>();
+
+    // exact posterior mean of the log noise on a grid
+    let (mut total, mut weighted) = (0., 0.);
+    for i in 0..800 {
+        let log_noise = -4. + 0.005 * i as f64;
+        let density = (normal.logpdf(&log_noise, (-0.5, 0.5)) + noisy_walk_log_ml(&ys, log_noise.exp())).exp();
+        total += density;
+        weighted += log_noise * density;
+    }
+    let expected = weighted / total;
+
+    let make_model = |noise: &f64| (noisy_walk_model, (0., *noise));
+    let mut rng = StdRng::seed_from_u64(2);
+    let mut trace = log_noise_prior.simulate(&mut rng, ());
+    let (model, args) = make_model(trace.retv.as_ref().unwrap());
+    let mut log_ml = estimate_log_marginal_likelihood(&mut rng, model, args, &observations, NUM_PARTICLES);
+    let mut mean = 0.;
+    for iter in 0..NUM_ITERS {
+        (trace, log_ml, _) = pmmh(&mut rng, &log_noise_prior, trace, log_ml, &log_noise_drift, (), make_model, &observations, NUM_PARTICLES);
+        if iter >= BURN_IN {
+            mean += trace.data.read::<f64>("log_noise") / (NUM_ITERS - BURN_IN) as f64;
+        }
+    }
+    approx::assert_abs_diff_eq!(mean, expected, epsilon = 0.1);
+}
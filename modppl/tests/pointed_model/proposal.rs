@@ -3,7 +3,7 @@ use nalgebra::DMatrix;
 use modppl::{Distribution, mvnormal, GenFn, Trace, ArgDiff};
 use super::model::PointedBuffer;
 use super::types_2d::{Point,Bounds};
-use rand::rngs::ThreadRng;
+use rand::Rng;
 
 
 pub struct DriftProposal {
@@ -14,19 +14,18 @@ pub type DriftProposalArgs = (Weak<Trace<Bounds,PointedBuffer,Point>>,());
 
 impl GenFn<DriftProposalArgs,PointedBuffer,()> for DriftProposal {
 
-    fn simulate(&self, args: DriftProposalArgs) -> Trace<DriftProposalArgs,PointedBuffer,()> {
-        let mut rng = ThreadRng::default();
+    fn simulate(&self, rng: &mut impl Rng, args: DriftProposalArgs) -> Trace<DriftProposalArgs,PointedBuffer,()> {
         let prev_trace = args.0.upgrade().unwrap();
         let mut choices = (None, prev_trace.data.1.clone());
 
-        let new_latent = mvnormal.random(&mut rng, (prev_trace.data.0.clone().unwrap(), self.drift_cov.clone()));
+        let new_latent = mvnormal.random(rng, (prev_trace.data.0.clone().unwrap(), self.drift_cov.clone()));
         choices.0 = Some(new_latent);
         let logp = mvnormal.logpdf(&choices.0.clone().unwrap(), (prev_trace.data.0.clone().unwrap(), self.drift_cov.clone()));
 
         Trace::new(args, choices, (), logp)
     }
 
-    fn generate(&self, args: DriftProposalArgs, constraints: PointedBuffer) -> (Trace<DriftProposalArgs,PointedBuffer,()>, f64) {
+    fn generate(&self, rng: &mut impl Rng, args: DriftProposalArgs, constraints: PointedBuffer) -> (Trace<DriftProposalArgs,PointedBuffer,()>, f64) {
         let prev_trace = args.0.upgrade().unwrap();
         let mut choices = (None, prev_trace.data.1.clone());
 
@@ -40,8 +39,7 @@ impl GenFn<DriftProposalArgs,PointedBuffer,()> for DriftProposal {
                 weight = logp;
             }
             None => {
-                let mut rng = ThreadRng::default();
-                new_latent = mvnormal.random(&mut rng, (prev_trace.data.0.clone().unwrap(), self.drift_cov.clone()));
+                new_latent = mvnormal.random(rng, (prev_trace.data.0.clone().unwrap(), self.drift_cov.clone()));
                 logp = mvnormal.logpdf(&new_latent, (prev_trace.data.0.clone().unwrap(), self.drift_cov.clone()));
             }
         }
@@ -50,7 +48,7 @@ impl GenFn<DriftProposalArgs,PointedBuffer,()> for DriftProposal {
         (Trace::new(args, choices, (), logp), weight)
     }
 
-    fn update(&self, _: Trace<DriftProposalArgs,PointedBuffer,()>, _: DriftProposalArgs, _: ArgDiff, _: PointedBuffer) -> (Trace<DriftProposalArgs,PointedBuffer,()>, PointedBuffer, f64) {
+    fn update(&self, _: &mut impl Rng, _: Trace<DriftProposalArgs,PointedBuffer,()>, _: DriftProposalArgs, _: ArgDiff, _: PointedBuffer) -> (Trace<DriftProposalArgs,PointedBuffer,()>, PointedBuffer, f64) {
         panic!("not implemented")
     }
 }
\ No newline at end of file
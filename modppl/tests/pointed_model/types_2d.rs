@@ -1,4 +1,4 @@
-use rand::rngs::ThreadRng;
+use rand::Rng;
 use modppl::{Distribution,u01};
 use nalgebra::{DVector,dvector};
 
@@ -20,7 +20,7 @@ impl Distribution<Point,Bounds> for Uniform2D {
         }
     }
 
-    fn random(&self, rng: &mut ThreadRng, b: Bounds) -> Point {
+    fn random(&self, rng: &mut impl Rng, b: Bounds) -> Point {
         assert!(b.xmax > b.xmin);
         assert!(b.ymax > b.ymin);
         dvector![
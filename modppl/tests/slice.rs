@@ -0,0 +1,166 @@
+#![allow(non_upper_case_globals)]
+
+use nalgebra::{DMatrix, DVector};
+use modppl::prelude::*;
+
+mod pointed_model;
+mod dyngenfns;
+use dyngenfns::{normal_mean_model, observe_ys};
+
+
+fn rbf_kernel(xs: &[f64], length_scale: f64) -> DMatrix<f64> {
+    let n = xs.len();
+    DMatrix::from_fn(n, n, |i, j| (-(xs[i] - xs[j]).powi(2) / (2. * length_scale * length_scale)).exp() + if i == j { 1e-6 } else { 0. })
+}
+
+// gaussian process regression with a latent function value at each input
+dyngen!(
+fn gp_model(cov: DMatrix<f64>, noise: f64) {
+    let n = cov.nrows();
+    let f = mvnormal(DVector::zeros(n), cov) %= "f";
+    for i in 0..n {
+        normal(f[i], noise) %= &format!("ys / {}", i);
+    }
+});
+
+dyngen!(
+fn scale_model(n: usize) {
+    let scale = gamma(2., 1.) %= "scale";
+    for i in 0..n {
+        normal(0., scale) %= &format!("ys / {}", i);
+    }
+});
+
+#[test]
+fn test_elliptical_slice_gp() {
+    const NUM_ITERS: usize = 4000;
+    const BURN_IN: usize = 500;
+    let noise = 0.3;
+
+    let xs = [-2., -1., 0., 1., 2.];
+    let ys = [0.9, 0.1, -0.8, -0.2, 0.7];
+    let cov = rbf_kernel(&xs, 1.);
+
+    // exact posterior mean: K (K + noise^2 I)^-1 y
+    let n = xs.len();
+    let y = DVector::from_column_slice(&ys);
+    let expected = &cov * (&cov + DMatrix::identity(n, n) * noise * noise).try_inverse().unwrap() * y;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let (mut trace, _) = gp_model.generate(&mut rng, (cov.clone(), noise), observe_ys(&ys));
+    let prior_params = (DVector::zeros(n), cov);
+    let mut mean = DVector::zeros(n);
+    for iter in 0..NUM_ITERS {
+        let accepted;
+        (trace, accepted) = elliptical_slice(&mut rng, &gp_model, trace, "f", &mvnormal, &prior_params);
+        assert!(accepted);
+        if iter >= BURN_IN {
+            mean += trace.data.read::<DVector<f64>>("f") / (NUM_ITERS - BURN_IN) as f64;
+        }
+    }
+    for i in 0..n {
+        approx::assert_abs_diff_eq!(mean[i], expected[i], epsilon = 0.05);
+    }
+}
+
+#[test]
+fn test_elliptical_slice_normal() {
+    const NUM_ITERS: usize = 4000;
+    let ys = [2.1, 1.4, 2.6, 1.9];
+
+    // conjugate posterior of the mean
+    let (prior_mean, prior_var, noise_var) = (1., 4., 0.25);
+    let posterior_var = 1. / (1. / prior_var + ys.len() as f64 / noise_var);
+    let posterior_mean = posterior_var * (prior_mean / prior_var + ys.iter().sum::<f64>() / noise_var);
+
+    let mut rng = StdRng::seed_from_u64(1);
+    let (mut trace, _) = normal_mean_model.generate(&mut rng, ys.len(), observe_ys(&ys));
+    let (mut sum, mut sum_sq) = (0., 0.);
+    for _ in 0..NUM_ITERS {
+        (trace, _) = elliptical_slice_sampling(&mut rng, &normal_mean_model, trace, "mu", &normal, &(1., 2.));
+        let mu = trace.data.read::<f64>("mu");
+        sum += mu;
+        sum_sq += mu * mu;
+    }
+    let mean = sum / NUM_ITERS as f64;
+    approx::assert_abs_diff_eq!(mean, posterior_mean, epsilon = 0.03);
+    approx::assert_abs_diff_eq!(sum_sq / NUM_ITERS as f64 - mean * mean, posterior_var, epsilon = 0.01);
+}
+
+#[test]
+fn test_elliptical_slice_prior_mismatch() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let ys = [2.1, 1.4];
+    let (trace, _) = normal_mean_model.generate(&mut rng, ys.len(), observe_ys(&ys));
+    match try_elliptical_slice_sampling(&mut rng, &normal_mean_model, trace.clone(), "mu", &normal, &(0., 1.)) {
+        Err(Error::InvalidParameters(_)) => { },
+        _ => panic!("expected an error for prior parameters that don't match the model")
+    }
+    assert!(matches!(try_elliptical_slice_sampling(&mut rng, &normal_mean_model, trace, "nu", &normal, &(1., 2.)), Err(Error::MissingAddress(_))));
+}
+
+#[test]
+#[should_panic(expected = "but the trace recorded")]
+fn test_elliptical_slice_prior_mismatch_panics() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let ys = [2.1, 1.4];
+    let (trace, _) = normal_mean_model.generate(&mut rng, ys.len(), observe_ys(&ys));
+    elliptical_slice_sampling(&mut rng, &normal_mean_model, trace, "mu", &normal, &(0., 1.));
+}
+
+#[test]
+fn test_slice_sampling_normal() {
+    const NUM_ITERS: usize = 4000;
+    const BURN_IN: usize = 100;
+    let ys = [2.1, 1.4, 2.6, 1.9];
+
+    let (prior_mean, prior_var, noise_var) = (1., 4., 0.25);
+    let posterior_var = 1. / (1. / prior_var + ys.len() as f64 / noise_var);
+    let posterior_mean = posterior_var * (prior_mean / prior_var + ys.iter().sum::<f64>() / noise_var);
+
+    let mut rng = StdRng::seed_from_u64(2);
+    let (mut trace, _) = normal_mean_model.generate(&mut rng, ys.len(), observe_ys(&ys));
+    let (mut sum, mut sum_sq) = (0., 0.);
+    for _ in 0..BURN_IN {
+        (trace, _) = slice_sampling(&mut rng, &normal_mean_model, trace, "mu", 1., 10);
+    }
+    for _ in 0..NUM_ITERS {
+        let accepted;
+        (trace, accepted) = slice_sampling(&mut rng, &normal_mean_model, trace, "mu", 1., 10);
+        assert!(accepted);
+        let mu = trace.data.read::<f64>("mu");
+        sum += mu;
+        sum_sq += mu * mu;
+    }
+    let mean = sum / NUM_ITERS as f64;
+    approx::assert_abs_diff_eq!(mean, posterior_mean, epsilon = 0.03);
+    approx::assert_abs_diff_eq!(sum_sq / NUM_ITERS as f64 - mean * mean, posterior_var, epsilon = 0.01);
+}
+
+#[test]
+fn test_slice_sampling_bounded() {
+    const NUM_ITERS: usize = 5000;
+    let ys = [0.3, -1.2, 2.5, -0.4, 0.9, -2.1];
+
+    // posterior mean of the scale on a grid
+    let (mut total, mut weighted) = (0., 0.);
+    for i in 1..10000 {
+        let scale = 0.001 * i as f64;
+        let density = (gamma.logpdf(&scale, (2., 1.)) + ys.iter().map(|y| normal.logpdf(y, (0., scale))).sum::<f64>()).exp();
+        total += density;
+        weighted += scale * density;
+    }
+    let expected = weighted / total;
+
+    // a wide interval often steps out past zero, where the model is undefined
+    let mut rng = StdRng::seed_from_u64(3);
+    let (mut trace, _) = scale_model.generate(&mut rng, ys.len(), observe_ys(&ys));
+    let mut mean = 0.;
+    for _ in 0..NUM_ITERS {
+        (trace, _) = slice_sample(&mut rng, &scale_model, trace, "scale", 5., 4);
+        let scale = trace.data.read::<f64>("scale");
+        assert!(scale > 0.);
+        mean += scale / NUM_ITERS as f64;
+    }
+    approx::assert_abs_diff_eq!(mean, expected, epsilon = 0.05);
+}
@@ -3,15 +3,15 @@ use std::{
     sync::Arc,
     f64::consts::PI
 };
-use modppl::{Distribution,DynTrie,u01,normal,inference::ParticleSystem};
-use nalgebra::dvector;
-use rand::rngs::ThreadRng;
+use modppl::{Distribution,DynTrie,GenFn,ArgDiff,AddrMap,Error,u01,normal,regen_mh,inference::ParticleSystem};
+use nalgebra::{dvector, DMatrix, DVector};
+use rand::{SeedableRng, rngs::{ThreadRng, StdRng}};
 
 pub mod pointed_model;
 use pointed_model::types_2d::{Bounds,Point};
 
 pub mod dyngenfns;
-use dyngenfns::spiral_model;
+use dyngenfns::{spiral_model, random_walk_model, random_walk_proposal, random_walk_observations, random_walk_log_ml, lagged_walk_model};
 
 
 fn simulate_loop(rng: &mut ThreadRng, bounds: &Bounds, timesteps: i64) -> Vec<DynTrie>{
@@ -89,4 +89,209 @@ fn test_smc() -> std::io::Result<()> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_dynunfold_revisit() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let ys = [0.3, 1.1, 0.8];
+    let (trace, _) = random_walk_model.generate(&mut rng, (3, 0.), random_walk_observations(&ys));
+
+    // regenerating nothing leaves the trace unchanged
+    let mut mask = AddrMap::new();
+    mask.visit("7");
+    let (same, weight) = random_walk_model.regenerate(&mut rng, trace.clone(), (3, 0.), ArgDiff::NoChange, &mask);
+    assert_eq!(weight, 0.);
+    assert_eq!(same.retv, trace.retv);
+
+    // regenerating "1 / x" rescores the choices of step 2, whose input state changed
+    let mut mask = AddrMap::new();
+    mask.visit("1 / x");
+    let (new_trace, weight) = random_walk_model.regenerate(&mut rng, trace.clone(), (3, 0.), ArgDiff::NoChange, &mask);
+    let xs = new_trace.retv.clone().unwrap();
+    assert_eq!(xs[0], trace.retv.as_ref().unwrap()[0]);
+    assert_ne!(xs[1], trace.retv.as_ref().unwrap()[1]);
+    assert_eq!(xs[2], trace.retv.as_ref().unwrap()[2]);
+    let mut expected_logjp = 0.;
+    for t in 0..3 {
+        let prev_x = if t == 0 { 0. } else { xs[t-1] };
+        expected_logjp += normal.logpdf(&xs[t], (prev_x, 1.)) + normal.logpdf(&ys[t], (xs[t], 0.5));
+    }
+    approx::assert_abs_diff_eq!(new_trace.logjp, expected_logjp, epsilon = 1e-12);
+    let proposal_ratio = normal.logpdf(&xs[1], (xs[0], 1.)) - normal.logpdf(&trace.retv.as_ref().unwrap()[1], (xs[0], 1.));
+    approx::assert_abs_diff_eq!(weight, new_trace.logjp - trace.logjp - proposal_ratio, epsilon = 1e-12);
+
+    // constraining step 0 through a NoChange update returns the discarded choice
+    let mut constraints = vec![DynTrie::new(), DynTrie::new(), DynTrie::new()];
+    constraints[0].observe("x", Arc::new(0.5));
+    let (updated, discard, weight) = random_walk_model.update(&mut rng, trace.clone(), (3, 0.), ArgDiff::NoChange, constraints);
+    assert_eq!(updated.retv.as_ref().unwrap()[0], 0.5);
+    assert_eq!(discard[0].read::<f64>("x"), trace.retv.as_ref().unwrap()[0]);
+    assert!(discard[1].is_empty() && discard[2].is_empty());
+    approx::assert_abs_diff_eq!(weight, updated.logjp - trace.logjp, epsilon = 1e-12);
+}
+
+#[test]
+fn test_dynunfold_invalid_arguments() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let ys = [0.3, 1.1, 0.8];
+    assert!(matches!(random_walk_model.try_simulate(&mut rng, (0, 0.)), Err(Error::InvalidArguments(_))));
+    assert!(matches!(random_walk_model.try_generate(&mut rng, (2, 0.), random_walk_observations(&ys)), Err(Error::InvalidArguments(_))));
+
+    let (trace, _) = random_walk_model.generate(&mut rng, (3, 0.), random_walk_observations(&ys));
+    let result = random_walk_model.try_update(&mut rng, trace.clone(), (3, 0.), ArgDiff::NoChange, vec![DynTrie::new()]);
+    assert!(matches!(result, Err(Error::InvalidArguments(_))));
+    let result = random_walk_model.try_update(&mut rng, trace.clone(), (4, 0.), ArgDiff::NoChange, random_walk_observations(&ys));
+    assert!(matches!(result, Err(Error::InvalidArguments(_))));
+    let result = random_walk_model.try_update(&mut rng, trace.clone(), (5, 0.), ArgDiff::Extend, vec![DynTrie::new()]);
+    assert!(matches!(result, Err(Error::InvalidArguments(_))));
+    let result = random_walk_model.try_regenerate(&mut rng, trace, (4, 0.), ArgDiff::NoChange, &AddrMap::new());
+    assert!(matches!(result, Err(Error::InvalidArguments(_))));
+}
+
+#[test]
+fn test_smc_rejuvenate() {
+    const NUM_PARTICLES: usize = 5000;
+    let ys = [0.3, 1.1, 0.8, 2.0, 1.4, 0.9];
+    let expected = random_walk_log_ml(&ys);
+
+    let mut filter = ParticleSystem::new(random_walk_model, NUM_PARTICLES, StdRng::seed_from_u64(1));
+    let mut data_it = random_walk_observations(&ys).into_iter();
+    filter.init_step(0., vec![data_it.next().unwrap()]);
+    for constraints in data_it {
+        filter.resample();
+        let t = filter.traces[0].args.0;
+        let mut mask = AddrMap::new();
+        mask.visit(&format!("{} / x", t - 1));
+        let acceptance_rate = filter.rejuvenate(|rng, model, trace| regen_mh(rng, model, trace, &mask));
+        assert!(acceptance_rate > 0.);
+        filter = filter.step(vec![constraints]);
+    }
+
+    approx::assert_abs_diff_eq!(filter.log_marginal_likelihood_estimate(), expected, epsilon = 0.1);
+    for trace in filter.traces.iter() {
+        let recomputed = trace.data.iter().map(|step| step.weight()).sum::<f64>();
+        approx::assert_abs_diff_eq!(trace.logjp, recomputed, epsilon = 1e-9);
+    }
+}
+
+#[test]
+fn test_smc_step_with_proposal() {
+    const NUM_PARTICLES: usize = 1000;
+    let ys = [0.3, 1.1, 0.8, 2.0, 1.4, 0.9];
+    let expected = random_walk_log_ml(&ys);
+
+    let mut filter = ParticleSystem::new(random_walk_model, NUM_PARTICLES, StdRng::seed_from_u64(2))
+        .with_ess_threshold(0.5);
+    let mut data_it = random_walk_observations(&ys).into_iter().zip(ys);
+    filter.init_step(0., vec![data_it.next().unwrap().0]);
+    for (constraints, y) in data_it {
+        let prev_xs = filter.traces.iter().map(|tr| *tr.retv.as_ref().unwrap().last().unwrap()).collect::<Vec<f64>>();
+        let prev_log_ml = filter.log_marginal_likelihood_estimate();
+        filter = filter.step_with_proposal(vec![constraints], &random_walk_proposal, y);
+
+        // under the locally optimal proposal, every particle is weighted by the predictive likelihood p(y | prev_x)
+        let predictive = prev_xs.iter().map(|x| normal.logpdf(&y, (*x, 1.25_f64.sqrt()))).collect::<Vec<f64>>();
+        let incremental = filter.log_marginal_likelihood_estimate() - prev_log_ml;
+        assert!(predictive.iter().cloned().fold(f64::INFINITY, f64::min) <= incremental + 1e-9);
+        assert!(predictive.iter().cloned().fold(f64::NEG_INFINITY, f64::max) >= incremental - 1e-9);
+    }
+
+    approx::assert_abs_diff_eq!(filter.log_marginal_likelihood_estimate(), expected, epsilon = 0.05);
+}
+
+/// Exact posterior means of the states of `random_walk_model` given `ys` by Kalman (RTS) smoothing.
+fn random_walk_posterior_means(ys: &[f64]) -> Vec<f64> {
+    let (mut pred_mean, mut pred_var) = (0., 1.);
+    let mut filtered = vec![];
+    for y in ys {
+        let gain = pred_var / (pred_var + 0.25);
+        let mean = pred_mean + gain * (y - pred_mean);
+        let var = (1. - gain) * pred_var;
+        filtered.push((mean, var));
+        (pred_mean, pred_var) = (mean, var + 1.);
+    }
+    let mut smoothed = vec![filtered.last().unwrap().0];
+    for (mean, var) in filtered.iter().rev().skip(1) {
+        let next = *smoothed.last().unwrap();
+        smoothed.push(mean + var / (var + 1.) * (next - mean));
+    }
+    smoothed.reverse();
+    smoothed
+}
+
+#[test]
+fn test_particle_gibbs() {
+    const NUM_PARTICLES: usize = 20;
+    const NUM_ITERS: usize = 600;
+    const BURN_IN: usize = 100;
+    let ys = [0.3, 1.1, 0.8, 2.0, 1.4, 0.9];
+    let expected = random_walk_posterior_means(&ys);
+
+    for ancestor_sampling in [false, true] {
+        let mut rng = StdRng::seed_from_u64(3);
+        let (mut trace, _) = random_walk_model.generate(&mut rng, (ys.len() as i64, 0.), random_walk_observations(&ys));
+        let mut filter = ParticleSystem::new(random_walk_model, NUM_PARTICLES, rng);
+        let mut means = vec![0.; ys.len()];
+        for iter in 0..NUM_ITERS {
+            trace = filter.conditional_smc(&trace, random_walk_observations(&ys), ancestor_sampling);
+            assert_eq!(trace.data.len(), ys.len());
+            if iter >= BURN_IN {
+                for (mean, x) in means.iter_mut().zip(trace.retv.as_ref().unwrap()) {
+                    *mean += x / (NUM_ITERS - BURN_IN) as f64;
+                }
+            }
+        }
+        for (mean, expected) in means.iter().zip(&expected) {
+            approx::assert_abs_diff_eq!(mean, expected, epsilon = 0.15);
+        }
+    }
+}
+
+/// Exact posterior means of the states of `lagged_walk_model` given `ys`, from the joint Gaussian of the states and observations.
+fn lagged_walk_posterior_means(ys: &[f64]) -> Vec<f64> {
+    let n = ys.len();
+    // the states are a random walk, and each observation is `x[t] + 2 x[t-1]` plus noise
+    let cov_x = DMatrix::from_fn(n, n, |i, j| (i.min(j) + 1) as f64);
+    let a = DMatrix::from_fn(n, n, |i, j| if i == j { 1. } else if i == j + 1 { 2. } else { 0. });
+    let cov_y = &a * &cov_x * a.transpose() + DMatrix::identity(n, n) * 0.09;
+    let means = &cov_x * a.transpose() * cov_y.try_inverse().unwrap() * DVector::from_column_slice(ys);
+    means.as_slice().to_vec()
+}
+
+#[test]
+fn test_particle_gibbs_ancestor_sampling() {
+    const NUM_PARTICLES: usize = 20;
+    const NUM_ITERS: usize = 600;
+    const BURN_IN: usize = 100;
+    let ys = [0.3, 1.1, 0.8, 2.0, 1.4, 0.9];
+    let expected = lagged_walk_posterior_means(&ys);
+
+    let mut all_means = vec![];
+    for ancestor_sampling in [false, true] {
+        let mut rng = StdRng::seed_from_u64(4);
+        let (mut trace, _) = lagged_walk_model.generate(&mut rng, (ys.len() as i64, 0.), random_walk_observations(&ys));
+        let mut filter = ParticleSystem::new(lagged_walk_model, NUM_PARTICLES, rng);
+        let mut means = vec![0.; ys.len()];
+        for iter in 0..NUM_ITERS {
+            trace = filter.conditional_smc(&trace, random_walk_observations(&ys), ancestor_sampling);
+            // the reference (the first particle) is weighted by its last observation given its (resampled) ancestor
+            let xs = filter.traces[0].retv.as_ref().unwrap();
+            let last = ys.len() - 1;
+            let log_likelihood = normal.logpdf(&ys[last], (xs[last] + 2.*xs[last - 1], 0.3));
+            approx::assert_abs_diff_eq!(filter.log_weights()[0], log_likelihood, epsilon = 1e-12);
+            if iter >= BURN_IN {
+                for (mean, x) in means.iter_mut().zip(trace.retv.as_ref().unwrap()) {
+                    *mean += x / (NUM_ITERS - BURN_IN) as f64;
+                }
+            }
+        }
+        for (mean, expected) in means.iter().zip(&expected) {
+            approx::assert_abs_diff_eq!(mean, expected, epsilon = 0.15);
+        }
+        all_means.push(means);
+    }
+    for (without, with) in all_means[0].iter().zip(&all_means[1]) {
+        approx::assert_abs_diff_eq!(without, with, epsilon = 0.2);
+    }
+}
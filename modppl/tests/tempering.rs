@@ -0,0 +1,83 @@
+#![allow(non_upper_case_globals)]
+
+use nalgebra::{DMatrix, DVector};
+use modppl::prelude::*;
+
+mod pointed_model;
+mod dyngenfns;
+use dyngenfns::{normal_mean_model, observe_ys};
+
+
+fn normal_mean_observations() -> DynTrie {
+    observe_ys(&[1.2, 0.7, 1.9, 1.4, 0.8, 1.6])
+}
+
+// exact evidence of the conjugate model: ys ~ N(1, 0.25 I + 4 1^T)
+fn normal_mean_log_ml(observations: &DynTrie, n: usize) -> f64 {
+    let ys = DVector::from_iterator(n, (0..n).map(|i| observations.read::<f64>(&format!("ys / {}", i))));
+    let cov = DMatrix::identity(n, n) * 0.25 + DMatrix::from_element(n, n, 4.);
+    mvnormal.logpdf(&ys, (DVector::from_element(n, 1.), cov))
+}
+
+fn latents() -> AddrMap {
+    let mut selection = AddrMap::new();
+    selection.visit("mu");
+    selection
+}
+
+#[test]
+fn test_tempered_smc() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let observations = normal_mean_observations();
+    let expected = normal_mean_log_ml(&observations, 6);
+
+    let (traces, betas, log_ml_estimate) =
+        tempered_smc(&mut rng, &normal_mean_model, 6, observations, 1000, 0.5, &latents(), 5).unwrap();
+
+    assert_eq!(traces.len(), 1000);
+    assert!(betas.len() > 1);
+    assert!(betas.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(*betas.last().unwrap(), 1.);
+    approx::assert_abs_diff_eq!(log_ml_estimate, expected, epsilon = 0.2);
+
+    // posterior mean is (1 / 4 + sum(ys) / 0.25) / (1 / 4 + n / 0.25)
+    let mean = traces.iter().map(|tr| tr.data.read::<f64>("mu")).sum::<f64>() / traces.len() as f64;
+    approx::assert_abs_diff_eq!(mean, 30.65 / 24.25, epsilon = 0.05);
+}
+
+dyngen!(
+fn threshold_model() {
+    let x = uniform(0., 1.) %= "x";
+    uniform(0., x) %= "y";
+});
+
+#[test]
+fn test_tempered_smc_degenerate() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut observations = DynTrie::new();
+    observations.observe("y", Arc::new(0.95));
+    let mut selection = AddrMap::new();
+    selection.visit("x");
+
+    // only the ~5% of particles with x > 0.95 have positive likelihood, whatever the increment of beta
+    match tempered_smc(&mut rng, &threshold_model, (), observations, 200, 0.5, &selection, 1) {
+        Err(Error::DegenerateWeights(_)) => { },
+        Err(err) => panic!("unexpected error: {err}"),
+        Ok(_) => panic!("expected degenerate weights")
+    }
+}
+
+#[test]
+fn test_annealed_importance_sampling() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let observations = normal_mean_observations();
+    let expected = normal_mean_log_ml(&observations, 6);
+
+    let betas = (1..=50).map(|k| (k as f64 / 50.).powi(3)).collect::<Vec<f64>>();
+    let (traces, log_normalized_weights, log_ml_estimate) =
+        annealed_importance_sampling(&mut rng, &normal_mean_model, 6, observations, 500, &betas, &latents(), 2);
+
+    assert_eq!(traces.len(), 500);
+    approx::assert_abs_diff_eq!(modppl::logsumexp(&log_normalized_weights), 0., epsilon = 1e-8);
+    approx::assert_abs_diff_eq!(log_ml_estimate, expected, epsilon = 0.2);
+}
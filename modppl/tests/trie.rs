@@ -1,4 +1,4 @@
-use modppl::Trie;
+use modppl::{Trie,Error};
 
 
 // inserting a trie into a root and then removing it should yield the previous tries
@@ -140,3 +140,26 @@ pub fn test_trie_extended_example() {
     let v = l.clone().take_inner().unwrap();
     assert_eq!(v, 1.0);
 }
+
+// try_* operations on an occupied address should return an error rather than panic
+#[test]
+pub fn test_try_insert_into_occupied() {
+    let mut root = Trie::<i32>::new();
+    root.w_observe("some/address", 1, -0.5);
+    assert_eq!(root.try_w_observe("some / address", 2, 0.), Err(Error::AddressCollision(String::from("some / address"))));
+    assert_eq!(root.try_observe("some/address", 2), Err(Error::AddressCollision(String::from("some / address"))));
+    assert_eq!(root.try_insert("some", Trie::new()), Err(Error::AddressCollision(String::from("some"))));
+    assert_eq!(root.weight(), -0.5);
+    assert!(root.try_insert("other", Trie::leaf(3, -1.)).is_ok());
+    assert_eq!(root.weight(), -1.5);
+}
+
+// searching below a missing prefix should return None, and try_search the missing address
+#[test]
+pub fn test_search_missing_prefix() {
+    let mut root = Trie::<i32>::new();
+    root.w_observe("a/b", 1, 0.);
+    assert!(root.search("c/d").is_none());
+    assert_eq!(root.try_search("c/d"), Err(Error::MissingAddress(String::from("c / d"))));
+    assert!(root.try_search("a/b").is_ok());
+}